@@ -0,0 +1,584 @@
+use anyhow::{Result, bail};
+use bitflags::bitflags;
+
+use crate::cpu::Cpu;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct BreakKind: u8 {
+        const EXECUTE = 1 << 0;
+        const READ = 1 << 1;
+        const WRITE = 1 << 2;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddrSpace {
+    #[default]
+    Cpu,
+    Ppu,
+}
+
+impl AddrSpace {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AddrSpace::Cpu => "CPU",
+            AddrSpace::Ppu => "PPU",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub id: u32,
+    pub enabled: bool,
+    pub kind: BreakKind,
+    pub space: AddrSpace,
+    pub start: u16,
+    pub end: u16,
+    pub condition: Option<Condition>,
+    /// Break only once the breakpoint has matched this many times (0 breaks on every match)
+    pub hit_target: u32,
+    pub hits: u32,
+}
+
+impl Breakpoint {
+    fn matches(&self, space: AddrSpace, kind: BreakKind, addr: u16) -> bool {
+        self.enabled
+            && self.space == space
+            && self.kind.contains(kind)
+            && (self.start..=self.end).contains(&addr)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BreakHit {
+    pub id: u32,
+    pub kind: BreakKind,
+    pub space: AddrSpace,
+    pub addr: u16,
+    pub value: u8,
+    pub pc: u16,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EvalContext {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub p: u8,
+    pub pc: u16,
+    pub addr: u16,
+    pub value: u8,
+    pub scanline: u16,
+    pub dot: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    A,
+    X,
+    Y,
+    Sp,
+    P,
+    Pc,
+    Addr,
+    Value,
+    Scanline,
+    Dot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(i64),
+    Operand(Operand),
+    Not(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, ctx: &EvalContext) -> i64 {
+        match self {
+            Expr::Num(n) => *n,
+            Expr::Operand(op) => match op {
+                Operand::A => ctx.a as i64,
+                Operand::X => ctx.x as i64,
+                Operand::Y => ctx.y as i64,
+                Operand::Sp => ctx.sp as i64,
+                Operand::P => ctx.p as i64,
+                Operand::Pc => ctx.pc as i64,
+                Operand::Addr => ctx.addr as i64,
+                Operand::Value => ctx.value as i64,
+                Operand::Scanline => ctx.scanline as i64,
+                Operand::Dot => ctx.dot as i64,
+            },
+            Expr::Not(e) => (e.eval(ctx) == 0) as i64,
+            Expr::Binary(op, lhs, rhs) => {
+                let l = lhs.eval(ctx);
+                match op {
+                    BinOp::Or => (l != 0 || rhs.eval(ctx) != 0) as i64,
+                    BinOp::And => (l != 0 && rhs.eval(ctx) != 0) as i64,
+                    _ => {
+                        let r = rhs.eval(ctx);
+                        match op {
+                            BinOp::Eq => (l == r) as i64,
+                            BinOp::Ne => (l != r) as i64,
+                            BinOp::Lt => (l < r) as i64,
+                            BinOp::Le => (l <= r) as i64,
+                            BinOp::Gt => (l > r) as i64,
+                            BinOp::Ge => (l >= r) as i64,
+                            BinOp::BitOr => l | r,
+                            BinOp::BitXor => l ^ r,
+                            BinOp::BitAnd => l & r,
+                            BinOp::Add => l.wrapping_add(r),
+                            BinOp::Sub => l.wrapping_sub(r),
+                            BinOp::Or | BinOp::And => unreachable!(),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(i64),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>> {
+    const OPS: [&str; 15] = [
+        "||", "&&", "==", "!=", "<=", ">=", "<", ">", "|", "^", "&", "+", "-", "!", "=",
+    ];
+
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else if c == '$' || c.is_ascii_digit() {
+            let (radix, start) = if c == '$' {
+                (16, i + 1)
+            } else if c == '0' && matches!(chars.get(i + 1), Some('x') | Some('X')) {
+                (16, i + 2)
+            } else {
+                (10, i)
+            };
+            let mut end = start;
+            while end < chars.len() && chars[end].is_ascii_alphanumeric() {
+                end += 1;
+            }
+            let digits: String = chars[start..end].iter().collect();
+            match i64::from_str_radix(&digits, radix) {
+                Ok(n) => tokens.push(Token::Num(n)),
+                Err(_) => bail!(
+                    "Invalid number \"{}\"",
+                    chars[i..end].iter().collect::<String>()
+                ),
+            }
+            i = end;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+            match OPS.iter().find(|op| rest.starts_with(**op)) {
+                Some(&"=") => bail!("Unexpected '=', did you mean '=='?"),
+                Some(op) => {
+                    tokens.push(Token::Op(op));
+                    i += op.len();
+                }
+                None => bail!("Unexpected character '{}'", c),
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    const LEVELS: [&[(&'static str, BinOp)]; 7] = [
+        &[("||", BinOp::Or)],
+        &[("&&", BinOp::And)],
+        &[
+            ("==", BinOp::Eq),
+            ("!=", BinOp::Ne),
+            ("<=", BinOp::Le),
+            (">=", BinOp::Ge),
+            ("<", BinOp::Lt),
+            (">", BinOp::Gt),
+        ],
+        &[("|", BinOp::BitOr)],
+        &[("^", BinOp::BitXor)],
+        &[("&", BinOp::BitAnd)],
+        &[("+", BinOp::Add), ("-", BinOp::Sub)],
+    ];
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        if level == Self::LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let Some(&(_, bin_op)) = Self::LEVELS[level].iter().find(|(s, _)| s == op) else {
+                break;
+            };
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(bin_op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Op("!")) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Op("-")) => Ok(Expr::Binary(
+                BinOp::Sub,
+                Box::new(Expr::Num(0)),
+                Box::new(self.unary()?),
+            )),
+            Some(Token::Num(n)) => Ok(Expr::Num(n)),
+            Some(Token::Ident(name)) => {
+                let operand = match name.to_ascii_lowercase().as_str() {
+                    "a" => Operand::A,
+                    "x" => Operand::X,
+                    "y" => Operand::Y,
+                    "sp" | "s" => Operand::Sp,
+                    "p" => Operand::P,
+                    "pc" => Operand::Pc,
+                    "addr" => Operand::Addr,
+                    "value" => Operand::Value,
+                    "scanline" => Operand::Scanline,
+                    "dot" | "cycle" => Operand::Dot,
+                    _ => bail!("Unknown identifier \"{}\"", name),
+                };
+                Ok(Expr::Operand(operand))
+            }
+            Some(Token::LParen) => {
+                let expr = self.binary(0)?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => bail!("Missing ')'"),
+                }
+            }
+            Some(token) => bail!("Unexpected {:?}", token),
+            None => bail!("Unexpected end of expression"),
+        }
+    }
+}
+
+/// Boolean expression over CPU registers and the accessed address/value,
+/// e.g. `A == $FF && X > 3`
+#[derive(Debug, Clone)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+        };
+        let expr = parser.binary(0)?;
+        if let Some(token) = parser.peek() {
+            bail!("Unexpected {:?}", token);
+        }
+
+        Ok(Self {
+            source: source.trim().to_string(),
+            expr,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn eval(&self, ctx: &EvalContext) -> bool {
+        self.expr.eval(ctx) != 0
+    }
+}
+
+/// Breakpoint set owned by the bus. The per-space masks let the hot paths skip
+/// all work with a single test when nothing is being watched.
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints {
+    list: Vec<Breakpoint>,
+    cpu_mask: BreakKind,
+    ppu_mask: BreakKind,
+    ctx: EvalContext,
+    hit: Option<BreakHit>,
+    resume_pc: Option<u16>,
+}
+
+impl Breakpoints {
+    pub fn set(&mut self, mut list: Vec<Breakpoint>) {
+        for bp in list.iter_mut() {
+            if let Some(old) = self.list.iter().find(|old| old.id == bp.id) {
+                bp.hits = old.hits;
+            }
+        }
+
+        self.cpu_mask = BreakKind::empty();
+        self.ppu_mask = BreakKind::empty();
+        for bp in list.iter().filter(|bp| bp.enabled) {
+            match bp.space {
+                AddrSpace::Cpu => self.cpu_mask |= bp.kind,
+                AddrSpace::Ppu => self.ppu_mask |= bp.kind & !BreakKind::EXECUTE,
+            }
+        }
+        self.list = list;
+    }
+
+    pub fn list(&self) -> &[Breakpoint] {
+        &self.list
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.cpu_mask.is_empty() && self.ppu_mask.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn watches(&self, space: AddrSpace, kind: BreakKind) -> bool {
+        match space {
+            AddrSpace::Cpu => self.cpu_mask.intersects(kind),
+            AddrSpace::Ppu => self.ppu_mask.intersects(kind),
+        }
+    }
+
+    pub fn update_registers(&mut self, cpu: &Cpu) {
+        self.ctx.a = cpu.a;
+        self.ctx.x = cpu.x;
+        self.ctx.y = cpu.y;
+        self.ctx.sp = cpu.sp;
+        self.ctx.p = cpu.p.bits();
+        self.ctx.pc = cpu.pc;
+    }
+
+    /// Checks execute breakpoints at `cpu.pc`. Returns `true` if execution must
+    /// stop before the instruction runs. The instruction a break stopped on is
+    /// let through once so resuming makes progress.
+    pub fn check_execute(&mut self, cpu: &Cpu, scanline: u16, dot: u16) -> bool {
+        self.update_registers(cpu);
+        if self.resume_pc.take() == Some(cpu.pc) {
+            return false;
+        }
+        if self.cpu_mask.contains(BreakKind::EXECUTE)
+            && self.check(AddrSpace::Cpu, BreakKind::EXECUTE, cpu.pc, 0, scanline, dot)
+        {
+            self.resume_pc = Some(cpu.pc);
+            return true;
+        }
+        false
+    }
+
+    /// Lets the instruction at `pc` run once without re-triggering an execute
+    /// breakpoint, used whenever emulation stops on it.
+    pub fn resume_at(&mut self, pc: u16) {
+        self.resume_pc = Some(pc);
+    }
+
+    pub fn check(
+        &mut self,
+        space: AddrSpace,
+        kind: BreakKind,
+        addr: u16,
+        value: u8,
+        scanline: u16,
+        dot: u16,
+    ) -> bool {
+        self.ctx.addr = addr;
+        self.ctx.value = value;
+        self.ctx.scanline = scanline;
+        self.ctx.dot = dot;
+
+        let mut triggered = None;
+        for bp in self.list.iter_mut() {
+            if !bp.matches(space, kind, addr) {
+                continue;
+            }
+            if bp
+                .condition
+                .as_ref()
+                .is_some_and(|cond| !cond.eval(&self.ctx))
+            {
+                continue;
+            }
+            bp.hits = bp.hits.saturating_add(1);
+            if triggered.is_none() && bp.hits >= bp.hit_target {
+                triggered = Some(bp.id);
+            }
+        }
+
+        match triggered {
+            Some(id) => {
+                if self.hit.is_none() {
+                    self.hit = Some(BreakHit {
+                        id,
+                        kind,
+                        space,
+                        addr,
+                        value,
+                        pc: self.ctx.pc,
+                    });
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn take_hit(&mut self) -> Option<BreakHit> {
+        self.hit.take()
+    }
+
+    pub fn hit_counts(&self) -> Vec<(u32, u32)> {
+        self.list.iter().map(|bp| (bp.id, bp.hits)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> EvalContext {
+        EvalContext {
+            a: 0xFF,
+            x: 3,
+            y: 0x10,
+            sp: 0xFD,
+            p: 0x24,
+            pc: 0xC000,
+            addr: 0x2002,
+            value: 0x80,
+            scanline: 241,
+            dot: 1,
+        }
+    }
+
+    fn eval(source: &str) -> bool {
+        Condition::parse(source).unwrap().eval(&ctx())
+    }
+
+    #[test]
+    fn numbers() {
+        assert!(eval("a == $FF"));
+        assert!(eval("A == 0xff"));
+        assert!(eval("a == 255"));
+        assert!(eval("pc == $C000"));
+    }
+
+    #[test]
+    fn operands() {
+        assert!(eval(
+            "x == 3 && y == 16 && sp == $FD && s == $FD && p == $24"
+        ));
+        assert!(eval("addr == $2002 && value == $80"));
+        assert!(eval("scanline == 241 && dot == 1 && cycle == 1"));
+    }
+
+    #[test]
+    fn precedence() {
+        assert!(eval("x == 1 || x == 3 && y == 16"));
+        assert!(!eval("(x == 1 || x == 3) && y == 0"));
+        assert!(eval("value & $80 == $80"));
+        assert!(eval("x + 1 == 4"));
+        assert!(eval("a ^ $0F | 1 == $F1"));
+        assert!(eval("-x + 3 == 0"));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(eval("x < 4 && x <= 3 && x > 2 && x >= 3 && x != 2"));
+        assert!(!eval("x > 3"));
+    }
+
+    #[test]
+    fn not() {
+        assert!(eval("!(x == 2)"));
+        assert!(!eval("!x"));
+        assert!(eval("!!x"));
+    }
+
+    #[test]
+    fn errors() {
+        for source in [
+            "", "a ==", "(a == 1", "a == 1)", "foo == 1", "a = 1", "a # 1", "$G0",
+        ] {
+            assert!(Condition::parse(source).is_err(), "{source}");
+        }
+    }
+
+    #[test]
+    fn multibyte_before_bad_number() {
+        let error = Condition::parse("\u{a0}é == 1 && a == $1Z").unwrap_err();
+        assert!(
+            error.to_string().contains("Unexpected character"),
+            "{error}"
+        );
+        // Char and byte offsets part ways after multi-byte spaces
+        for source in ["a\u{a0}\u{a0}== $1Z", "\u{3000}$1Z"] {
+            let error = Condition::parse(source).unwrap_err();
+            assert_eq!(error.to_string(), "Invalid number \"$1Z\"");
+        }
+    }
+
+    #[test]
+    fn source_is_trimmed() {
+        assert_eq!(Condition::parse("  a == 1 ").unwrap().source(), "a == 1");
+    }
+}
@@ -1,7 +1,20 @@
+use std::sync::Arc;
+
 use log::warn;
 use savefile::prelude::*;
 
-use crate::{apu::Apu, cart::Cart, ppu::Ppu};
+use crate::{
+    apu::Apu,
+    breakpoint::{AddrSpace, BreakKind, Breakpoints},
+    cart::Cart,
+    cdl::{Access, ChrFlags, CodeDataLog},
+    cheats::ActiveCheats,
+    debug::MemorySpace,
+    mapper::MapperMemory,
+    ppu::Ppu,
+    ppu_events::{PpuEventKind, PpuEventLog},
+    symbols::{Symbol, SymbolTable},
+};
 
 #[derive(Default, Clone, Savefile)]
 pub struct Controller {
@@ -23,6 +36,21 @@ pub struct Bus {
     pub controller2: Controller,
     pub open_bus: u8,
     pub dma_stall: u32,
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    pub breakpoints: Breakpoints,
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    pub symbols: Arc<SymbolTable>,
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    pub cdl: CodeDataLog,
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    pub cheats: ActiveCheats,
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    pub ppu_events: PpuEventLog,
 }
 
 impl Default for Bus {
@@ -36,6 +64,11 @@ impl Default for Bus {
             controller2: Default::default(),
             open_bus: 0,
             dma_stall: 0,
+            breakpoints: Default::default(),
+            symbols: Default::default(),
+            cdl: Default::default(),
+            cheats: Default::default(),
+            ppu_events: Default::default(),
         }
     }
 }
@@ -48,12 +81,21 @@ impl Bus {
     pub fn tick_apu(&mut self) {
         self.apu.step();
         if let Some(addr) = self.apu.poll_dmc_dma() {
+            let access = std::mem::replace(&mut self.cdl.access, Access::Pcm);
             let val = self.read_byte(addr);
+            self.cdl.access = access;
             self.apu.fill_dmc_buffer(val);
         }
     }
 
     pub fn insert_cartridge(&mut self, cart: Cart) {
+        let enabled = self.cdl.enabled;
+        self.cdl = CodeDataLog::new(
+            16 * 1024 * cart.header.prg_rom_size as usize,
+            8 * 1024 * cart.header.chr_rom_size as usize,
+        );
+        self.cdl.enabled = enabled;
+        self.ppu_events.reset();
         self.cart = Some(cart);
     }
 
@@ -66,34 +108,67 @@ impl Bus {
     }
 
     fn read_ppu(&mut self, addr: u16) -> u8 {
-        if self.ppu.frame > self.ppu.open_bus_decay_timer + 60 {
-            self.ppu.open_bus = 0;
-        }
-
         let reg = addr & 0x07;
-        let result = match reg {
+        match reg {
             2 => {
                 let status = self.ppu.read_status();
-                (status & 0xE0) | (self.ppu.open_bus & 0x1F)
+                self.ppu.apply_open_bus(0x1F, status)
             }
-            4 => self.ppu.read_oam_data(),
-            7 => self
-                .cart
-                .as_mut()
-                .map(|c| self.ppu.read_data(&mut c.mapper))
-                .unwrap_or(0),
-            _ => self.ppu.open_bus,
-        };
-
-        self.ppu.open_bus = result;
-        result
+            4 => {
+                let value = self.ppu.read_oam_data();
+                self.ppu.apply_open_bus(0x00, value)
+            }
+            7 => {
+                let vram_addr = self.ppu.v & 0x3FFF;
+                if self.cdl.enabled
+                    && vram_addr < 0x2000
+                    && let Some(cart) = &self.cart
+                {
+                    self.cdl
+                        .log_chr(cart.mapper.chr_offset(vram_addr), ChrFlags::READ);
+                }
+                let value = self
+                    .cart
+                    .as_mut()
+                    .map(|c| self.ppu.read_data(&mut c.mapper))
+                    .unwrap_or(0);
+                let open_mask = if vram_addr >= 0x3F00 { 0xC0 } else { 0x00 };
+                let value = self.ppu.apply_open_bus(open_mask, value);
+                if self.breakpoints.watches(AddrSpace::Ppu, BreakKind::READ) {
+                    self.check_breakpoint(AddrSpace::Ppu, BreakKind::READ, vram_addr, value);
+                }
+                value
+            }
+            _ => self.ppu.apply_open_bus(0xFF, 0),
+        }
     }
 
     fn read_cartridge(&self, addr: u16) -> u8 {
-        self.cart
+        let value = self
+            .cart
             .as_ref()
             .and_then(|c| c.mapper.read_prg(addr))
-            .unwrap_or(self.open_bus)
+            .unwrap_or(self.open_bus);
+        self.cheats.patch(addr, value)
+    }
+
+    /// Rewrites the RAM frozen by cheats, once per frame. Straight into
+    /// memory, so nothing else on the bus sees a write
+    pub fn apply_freezes(&mut self) {
+        for &(addr, value) in self.cheats.freezes() {
+            let byte = match addr {
+                0x0000..=0x07FF => self.mem.get_mut(addr as usize),
+                0x6000..=0x7FFF => self.cart.as_mut().and_then(|cart| {
+                    cart.mapper
+                        .memory_mut(MapperMemory::PrgRam)
+                        .get_mut(addr as usize - 0x6000)
+                }),
+                _ => None,
+            };
+            if let Some(byte) = byte {
+                *byte = value;
+            }
+        }
     }
 
     fn read_controller1(&mut self) -> u8 {
@@ -137,18 +212,50 @@ impl Bus {
         if addr != 0x4015 {
             self.open_bus = value;
         }
+
+        if self.cdl.enabled
+            && let Some(offset) = self
+                .cart
+                .as_ref()
+                .and_then(|c| c.mapper.prg_rom_offset(addr))
+        {
+            self.cdl.log_prg(offset, addr);
+        }
+
+        // Fetching code would otherwise trip read watchpoints on every instruction run
+        if self.breakpoints.watches(AddrSpace::Cpu, BreakKind::READ) && self.cdl.access.is_data() {
+            self.check_breakpoint(AddrSpace::Cpu, BreakKind::READ, addr, value);
+        }
         value
     }
 
+    fn check_breakpoint(&mut self, space: AddrSpace, kind: BreakKind, addr: u16, value: u8) {
+        let (scanline, dot) = (self.ppu.scanline, self.ppu.dot);
+        self.breakpoints
+            .check(space, kind, addr, value, scanline, dot);
+    }
+
+    pub fn log_ppu_event(&mut self, kind: PpuEventKind, addr: u16, value: u8) {
+        if self.ppu_events.enabled {
+            let (scanline, dot) = (self.ppu.scanline, self.ppu.dot);
+            self.ppu_events.push(kind, addr, value, scanline, dot);
+        }
+    }
+
+    /// Label at `addr` as seen through the currently mapped PRG banks
+    pub fn symbol(&self, addr: u16) -> Option<&Symbol> {
+        let prg_offset = self
+            .cart
+            .as_ref()
+            .and_then(|c| c.mapper.prg_rom_offset(addr));
+        self.symbols.lookup(addr, prg_offset)
+    }
+
     pub fn read_only(&self, addr: u16) -> u8 {
         match addr {
             0x0000..=0x1FFF => self.read_mem(addr),
             0x2000..=0x3FFF => 0,
-            0x4020..=0xFFFF => self
-                .cart
-                .as_ref()
-                .and_then(|c| c.mapper.read_prg(addr))
-                .unwrap_or(self.open_bus),
+            0x4020..=0xFFFF => self.read_cartridge(addr),
             _ => 0,
         }
     }
@@ -166,6 +273,81 @@ impl Bus {
         (0..bytes).map(|i| self.read_only(addr + i)).collect()
     }
 
+    /// Raw cartridge memory, empty when no cartridge is inserted
+    pub fn memory(&self, kind: MapperMemory) -> &[u8] {
+        self.cart
+            .as_ref()
+            .map(|c| c.mapper.memory(kind))
+            .unwrap_or(&[])
+    }
+
+    pub fn space_size(&self, space: MemorySpace) -> usize {
+        match space {
+            MemorySpace::CpuBus => 0x10000,
+            MemorySpace::InternalRam => self.mem.len(),
+            MemorySpace::PrgRom => self.memory(MapperMemory::PrgRom).len(),
+            MemorySpace::PrgRam => self.memory(MapperMemory::PrgRam).len(),
+            MemorySpace::Chr => self.memory(MapperMemory::Chr).len(),
+            MemorySpace::Nametable => self.ppu.vram.len(),
+            MemorySpace::Palette => self.ppu.palette.len(),
+            MemorySpace::Oam => self.ppu.oam.len(),
+        }
+    }
+
+    /// Reads a byte of `space` without side effects, 0 when out of range
+    pub fn peek(&self, space: MemorySpace, addr: usize) -> u8 {
+        let memory: &[u8] = match space {
+            MemorySpace::CpuBus => return self.read_only(addr as u16),
+            MemorySpace::InternalRam => &self.mem,
+            MemorySpace::PrgRom => self.memory(MapperMemory::PrgRom),
+            MemorySpace::PrgRam => self.memory(MapperMemory::PrgRam),
+            MemorySpace::Chr => self.memory(MapperMemory::Chr),
+            MemorySpace::Nametable => &self.ppu.vram,
+            MemorySpace::Palette => &self.ppu.palette,
+            MemorySpace::Oam => &self.ppu.oam,
+        };
+        memory.get(addr).copied().unwrap_or(0)
+    }
+
+    /// Debugger write. ROM is patched in place, while the CPU bus only takes
+    /// writes to RAM and ignores the rest, see `MemorySpace::writable`.
+    pub fn poke(&mut self, space: MemorySpace, addr: usize, value: u8) {
+        if !space.writable(addr) {
+            return;
+        }
+        let memory: &mut [u8] = match space {
+            MemorySpace::CpuBus => {
+                if addr < 0x2000 {
+                    self.write_mem(addr as u16, value);
+                } else {
+                    self.poke(MemorySpace::PrgRam, addr - 0x6000, value);
+                }
+                return;
+            }
+            MemorySpace::Palette => {
+                self.ppu.write_palette(addr as u16, value);
+                return;
+            }
+            MemorySpace::InternalRam => &mut self.mem,
+            MemorySpace::PrgRom | MemorySpace::PrgRam | MemorySpace::Chr => {
+                let kind = match space {
+                    MemorySpace::PrgRom => MapperMemory::PrgRom,
+                    MemorySpace::PrgRam => MapperMemory::PrgRam,
+                    _ => MapperMemory::Chr,
+                };
+                match &mut self.cart {
+                    Some(cart) => cart.mapper.memory_mut(kind),
+                    None => return,
+                }
+            }
+            MemorySpace::Nametable => &mut self.ppu.vram,
+            MemorySpace::Oam => &mut self.ppu.oam,
+        };
+        if let Some(byte) = memory.get_mut(addr) {
+            *byte = value;
+        }
+    }
+
     fn write_mem(&mut self, addr: u16, value: u8) {
         self.mem[(addr & 0x7FF) as usize] = value;
     }
@@ -175,8 +357,7 @@ impl Bus {
     }
 
     fn write_ppu(&mut self, addr: u16, value: u8) {
-        self.ppu.open_bus = value;
-        self.ppu.open_bus_decay_timer = self.ppu.frame;
+        self.ppu.set_open_bus(0xFF, value);
 
         let reg = addr & 0x07;
         match reg {
@@ -188,6 +369,10 @@ impl Bus {
             5 => self.ppu.write_scroll(value),
             6 => self.ppu.write_addr(value),
             7 => {
+                if self.breakpoints.watches(AddrSpace::Ppu, BreakKind::WRITE) {
+                    let vram_addr = self.ppu.v & 0x3FFF;
+                    self.check_breakpoint(AddrSpace::Ppu, BreakKind::WRITE, vram_addr, value);
+                }
                 if let Some(cart) = &mut self.cart {
                     self.ppu.write_data(value, &mut cart.mapper);
                 }
@@ -226,15 +411,26 @@ impl Bus {
     pub fn write_byte(&mut self, addr: u16, value: u8) {
         self.open_bus = value;
 
+        if self.breakpoints.watches(AddrSpace::Cpu, BreakKind::WRITE) {
+            self.check_breakpoint(AddrSpace::Cpu, BreakKind::WRITE, addr, value);
+        }
+
         match addr {
             0x0000..=0x1FFF => self.write_mem(addr, value),
-            0x2000..=0x3FFF => self.write_ppu(addr, value),
-            0x4014 => self.write_dma(value),
+            0x2000..=0x3FFF => {
+                self.log_ppu_event(PpuEventKind::RegisterWrite, 0x2000 | (addr & 0x07), value);
+                self.write_ppu(addr, value)
+            }
+            0x4014 => {
+                self.log_ppu_event(PpuEventKind::OamDma, addr, value);
+                self.write_dma(value)
+            }
             0x4015 => self.write_apu(addr, value),
             0x4016 => self.write_controller(value),
             0x4017 => self.write_apu(addr, value),
             0x4000..=0x401F => self.write_apu(addr, value),
             0x4020..=0xFFFF => {
+                self.log_ppu_event(PpuEventKind::MapperWrite, addr, value);
                 if let Some(cart) = &mut self.cart {
                     cart.mapper.write_prg(addr, value);
                 }
@@ -0,0 +1,220 @@
+use anyhow::{Result, bail};
+use bitflags::bitflags;
+
+bitflags! {
+    /// FCEUX PRG byte flags: `xPdcAADC`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PrgFlags: u8 {
+        const CODE = 0x01;
+        const DATA = 0x02;
+        /// 8 KiB window ($8000/$A000/$C000/$E000) the byte was last accessed through
+        const BANK = 0x0C;
+        const INDIRECT_CODE = 0x10;
+        const INDIRECT_DATA = 0x20;
+        const PCM = 0x40;
+    }
+}
+
+bitflags! {
+    /// FCEUX CHR byte flags: `xxxxxxRD`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ChrFlags: u8 {
+        const DRAWN = 0x01;
+        const READ = 0x02;
+    }
+}
+
+/// What the CPU is reading PRG for, set around fetches and operand accesses
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Access {
+    #[default]
+    Data,
+    IndirectData,
+    Code,
+    IndirectCode,
+    Pcm,
+    /// Reads the 6502 makes on the way to the real one, whose value is thrown away
+    Dummy,
+}
+
+impl Access {
+    fn flags(self) -> PrgFlags {
+        match self {
+            Access::Data => PrgFlags::DATA,
+            Access::IndirectData => PrgFlags::DATA | PrgFlags::INDIRECT_DATA,
+            Access::Code => PrgFlags::CODE,
+            Access::IndirectCode => PrgFlags::CODE | PrgFlags::INDIRECT_CODE,
+            Access::Pcm => PrgFlags::PCM,
+            Access::Dummy => PrgFlags::empty(),
+        }
+    }
+
+    /// Reads an instruction asked for, rather than opcode fetches and dummy reads
+    pub fn is_data(self) -> bool {
+        matches!(self, Access::Data | Access::IndirectData | Access::Pcm)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CdlStats {
+    pub prg_size: usize,
+    pub code: usize,
+    pub data: usize,
+    pub pcm: usize,
+    pub prg_logged: usize,
+    pub chr_size: usize,
+    pub chr_drawn: usize,
+    pub chr_read: usize,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CodeDataLog {
+    pub enabled: bool,
+    pub access: Access,
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+}
+
+impl CodeDataLog {
+    /// CHR RAM carts get an empty CHR log, matching FCEUX
+    pub fn new(prg_size: usize, chr_rom_size: usize) -> Self {
+        Self {
+            enabled: false,
+            access: Access::Data,
+            prg: vec![0; prg_size],
+            chr: vec![0; chr_rom_size],
+        }
+    }
+
+    pub fn log_prg(&mut self, offset: usize, addr: u16) {
+        if self.access == Access::Dummy {
+            return;
+        }
+        if let Some(byte) = self.prg.get_mut(offset) {
+            let bank = (((addr >> 13) & 0x03) as u8) << 2;
+            *byte = (*byte & !PrgFlags::BANK.bits()) | bank | self.access.flags().bits();
+        }
+    }
+
+    pub fn log_chr(&mut self, offset: usize, flags: ChrFlags) {
+        if let Some(byte) = self.chr.get_mut(offset) {
+            *byte |= flags.bits();
+        }
+    }
+
+    pub fn prg_flags(&self, offset: usize) -> PrgFlags {
+        PrgFlags::from_bits_truncate(self.prg.get(offset).copied().unwrap_or(0))
+    }
+
+    pub fn reset(&mut self) {
+        self.prg.fill(0);
+        self.chr.fill(0);
+    }
+
+    /// FCEUX `.cdl` layout: the PRG log followed by the CHR log
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.prg.as_slice(), self.chr.as_slice()].concat()
+    }
+
+    pub fn load_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        if bytes.len() != self.prg.len() + self.chr.len() {
+            bail!(
+                "CDL file is {} bytes, expected {} for this ROM",
+                bytes.len(),
+                self.prg.len() + self.chr.len()
+            );
+        }
+        let (prg, chr) = bytes.split_at(self.prg.len());
+        self.prg.copy_from_slice(prg);
+        self.chr.copy_from_slice(chr);
+        Ok(())
+    }
+
+    pub fn stats(&self) -> CdlStats {
+        let prg_count =
+            |flags: PrgFlags| self.prg.iter().filter(|b| **b & flags.bits() != 0).count();
+        let chr_count =
+            |flags: ChrFlags| self.chr.iter().filter(|b| **b & flags.bits() != 0).count();
+        CdlStats {
+            prg_size: self.prg.len(),
+            code: prg_count(PrgFlags::CODE),
+            data: prg_count(PrgFlags::DATA),
+            pcm: prg_count(PrgFlags::PCM),
+            prg_logged: prg_count(PrgFlags::CODE | PrgFlags::DATA | PrgFlags::PCM),
+            chr_size: self.chr.len(),
+            chr_drawn: chr_count(ChrFlags::DRAWN),
+            chr_read: chr_count(ChrFlags::READ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(cdl: &mut CodeDataLog, access: Access, offset: usize, addr: u16) {
+        cdl.access = access;
+        cdl.log_prg(offset, addr);
+    }
+
+    #[test]
+    fn prg_bytes_match_fceux() {
+        let mut cdl = CodeDataLog::new(0x8000, 0);
+        log(&mut cdl, Access::Code, 0, 0x8000);
+        log(&mut cdl, Access::IndirectData, 1, 0xA001);
+        log(&mut cdl, Access::Pcm, 2, 0xC002);
+        log(&mut cdl, Access::IndirectCode, 3, 0xE003);
+        assert_eq!(
+            cdl.to_bytes()[..4],
+            [0x01, 0x22 | 0x04, 0x40 | 0x08, 0x11 | 0x0C]
+        );
+    }
+
+    #[test]
+    fn flags_add_up_and_the_window_moves() {
+        let mut cdl = CodeDataLog::new(0x4000, 0);
+        log(&mut cdl, Access::Code, 0x10, 0xC010);
+        log(&mut cdl, Access::Data, 0x10, 0x8010);
+        assert_eq!(cdl.prg_flags(0x10), PrgFlags::CODE | PrgFlags::DATA);
+        log(&mut cdl, Access::Dummy, 0x11, 0x8011);
+        assert_eq!(cdl.prg_flags(0x11), PrgFlags::empty());
+        // Past the end of PRG is ignored
+        log(&mut cdl, Access::Code, 0x4000, 0xC000);
+        assert_eq!(cdl.to_bytes().len(), 0x4000);
+    }
+
+    #[test]
+    fn file_is_prg_then_chr() {
+        let mut cdl = CodeDataLog::new(4, 2);
+        log(&mut cdl, Access::Data, 3, 0x8003);
+        cdl.log_chr(0, ChrFlags::DRAWN);
+        cdl.log_chr(1, ChrFlags::READ);
+        cdl.log_chr(1, ChrFlags::DRAWN);
+        let bytes = cdl.to_bytes();
+        assert_eq!(bytes, [0, 0, 0, 0x02, 0x01, 0x03]);
+
+        let mut loaded = CodeDataLog::new(4, 2);
+        loaded.load_bytes(&bytes).unwrap();
+        assert_eq!(loaded.to_bytes(), bytes);
+        assert!(loaded.load_bytes(&bytes[..5]).is_err());
+
+        loaded.reset();
+        assert_eq!(loaded.to_bytes(), [0; 6]);
+    }
+
+    #[test]
+    fn stats() {
+        let mut cdl = CodeDataLog::new(8, 4);
+        log(&mut cdl, Access::Code, 0, 0x8000);
+        log(&mut cdl, Access::Code, 1, 0x8001);
+        log(&mut cdl, Access::Data, 1, 0x8001);
+        log(&mut cdl, Access::Pcm, 2, 0x8002);
+        cdl.log_chr(0, ChrFlags::DRAWN);
+        cdl.log_chr(1, ChrFlags::READ);
+        let stats = cdl.stats();
+        assert_eq!((stats.prg_size, stats.chr_size), (8, 4));
+        assert_eq!((stats.code, stats.data, stats.pcm), (2, 1, 1));
+        assert_eq!(stats.prg_logged, 3);
+        assert_eq!((stats.chr_drawn, stats.chr_read), (1, 1));
+    }
+}
@@ -0,0 +1,264 @@
+use anyhow::{Result, bail};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+/// Game Genie letters in the order of the nibble they encode
+const GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cheat {
+    /// Game Genie code, or a RAM freeze as `AAAA:VV` or Pro Action Replay style `AAAAVV`
+    pub code: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheatEffect {
+    /// Replaces what the CPU reads from PRG at `addr`, only while the ROM
+    /// holds `compare` there when one is given
+    Patch {
+        addr: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// Rewrites a byte of internal RAM ($0000-$07FF) or PRG RAM ($6000-$7FFF) every frame
+    Freeze { addr: u16, value: u8 },
+}
+
+impl Cheat {
+    pub fn new(code: &str, description: &str) -> Result<Self> {
+        let cheat = Self {
+            code: code.trim().to_uppercase(),
+            description: description.trim().to_string(),
+            enabled: true,
+        };
+        cheat.effect()?;
+        Ok(cheat)
+    }
+
+    pub fn freeze(addr: u16, value: u8, description: &str) -> Self {
+        Self {
+            code: format!("{:04X}:{:02X}", addr, value),
+            description: description.to_string(),
+            enabled: true,
+        }
+    }
+
+    pub fn effect(&self) -> Result<CheatEffect> {
+        let code = self.code.trim();
+        if let Some((addr, value)) = code.split_once(':') {
+            return freeze(
+                u16::from_str_radix(addr, 16)?,
+                u8::from_str_radix(value, 16)?,
+            );
+        }
+        if code.chars().all(|c| GAME_GENIE_LETTERS.contains(c)) {
+            return decode_game_genie(code);
+        }
+        if code.len() == 6 && code.chars().all(|c| c.is_ascii_hexdigit()) {
+            return freeze(
+                u16::from_str_radix(&code[..4], 16)?,
+                u8::from_str_radix(&code[4..], 16)?,
+            );
+        }
+        bail!("Unrecognized cheat code \"{}\"", code)
+    }
+}
+
+/// Only RAM can be frozen, rewriting ROM or registers every frame would stick or have side effects
+fn freeze(addr: u16, value: u8) -> Result<CheatEffect> {
+    if !matches!(addr, 0x0000..=0x07FF | 0x6000..=0x7FFF) {
+        bail!("Only $0000-$07FF and $6000-$7FFF can be frozen, not ${addr:04X}");
+    }
+    Ok(CheatEffect::Freeze { addr, value })
+}
+
+fn decode_game_genie(code: &str) -> Result<CheatEffect> {
+    let n: Vec<u16> = code
+        .chars()
+        .filter_map(|c| GAME_GENIE_LETTERS.find(c))
+        .map(|i| i as u16)
+        .collect();
+    if n.len() != 6 && n.len() != 8 {
+        bail!("Game Genie codes are 6 or 8 letters long");
+    }
+
+    let addr = 0x8000
+        | ((n[3] & 7) << 12)
+        | ((n[5] & 7) << 8)
+        | ((n[4] & 8) << 8)
+        | ((n[2] & 7) << 4)
+        | ((n[1] & 8) << 4)
+        | (n[4] & 7)
+        | (n[3] & 8);
+    // The last letter's high bit moves from the value to the compare byte in 8-letter codes
+    let value_top = if n.len() == 6 { n[5] } else { n[7] };
+    let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7) | (value_top & 8);
+    let compare = (n.len() == 8)
+        .then(|| ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8))
+        .map(|c| c as u8);
+
+    Ok(CheatEffect::Patch {
+        addr,
+        value: value as u8,
+        compare,
+    })
+}
+
+/// Enabled cheats, split by where they take effect
+#[derive(Debug, Default, Clone)]
+pub struct ActiveCheats {
+    patches: Vec<(u16, u8, Option<u8>)>,
+    freezes: Vec<(u16, u8)>,
+}
+
+impl ActiveCheats {
+    pub fn set(&mut self, cheats: &[Cheat]) {
+        self.patches.clear();
+        self.freezes.clear();
+        for cheat in cheats.iter().filter(|c| c.enabled) {
+            match cheat.effect() {
+                Ok(CheatEffect::Patch {
+                    addr,
+                    value,
+                    compare,
+                }) => self.patches.push((addr, value, compare)),
+                Ok(CheatEffect::Freeze { addr, value }) => self.freezes.push((addr, value)),
+                Err(e) => error!("Skipping cheat: {e}"),
+            }
+        }
+    }
+
+    #[inline]
+    pub fn patch(&self, addr: u16, value: u8) -> u8 {
+        if self.patches.is_empty() {
+            return value;
+        }
+        self.patches
+            .iter()
+            .find(|(a, _, compare)| *a == addr && compare.is_none_or(|c| c == value))
+            .map(|(_, v, _)| *v)
+            .unwrap_or(value)
+    }
+
+    pub fn freezes(&self) -> &[(u16, u8)] {
+        &self.freezes
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CheatFile {
+    cheats: Vec<Cheat>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn cheat_file_path(hash: &str) -> Result<std::path::PathBuf> {
+    use crate::platform::native::{ProjDirKind, get_project_dir};
+
+    let mut path = get_project_dir(ProjDirKind::Config)?;
+    path.push("cheats");
+    path.push(format!("{}.toml", hash));
+    Ok(path)
+}
+
+/// Cheats saved for the ROM with the given SHA1
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_for_rom(hash: &str) -> Result<Vec<Cheat>> {
+    let path = cheat_file_path(hash)?;
+    if !std::fs::exists(&path)? {
+        return Ok(Vec::new());
+    }
+    let file: CheatFile = toml::from_str(&std::fs::read_to_string(path)?)?;
+    Ok(file.cheats)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_for_rom(hash: &str, cheats: &[Cheat]) -> Result<()> {
+    let path = cheat_file_path(hash)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file = CheatFile {
+        cheats: cheats.to_vec(),
+    };
+    std::fs::write(path, toml::to_string_pretty(&file)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn effect(code: &str) -> Result<CheatEffect> {
+        Cheat::new(code, "")?.effect()
+    }
+
+    #[test]
+    fn six_letter_game_genie() {
+        assert_eq!(
+            effect("SXIOPO").unwrap(),
+            CheatEffect::Patch {
+                addr: 0x91D9,
+                value: 0xAD,
+                compare: None,
+            }
+        );
+    }
+
+    #[test]
+    fn eight_letter_game_genie() {
+        assert_eq!(
+            effect("zexpygla").unwrap(),
+            CheatEffect::Patch {
+                addr: 0x94A7,
+                value: 0x02,
+                compare: Some(0x03),
+            }
+        );
+    }
+
+    #[test]
+    fn game_genie_length() {
+        assert!(effect("SXIOP").is_err());
+        assert!(effect("SXIOPOA").is_err());
+    }
+
+    #[test]
+    fn freezes() {
+        let freeze = |addr, value| CheatEffect::Freeze { addr, value };
+        assert_eq!(effect("075A:09").unwrap(), freeze(0x075A, 0x09));
+        assert_eq!(effect("60FF12").unwrap(), freeze(0x60FF, 0x12));
+        assert_eq!(
+            Cheat::freeze(0x7FFF, 1, "").effect().unwrap(),
+            freeze(0x7FFF, 1)
+        );
+    }
+
+    #[test]
+    fn freezes_outside_ram() {
+        for code in ["0800:01", "2000:80", "4014:02", "8000:EA", "FFFC00"] {
+            assert!(effect(code).is_err(), "{code}");
+        }
+    }
+
+    #[test]
+    fn patches_respect_compare() {
+        let mut active = ActiveCheats::default();
+        active.set(&[
+            Cheat::new("SXIOPO", "").unwrap(),
+            Cheat::new("ZEXPYGLA", "").unwrap(),
+        ]);
+        assert_eq!(active.patch(0x91D9, 0x00), 0xAD);
+        assert_eq!(active.patch(0x94A7, 0x03), 0x02);
+        assert_eq!(active.patch(0x94A7, 0x04), 0x04);
+        assert_eq!(active.patch(0x8000, 0x55), 0x55);
+    }
+}
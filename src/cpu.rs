@@ -8,9 +8,17 @@ use phf::phf_map;
 use savefile::prelude::*;
 
 use crate::bus::Bus;
+use crate::cdl::Access;
+use crate::ppu_events::PpuEventKind;
 
 const MAX_LOG_SIZE: usize = 3000;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+}
+
 #[derive(Debug)]
 pub enum OperandValue {
     Implied,
@@ -114,7 +122,7 @@ impl AddrMode {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum OpMnemonic {
     LDA,
     STA,
@@ -506,6 +514,17 @@ impl Default for Cpu {
     }
 }
 
+pub fn decode_opcode(opcode: u8) -> Option<&'static Op> {
+    OPCODES.get(&opcode)
+}
+
+/// A read whose value the 6502 discards, kept for its side effects on registers
+fn dummy_read(bus: &mut Bus, addr: u16) {
+    let access = std::mem::replace(&mut bus.cdl.access, Access::Dummy);
+    bus.read_byte(addr);
+    bus.cdl.access = access;
+}
+
 impl Cpu {
     pub fn new(enable_logging: bool) -> Self {
         Self {
@@ -537,17 +556,25 @@ impl Cpu {
         self.cycles = 7;
     }
 
+    /// Opcode and operand reads are logged as code until `execute` hands over
     fn fetch(&self, bus: &mut Bus) -> u8 {
+        if bus.cdl.access != Access::IndirectCode {
+            bus.cdl.access = Access::Code;
+        }
         bus.read_byte(self.pc)
     }
 
     fn decode(&self, opcode: u8) -> Option<&'static Op> {
-        OPCODES.get(&opcode)
+        decode_opcode(opcode)
     }
 
     fn execute(&mut self, bus: &mut Bus, op: &Op, opcode: u8) {
         let operand_bytes = op.mode.operand_bytes();
         let operands = bus.read_range(self.pc.wrapping_add(1), operand_bytes);
+        bus.cdl.access = match op.mode {
+            AddrMode::IndirectX | AddrMode::IndirectY => Access::IndirectData,
+            _ => Access::Data,
+        };
 
         if self.log.is_some() {
             self.log(bus, opcode, op, &operands);
@@ -555,11 +582,20 @@ impl Cpu {
 
         self.pc = self.pc.wrapping_add(1 + operand_bytes);
         let extra_cycles = (op.execute)(self, bus, op.mode, &operands);
+        // JMP ($nnnn) lands on code reached through a pointer
+        bus.cdl.access = if op.mode == AddrMode::Indirect {
+            Access::IndirectCode
+        } else {
+            Access::Data
+        };
         let total_cycles = op.base_cycles + extra_cycles;
         self.cycles += total_cycles as u32;
 
-        bus.ppu
-            .step(&mut bus.cart.as_mut().unwrap().mapper, total_cycles as u32);
+        bus.ppu.step(
+            &mut bus.cart.as_mut().unwrap().mapper,
+            &mut bus.cdl,
+            total_cycles as u32,
+        );
         let nmi_current_state = bus.ppu.check_nmi();
         if nmi_current_state && !self.nmi_previous_state {
             self.nmi_pending = true;
@@ -569,6 +605,10 @@ impl Cpu {
 
     fn log(&mut self, bus: &Bus, opcode: u8, op: &Op, operands: &[u8]) {
         let log = self.log.as_mut().unwrap();
+        if let Some(symbol) = bus.symbol(self.pc) {
+            log.extend(symbol.name.chars());
+            log.extend(":\n".chars());
+        }
         let step_str = format!(
             "{:04X}  {:02X} {:6}{}{} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:3},{:3} CYC:{}\n",
             self.pc,
@@ -598,33 +638,58 @@ impl Cpu {
         }
     }
 
-    pub fn step(&mut self, bus: &mut Bus) -> Result<()> {
+    pub fn pending_interrupt(&self) -> Option<Interrupt> {
         if self.nmi_pending {
-            self.handle_nmi(bus);
-            self.nmi_pending = false;
-            Ok(())
+            Some(Interrupt::Nmi)
         } else if self.irq_pending && !self.p.contains(Flags::I) {
-            self.handle_irq(bus);
-            self.irq_pending = false;
-            Ok(())
+            Some(Interrupt::Irq)
         } else {
-            let opcode = self.fetch(bus);
-            let op = self.decode(opcode);
+            None
+        }
+    }
 
-            match op {
-                Some(op) => {
-                    self.execute(bus, op, opcode);
-                    Ok(())
+    pub fn step(&mut self, bus: &mut Bus) -> Result<()> {
+        bus.ppu_events.pc = self.pc;
+        match self.pending_interrupt() {
+            Some(Interrupt::Nmi) => {
+                bus.cdl.access = Access::Data;
+                self.handle_nmi(bus);
+                self.nmi_pending = false;
+                Ok(())
+            }
+            Some(Interrupt::Irq) => {
+                bus.cdl.access = Access::Data;
+                self.handle_irq(bus);
+                self.irq_pending = false;
+                Ok(())
+            }
+            None => {
+                if !bus.breakpoints.is_empty() {
+                    let (scanline, dot) = (bus.ppu.scanline, bus.ppu.dot);
+                    if bus.breakpoints.check_execute(self, scanline, dot) {
+                        return Ok(());
+                    }
                 }
-                None => {
-                    self.pc = self.pc.wrapping_add(1);
-                    bail!(format!("Unknown opcode: 0x{:02X}", opcode))
+
+                let opcode = self.fetch(bus);
+                let op = self.decode(opcode);
+
+                match op {
+                    Some(op) => {
+                        self.execute(bus, op, opcode);
+                        Ok(())
+                    }
+                    None => {
+                        self.pc = self.pc.wrapping_add(1);
+                        bail!(format!("Unknown opcode: 0x{:02X}", opcode))
+                    }
                 }
             }
         }
     }
 
     fn handle_nmi(&mut self, bus: &mut Bus) {
+        bus.log_ppu_event(PpuEventKind::Nmi, 0xFFFA, 0);
         self.push_stack(bus, (self.pc >> 8) as u8);
         self.push_stack(bus, self.pc as u8);
 
@@ -641,10 +706,12 @@ impl Cpu {
 
         let cycles: u32 = 7;
         self.cycles += cycles;
-        bus.ppu.step(&mut bus.cart.as_mut().unwrap().mapper, cycles);
+        bus.ppu
+            .step(&mut bus.cart.as_mut().unwrap().mapper, &mut bus.cdl, cycles);
     }
 
     fn handle_irq(&mut self, bus: &mut Bus) {
+        bus.log_ppu_event(PpuEventKind::Irq, 0xFFFE, 0);
         self.push_stack(bus, (self.pc >> 8) as u8);
         self.push_stack(bus, self.pc as u8);
 
@@ -661,7 +728,8 @@ impl Cpu {
 
         let cycles: u32 = 7;
         self.cycles += cycles;
-        bus.ppu.step(&mut bus.cart.as_mut().unwrap().mapper, cycles);
+        bus.ppu
+            .step(&mut bus.cart.as_mut().unwrap().mapper, &mut bus.cdl, cycles);
     }
 
     fn update_nz(&mut self, value: u8) {
@@ -687,7 +755,7 @@ impl Cpu {
                     let page = addr & 0xFF00;
                     let dummy_page = page.wrapping_sub(0x100);
                     let dummy_addr = dummy_page | (addr & 0x00FF);
-                    bus.read_byte(dummy_addr);
+                    dummy_read(bus, dummy_addr);
                 }
                 (bus.read_byte(addr), crossed)
             }
@@ -705,7 +773,7 @@ impl Cpu {
                         } else {
                             addr
                         };
-                        bus.read_byte(dummy_addr);
+                        dummy_read(bus, dummy_addr);
                     }
                     _ => {}
                 }
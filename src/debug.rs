@@ -1,13 +1,85 @@
+use std::sync::Arc;
+
 use crate::{
     apu::Apu,
+    breakpoint::Breakpoints,
     cart::Cart,
+    cdl::{CdlStats, PrgFlags},
     cpu::{Cpu, Flags},
-    ppu::{Ppu, PpuCtrl, PpuMask, PpuStatus},
+    disasm::DisasmLine,
+    ppu::{Ppu, PpuCtrl, PpuMask, PpuStatus, ScanlineRegisters},
+    ppu_events::PpuEvent,
+    ram_search::SearchMemory,
+    symbols::{Symbol, SymbolTable},
+    watch::WATCH_BYTES,
 };
 
 pub const ROWS_TO_SHOW: usize = 7;
 pub const BYTES_PER_ROW: usize = 0x10;
 pub const MEM_BLOCK_SIZE: usize = ROWS_TO_SHOW * BYTES_PER_ROW;
+pub const PRG_PAGE_SIZE: usize = 0x1000;
+pub const PRG_PAGES: usize = 0x8000 / PRG_PAGE_SIZE;
+
+/// Address spaces the memory editor can view and patch
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum MemorySpace {
+    #[default]
+    CpuBus,
+    InternalRam,
+    PrgRom,
+    PrgRam,
+    Chr,
+    Nametable,
+    Palette,
+    Oam,
+}
+
+impl MemorySpace {
+    pub const ALL: [MemorySpace; 8] = [
+        MemorySpace::CpuBus,
+        MemorySpace::InternalRam,
+        MemorySpace::PrgRom,
+        MemorySpace::PrgRam,
+        MemorySpace::Chr,
+        MemorySpace::Nametable,
+        MemorySpace::Palette,
+        MemorySpace::Oam,
+    ];
+
+    /// Whether the memory editor may change `addr`. On the CPU bus only RAM is,
+    /// as writing anywhere else would poke registers or patch PRG ROM
+    pub fn writable(self, addr: usize) -> bool {
+        match self {
+            MemorySpace::CpuBus => matches!(addr, 0x0000..=0x1FFF | 0x6000..=0x7FFF),
+            _ => true,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MemorySpace::CpuBus => "CPU bus",
+            MemorySpace::InternalRam => "Internal RAM",
+            MemorySpace::PrgRom => "PRG ROM",
+            MemorySpace::PrgRam => "PRG RAM",
+            MemorySpace::Chr => "CHR",
+            MemorySpace::Nametable => "Nametable VRAM",
+            MemorySpace::Palette => "Palette RAM",
+            MemorySpace::Oam => "OAM",
+        }
+    }
+}
+
+/// Parts of the snapshot that are costly to build, only filled in while the
+/// panels showing them are open
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DebugPanels {
+    pub disassembly: bool,
+    pub cdl_stats: bool,
+    /// Internal and PRG RAM, for the RAM search
+    pub ram: bool,
+    pub chr: bool,
+    pub nametables: bool,
+}
 
 #[derive(Clone)]
 pub struct DebugSnapshot {
@@ -16,7 +88,37 @@ pub struct DebugSnapshot {
     pub apu: ApuSnapshot,
     pub cart: Option<CartSnapshot>,
     pub mem_chunk: [u8; MEM_BLOCK_SIZE],
+    pub mem_space: MemorySpace,
+    pub mem_chunk_addr: usize,
+    /// Number of bytes in `mem_space`
+    pub mem_space_size: usize,
     pub stack: [u8; 0x100],
+    /// Internal RAM and cartridge PRG RAM, for the RAM search. Left empty
+    /// unless `DebugPanels::ram` is set
+    pub ram: [u8; 0x800],
+    pub prg_ram: Vec<u8>,
+    /// Pattern tables as currently banked in, $0000-$1FFF. Empty unless
+    /// `DebugPanels::chr` is set
+    pub chr: Vec<u8>,
+    /// Logical nametables $2000-$2FFF resolved through the current mirroring.
+    /// Empty unless `DebugPanels::nametables` is set
+    pub nametables: Vec<u8>,
+    /// Bytes at each address of `Command::SetWatches`
+    pub watch_values: Vec<(u16, [u8; WATCH_BYTES])>,
+    pub breakpoint_hits: Vec<(u32, u32)>,
+    /// Empty unless `DebugPanels::disassembly` is set
+    pub disasm: Vec<DisasmLine>,
+    pub symbols: Arc<SymbolTable>,
+    /// PRG ROM offset mapped at each 4 KiB page of $8000-$FFFF
+    pub prg_pages: [Option<usize>; PRG_PAGES],
+    pub cdl_enabled: bool,
+    /// All zero unless `DebugPanels::cdl_stats` is set
+    pub cdl_stats: CdlStats,
+    /// Code/data flags for each byte of `mem_chunk`
+    pub cdl_chunk: [PrgFlags; MEM_BLOCK_SIZE],
+    pub ppu_events_enabled: bool,
+    /// The last frame's worth of PPU events, up to the current dot
+    pub ppu_events: Vec<PpuEvent>,
 }
 
 impl Default for DebugSnapshot {
@@ -27,7 +129,24 @@ impl Default for DebugSnapshot {
             apu: Default::default(),
             cart: None,
             mem_chunk: [0; MEM_BLOCK_SIZE],
+            mem_space: MemorySpace::CpuBus,
+            mem_chunk_addr: 0,
+            mem_space_size: 0x10000,
             stack: [0; 0x100],
+            ram: [0; 0x800],
+            prg_ram: Vec::new(),
+            chr: Vec::new(),
+            nametables: Vec::new(),
+            watch_values: Vec::new(),
+            breakpoint_hits: Vec::new(),
+            disasm: Vec::new(),
+            symbols: Default::default(),
+            prg_pages: [None; PRG_PAGES],
+            cdl_enabled: false,
+            cdl_stats: Default::default(),
+            cdl_chunk: [PrgFlags::empty(); MEM_BLOCK_SIZE],
+            ppu_events_enabled: false,
+            ppu_events: Vec::new(),
         }
     }
 }
@@ -65,6 +184,8 @@ pub struct PpuSnapshot {
     pub w: bool,
     pub palette: [u8; 32],
     pub oam: [u8; 256],
+    /// What each visible scanline was rendered with
+    pub scanline_registers: Vec<ScanlineRegisters>,
 }
 
 impl Default for PpuSnapshot {
@@ -83,6 +204,7 @@ impl Default for PpuSnapshot {
             w: Default::default(),
             palette: Default::default(),
             oam: [0; 256],
+            scanline_registers: Vec::new(),
         }
     }
 }
@@ -132,6 +254,7 @@ impl DebugSnapshot {
         cart: Option<&Cart>,
         memory: &[u8],
         stack: &[u8],
+        breakpoints: &Breakpoints,
     ) -> Self {
         let mut mem_chunk = [0u8; MEM_BLOCK_SIZE];
         let len = memory.len().min(MEM_BLOCK_SIZE);
@@ -143,6 +266,15 @@ impl DebugSnapshot {
 
         let log_string = cpu.log.as_ref().map(|l| l.iter().collect::<String>());
 
+        let mut prg_pages = [None; PRG_PAGES];
+        if let Some(cart) = cart {
+            for (i, page) in prg_pages.iter_mut().enumerate() {
+                *page = cart
+                    .mapper
+                    .prg_rom_offset((0x8000 + i * PRG_PAGE_SIZE) as u16);
+            }
+        }
+
         Self {
             cpu: CpuSnapshot {
                 sp: cpu.sp,
@@ -174,6 +306,7 @@ impl DebugSnapshot {
                 w: ppu.w,
                 palette: ppu.palette,
                 oam: ppu.oam,
+                scanline_registers: ppu.scanline_registers.clone(),
             },
             apu: ApuSnapshot {
                 pulse1_enabled: apu.pulse1.enabled,
@@ -213,7 +346,61 @@ impl DebugSnapshot {
                 hash: h.hash.clone(),
             }),
             mem_chunk,
+            mem_space: MemorySpace::CpuBus,
+            mem_chunk_addr: 0,
+            mem_space_size: 0x10000,
             stack: stack_chunk,
+            ram: [0; 0x800],
+            prg_ram: Vec::new(),
+            chr: Vec::new(),
+            nametables: Vec::new(),
+            watch_values: Vec::new(),
+            breakpoint_hits: breakpoints.hit_counts(),
+            disasm: Vec::new(),
+            symbols: Default::default(),
+            prg_pages,
+            cdl_enabled: false,
+            cdl_stats: Default::default(),
+            cdl_chunk: [PrgFlags::empty(); MEM_BLOCK_SIZE],
+            ppu_events_enabled: false,
+            ppu_events: Vec::new(),
+        }
+    }
+
+    /// Label at `addr` as seen through the PRG banks mapped when the snapshot was taken
+    pub fn symbol(&self, addr: u16) -> Option<&Symbol> {
+        let prg_offset = match addr {
+            0x8000..=0xFFFF => {
+                let addr = addr as usize - 0x8000;
+                self.prg_pages[addr / PRG_PAGE_SIZE].map(|page| page + addr % PRG_PAGE_SIZE)
+            }
+            _ => None,
+        };
+        self.symbols.lookup(addr, prg_offset)
+    }
+
+    pub fn watch_value(&self, addr: u16) -> Option<[u8; WATCH_BYTES]> {
+        self.watch_values
+            .iter()
+            .find(|(a, _)| *a == addr)
+            .map(|(_, bytes)| *bytes)
+    }
+
+    pub fn search_memory(&self) -> SearchMemory<'_> {
+        SearchMemory {
+            ram: &self.ram,
+            prg_ram: &self.prg_ram,
+        }
+    }
+
+    /// Label for a byte of the memory editor's current space
+    pub fn mem_symbol(&self, addr: usize) -> Option<&Symbol> {
+        match self.mem_space {
+            MemorySpace::CpuBus => self.symbol(addr as u16),
+            MemorySpace::InternalRam => self.symbols.lookup(addr as u16, None),
+            MemorySpace::PrgRom => self.symbols.lookup(0, Some(addr)),
+            MemorySpace::PrgRam => self.symbols.lookup(0x6000 + addr as u16, None),
+            _ => None,
         }
     }
 }
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+
+use crate::{
+    bus::Bus,
+    cpu::{AddrMode, Cpu, OpMnemonic, decode_opcode},
+    symbols::SourceLine,
+};
+
+pub const LINES_BEFORE: usize = 12;
+pub const LINES_AFTER: usize = 40;
+
+const VECTORS: [(u16, &str); 3] = [(0xFFFA, "nmi"), (0xFFFC, "reset"), (0xFFFE, "irq")];
+
+#[derive(Clone, Debug, Default)]
+pub struct DisasmLine {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    /// PRG ROM offset backing `addr` in the currently selected banks
+    pub prg_offset: Option<usize>,
+    pub label: Option<String>,
+    pub comment: Option<String>,
+    pub source: Option<SourceLine>,
+    pub text: String,
+    pub illegal: bool,
+    /// Destination of a jump, call or branch
+    pub target: Option<u16>,
+    /// Memory operand resolved with the current registers, and the value stored there
+    pub effective: Option<(u16, u8)>,
+}
+
+struct Instruction {
+    addr: u16,
+    opcode: u8,
+    mnemonic: Option<OpMnemonic>,
+    mode: AddrMode,
+    illegal: bool,
+    operands: [u8; 2],
+    len: u16,
+}
+
+impl Instruction {
+    fn decode(bus: &Bus, addr: u16) -> Self {
+        let opcode = bus.read_only(addr);
+        match decode_opcode(opcode) {
+            Some(op) => {
+                let len = 1 + op.mode.operand_bytes();
+                let mut operands = [0; 2];
+                for (i, operand) in operands.iter_mut().enumerate().take(len as usize - 1) {
+                    *operand = bus.read_only(addr.wrapping_add(1 + i as u16));
+                }
+                Self {
+                    addr,
+                    opcode,
+                    mnemonic: Some(op.mnemonic),
+                    mode: op.mode,
+                    illegal: op.illegal,
+                    operands,
+                    len,
+                }
+            }
+            None => Self {
+                addr,
+                opcode,
+                mnemonic: None,
+                mode: AddrMode::Implied,
+                illegal: false,
+                operands: [0; 2],
+                len: 1,
+            },
+        }
+    }
+
+    fn next(&self) -> u16 {
+        self.addr.wrapping_add(self.len)
+    }
+
+    fn word(&self) -> u16 {
+        u16::from_le_bytes(self.operands)
+    }
+
+    fn target(&self, bus: &Bus) -> Option<u16> {
+        match (self.mnemonic?, self.mode) {
+            (_, AddrMode::Relative) => Some(
+                self.next()
+                    .wrapping_add_signed(self.operands[0] as i8 as i16),
+            ),
+            (OpMnemonic::JMP | OpMnemonic::JSR, AddrMode::Absolute) => Some(self.word()),
+            (OpMnemonic::JMP, AddrMode::Indirect) => {
+                let ptr = self.word();
+                let lo = bus.read_only(ptr);
+                let hi = bus.read_only((ptr & 0xFF00) | (ptr.wrapping_add(1) & 0x00FF));
+                Some(u16::from_le_bytes([lo, hi]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Indexed and indirect operands are only meaningful for the instruction at PC
+    fn effective_addr(&self, cpu: &Cpu, bus: &Bus) -> Option<u16> {
+        if matches!(self.mnemonic?, OpMnemonic::JMP | OpMnemonic::JSR) {
+            return None;
+        }
+        let at_pc = self.addr == cpu.pc;
+        let zp_ptr = |ptr: u8| {
+            let lo = bus.read_only(ptr as u16);
+            let hi = bus.read_only(ptr.wrapping_add(1) as u16);
+            u16::from_le_bytes([lo, hi])
+        };
+        match self.mode {
+            AddrMode::ZeroPage => Some(self.operands[0] as u16),
+            AddrMode::Absolute => Some(self.word()),
+            AddrMode::ZeroPageX if at_pc => Some(self.operands[0].wrapping_add(cpu.x) as u16),
+            AddrMode::ZeroPageY if at_pc => Some(self.operands[0].wrapping_add(cpu.y) as u16),
+            AddrMode::AbsoluteX if at_pc => Some(self.word().wrapping_add(cpu.x as u16)),
+            AddrMode::AbsoluteY if at_pc => Some(self.word().wrapping_add(cpu.y as u16)),
+            AddrMode::IndirectX if at_pc => Some(zp_ptr(self.operands[0].wrapping_add(cpu.x))),
+            AddrMode::IndirectY if at_pc => {
+                Some(zp_ptr(self.operands[0]).wrapping_add(cpu.y as u16))
+            }
+            _ => None,
+        }
+    }
+
+    /// Address written in the operand itself, before indexing or indirection
+    fn operand_addr(&self) -> Option<u16> {
+        match self.mode {
+            AddrMode::ZeroPage
+            | AddrMode::ZeroPageX
+            | AddrMode::ZeroPageY
+            | AddrMode::IndirectX
+            | AddrMode::IndirectY => Some(self.operands[0] as u16),
+            AddrMode::Absolute | AddrMode::AbsoluteX | AddrMode::AbsoluteY | AddrMode::Indirect => {
+                Some(self.word())
+            }
+            _ => None,
+        }
+    }
+
+    fn text(&self, target: Option<u16>, labels: &HashMap<u16, String>) -> String {
+        let Some(mnemonic) = self.mnemonic else {
+            return format!(".db ${:02X}", self.opcode);
+        };
+        let name = |addr: u16, zp: bool| match labels.get(&addr) {
+            Some(label) => label.clone(),
+            None if zp => format!("${:02X}", addr),
+            None => format!("${:04X}", addr),
+        };
+        let b = self.operands[0] as u16;
+        let w = self.word();
+        let operand = match self.mode {
+            AddrMode::Implied => String::new(),
+            AddrMode::Accumulator => "A".to_string(),
+            AddrMode::Immediate => format!("#${:02X}", b),
+            AddrMode::ZeroPage => name(b, true),
+            AddrMode::ZeroPageX => format!("{},X", name(b, true)),
+            AddrMode::ZeroPageY => format!("{},Y", name(b, true)),
+            AddrMode::Absolute | AddrMode::Relative => name(target.unwrap_or(w), false),
+            AddrMode::AbsoluteX => format!("{},X", name(w, false)),
+            AddrMode::AbsoluteY => format!("{},Y", name(w, false)),
+            AddrMode::Indirect => format!("({})", name(w, false)),
+            AddrMode::IndirectX => format!("({},X)", name(b, true)),
+            AddrMode::IndirectY => format!("({}),Y", name(b, true)),
+        };
+        if operand.is_empty() {
+            format!("{}", mnemonic)
+        } else {
+            format!("{} {}", mnemonic, operand)
+        }
+    }
+}
+
+/// Finds the earliest address before `center` whose instruction stream lands
+/// exactly on `center`, so the lines above PC line up with what gets executed.
+fn sync_start(bus: &Bus, center: u16) -> u16 {
+    let max_back = (LINES_BEFORE * 3) as u16;
+    for back in (1..=max_back).rev() {
+        let start = center.wrapping_sub(back);
+        let mut addr = start;
+        while (1..=back).contains(&center.wrapping_sub(addr)) {
+            addr = Instruction::decode(bus, addr).next();
+        }
+        if addr == center {
+            return start;
+        }
+    }
+    center
+}
+
+pub fn disassemble(cpu: &Cpu, bus: &Bus, center: u16) -> Vec<DisasmLine> {
+    let mut instructions = Vec::with_capacity(LINES_BEFORE * 3 + LINES_AFTER);
+    let mut addr = sync_start(bus, center);
+    while addr != center {
+        let ins = Instruction::decode(bus, addr);
+        addr = ins.next();
+        instructions.push(ins);
+    }
+    let skip = instructions.len().saturating_sub(LINES_BEFORE);
+    instructions.drain(..skip);
+    for _ in 0..LINES_AFTER {
+        let ins = Instruction::decode(bus, addr);
+        addr = ins.next();
+        instructions.push(ins);
+    }
+
+    let targets: Vec<Option<u16>> = instructions.iter().map(|i| i.target(bus)).collect();
+
+    let mut labels = HashMap::new();
+    for (ins, target) in instructions.iter().zip(&targets) {
+        if let Some(target) = target {
+            let prefix = if ins.mnemonic == Some(OpMnemonic::JSR) {
+                "sub"
+            } else {
+                "loc"
+            };
+            labels
+                .entry(*target)
+                .or_insert_with(|| format!("{}_{:04X}", prefix, target));
+        }
+    }
+    for (vector, name) in VECTORS {
+        let lo = bus.read_only(vector);
+        let hi = bus.read_only(vector + 1);
+        labels.insert(u16::from_le_bytes([lo, hi]), name.to_string());
+    }
+    // Imported symbols win over the generated names
+    let referenced = instructions
+        .iter()
+        .zip(&targets)
+        .flat_map(|(ins, target)| [Some(ins.addr), *target, ins.operand_addr()])
+        .flatten();
+    for addr in referenced {
+        if let Some(symbol) = bus.symbol(addr) {
+            labels.insert(addr, symbol.name.clone());
+        }
+    }
+
+    instructions
+        .iter()
+        .zip(targets)
+        .map(|(ins, target)| {
+            let prg_offset = bus
+                .cart
+                .as_ref()
+                .and_then(|c| c.mapper.prg_rom_offset(ins.addr));
+            DisasmLine {
+                addr: ins.addr,
+                bytes: (0..ins.len)
+                    .map(|i| bus.read_only(ins.addr.wrapping_add(i)))
+                    .collect(),
+                prg_offset,
+                label: labels.get(&ins.addr).cloned(),
+                comment: bus.symbol(ins.addr).and_then(|s| s.comment.clone()),
+                source: prg_offset.and_then(|offset| bus.symbols.source_line(offset)),
+                text: ins.text(target, &labels),
+                illegal: ins.illegal,
+                target,
+                effective: ins
+                    .effective_addr(cpu, bus)
+                    .map(|addr| (addr, bus.read_only(addr))),
+            }
+        })
+        .collect()
+}
@@ -1,4 +1,5 @@
-use std::sync::mpsc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
 
 #[cfg(not(target_arch = "wasm32"))]
 use std::fs;
@@ -11,25 +12,74 @@ use ringbuf::HeapProd;
 use ringbuf::traits::Producer;
 
 use crate::{
+    breakpoint::{BreakHit, Breakpoint},
     bus::Bus,
     cart::Cart,
-    cpu::Cpu,
-    debug::{DebugSnapshot, MEM_BLOCK_SIZE},
-    mapper::MapperEnum,
+    cdl::PrgFlags,
+    cheats::Cheat,
+    cpu::{Cpu, Interrupt},
+    debug::{DebugPanels, DebugSnapshot, MEM_BLOCK_SIZE, MemorySpace},
+    disasm::disassemble,
+    mapper::{MapperEnum, MapperMemory},
+    palette::Palette,
     platform::FileDataSource,
+    ppu::{BLANK_PIXEL, FRAME_HEIGHT, FRAME_WIDTH, PixelTiles, RenderOptions},
+    ppu_events::PpuEventKind,
+    recording::{Recorder, RecordingFormat},
+    symbols::SymbolTable,
 };
-use egui::Color32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepKind {
+    Instruction,
+    Over,
+    Out,
+    Scanline,
+    Frame,
+    RunTo(u16),
+    Nmi,
+    Irq,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepTarget {
+    Instruction,
+    Return { pc: u16, sp: u8 },
+    Out { sp: u8 },
+    Scanline(u16),
+    Frame,
+    Address(u16),
+    Interrupt(Interrupt),
+}
 
 pub enum Command {
     Stop,
     Pause,
     Resume,
-    Step,
-    MemoryAddress(usize),
+    Step(StepKind),
+    MemoryAddress(MemorySpace, usize),
+    WriteMemory(MemorySpace, usize, u8),
+    DisassemblyAddress(Option<u16>),
+    SetDebugPanels(DebugPanels),
     DumpMemory,
+    LoadSymbols(Vec<PathBuf>),
+    SetCdlLogging(bool),
+    ResetCdl,
+    SaveCdl(PathBuf),
+    LoadCdl(PathBuf),
     SaveState,
     LoadState(FileDataSource),
     ControllerInputs(u16),
+    SetBreakpoints(Vec<Breakpoint>),
+    SetCheats(Vec<Cheat>),
+    SetWatches(Vec<u16>),
+    SetPpuEventLogging(bool),
+    SetRenderOptions(RenderOptions),
+    SetOamDecay(bool),
+    /// Sends what each pixel was drawn from along with the frames, for HD packs
+    SetRecordTiles(bool),
+    StartRecording(RecordingFormat, PathBuf, Box<Palette>),
+    StopRecording,
 }
 
 pub enum Event {
@@ -38,6 +88,32 @@ pub enum Event {
     Paused,
     Resumed,
     Crashed(String),
+    BreakpointHit(BreakHit),
+    RecordingStarted(PathBuf),
+    RecordingStopped,
+}
+
+/// A finished frame as handed to the frontend
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub number: u64,
+    pub screen: Vec<u16>,
+    /// Empty unless the PPU is recording tiles
+    pub tiles: Vec<PixelTiles>,
+    /// CHR RAM at the end of the frame, to tell its tiles apart by their contents.
+    /// Empty for CHR ROM or when tiles aren't recorded
+    pub chr_ram: Vec<u8>,
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self {
+            number: 0,
+            screen: vec![BLANK_PIXEL; FRAME_WIDTH * FRAME_HEIGHT],
+            tiles: Vec::new(),
+            chr_ram: Vec::new(),
+        }
+    }
 }
 
 #[derive(Savefile)]
@@ -54,21 +130,30 @@ pub struct Emu {
     pub bus: Bus,
     pub running: bool,
     pub paused: bool,
-    pub want_step: bool,
+    pub step: Option<StepTarget>,
     pub event_tx: mpsc::Sender<Event>,
     pub debug_tx: triple_buffer::Input<DebugSnapshot>,
-    pub frame_tx: triple_buffer::Input<Vec<Color32>>,
+    pub frame_tx: triple_buffer::Input<Frame>,
+    pub mem_space: MemorySpace,
     pub mem_chunk_addr: usize,
+    /// Addresses whose values go into every snapshot
+    pub watches: Vec<u16>,
+    /// Where the disassembly is centered, following PC when `None`
+    pub disasm_addr: Option<u16>,
+    /// Which of the costlier parts of the snapshots to build
+    pub debug_panels: DebugPanels,
     pub audio_producer: HeapProd<f32>,
     pub cycles_per_sample: f32,
     pub cycles_accumulator: f32,
+    /// Receives every finished frame and audio sample while recording
+    pub recorder: Option<Recorder>,
 }
 
 impl Emu {
     pub fn new(
         event_tx: mpsc::Sender<Event>,
         debug_tx: triple_buffer::Input<DebugSnapshot>,
-        frame_tx: triple_buffer::Input<Vec<Color32>>,
+        frame_tx: triple_buffer::Input<Frame>,
         enable_logging: bool,
         audio_producer: HeapProd<f32>,
         sample_rate: f32,
@@ -80,14 +165,19 @@ impl Emu {
             bus,
             running: true,
             paused: false,
-            want_step: false,
+            step: None,
             event_tx,
             debug_tx,
             frame_tx,
+            mem_space: MemorySpace::CpuBus,
             mem_chunk_addr: 0,
+            watches: Vec::new(),
+            disasm_addr: None,
+            debug_panels: Default::default(),
             audio_producer,
             cycles_per_sample: 1789773.0 / sample_rate,
             cycles_accumulator: 0.0,
+            recorder: None,
         }
     }
 
@@ -101,6 +191,7 @@ impl Emu {
         let cart = Cart::from_bytes(bytes)?;
         self.bus.insert_cartridge(cart);
         info!("Rom loaded from bytes");
+        self.bus.symbols = Default::default();
         self.bus.ppu.reset();
         self.cpu.reset(&mut self.bus);
         self.send_event(Event::Started);
@@ -111,6 +202,7 @@ impl Emu {
         let cart = Cart::insert(rom_path)?;
         self.bus.insert_cartridge(cart);
         info!("Rom \"{}\" loaded", rom_path);
+        self.bus.symbols = Arc::new(SymbolTable::discover(Path::new(rom_path)));
         self.bus.ppu.reset();
         self.cpu.reset(&mut self.bus);
         self.send_event(Event::Started);
@@ -137,8 +229,14 @@ impl Emu {
         self.cpu = state.cpu;
         self.bus.mem = state.bus.mem;
         self.bus.apu = state.bus.apu;
+        let render_options = self.bus.ppu.render_options;
+        let oam_decay = self.bus.ppu.oam_decay;
+        let record_tiles = self.bus.ppu.record_tiles;
         self.bus.ppu = state.bus.ppu;
-        self.bus.ppu.screen = vec![Color32::BLACK; 256 * 240];
+        self.bus.ppu.render_options = render_options;
+        self.bus.ppu.set_oam_decay(oam_decay);
+        self.bus.ppu.set_record_tiles(record_tiles);
+        self.bus.ppu.screen = vec![BLANK_PIXEL; FRAME_WIDTH * FRAME_HEIGHT];
         self.bus.controller1 = state.bus.controller1;
         self.bus.controller2 = state.bus.controller2;
         self.bus.open_bus = state.bus.open_bus;
@@ -151,26 +249,107 @@ impl Emu {
     }
 
     pub fn stop(&mut self) {
+        self.stop_recording();
         self.running = false;
         self.send_event(Event::Stopped);
     }
 
+    /// Recording starts with the next frame, replacing any recording in progress
+    pub fn start_recording(&mut self, format: RecordingFormat, path: PathBuf, palette: Palette) {
+        self.stop_recording();
+        let sample_rate = (1789773.0 / self.cycles_per_sample).round() as u32;
+        match Recorder::start(format, path, palette, sample_rate) {
+            Ok(recorder) => {
+                info!("Recording to \"{}\"", recorder.path().display());
+                self.send_event(Event::RecordingStarted(recorder.path().to_path_buf()));
+                self.recorder = Some(recorder);
+            }
+            Err(e) => error!("Couldn't start recording: {e}"),
+        }
+    }
+
+    pub fn stop_recording(&mut self) {
+        let Some(recorder) = self.recorder.take() else {
+            return;
+        };
+        let path = recorder.path().to_path_buf();
+        match recorder.finish() {
+            Ok(frames) => info!("Recorded {frames} frames to \"{}\"", path.display()),
+            Err(e) => error!("Couldn't finish recording \"{}\": {e}", path.display()),
+        }
+        self.send_event(Event::RecordingStopped);
+    }
+
     pub fn pause(&mut self) {
         self.paused = true;
+        self.step = None;
+        self.bus.breakpoints.resume_at(self.cpu.pc);
         self.send_event(Event::Paused);
     }
 
     pub fn resume(&mut self) {
         self.paused = false;
+        self.step = None;
         self.send_event(Event::Resumed);
     }
 
+    pub fn request_step(&mut self, kind: StepKind) {
+        const JSR: u8 = 0x20;
+
+        self.step = Some(match kind {
+            StepKind::Instruction => StepTarget::Instruction,
+            StepKind::Over => {
+                if self.cpu.pending_interrupt().is_none() && self.bus.read_only(self.cpu.pc) == JSR
+                {
+                    StepTarget::Return {
+                        pc: self.cpu.pc.wrapping_add(3),
+                        sp: self.cpu.sp,
+                    }
+                } else {
+                    StepTarget::Instruction
+                }
+            }
+            StepKind::Out => StepTarget::Out { sp: self.cpu.sp },
+            StepKind::Scanline => StepTarget::Scanline(self.bus.ppu.scanline),
+            StepKind::Frame => StepTarget::Frame,
+            StepKind::RunTo(addr) => StepTarget::Address(addr),
+            StepKind::Nmi => StepTarget::Interrupt(Interrupt::Nmi),
+            StepKind::Irq => StepTarget::Interrupt(Interrupt::Irq),
+        });
+    }
+
+    fn step_reached(
+        &self,
+        target: StepTarget,
+        returned: bool,
+        interrupt: Option<Interrupt>,
+        frame_done: bool,
+    ) -> bool {
+        match target {
+            StepTarget::Instruction => true,
+            StepTarget::Return { pc, sp } => self.cpu.pc == pc && self.cpu.sp == sp,
+            StepTarget::Out { sp } => returned && (self.cpu.sp.wrapping_sub(sp) as i8) > 0,
+            StepTarget::Scanline(scanline) => self.bus.ppu.scanline != scanline,
+            StepTarget::Frame => frame_done,
+            StepTarget::Address(addr) => self.cpu.pc == addr,
+            StepTarget::Interrupt(kind) => interrupt == Some(kind),
+        }
+    }
+
     pub fn step_frame(&mut self) -> bool {
-        if self.paused && !self.want_step {
+        const RTS: u8 = 0x60;
+        const RTI: u8 = 0x40;
+
+        if self.paused && self.step.is_none() {
             return false;
         }
         let mut frame_ready = false;
         loop {
+            let interrupt = self.cpu.pending_interrupt();
+            let returned = matches!(self.step, Some(StepTarget::Out { .. }))
+                && interrupt.is_none()
+                && matches!(self.bus.read_only(self.cpu.pc), RTS | RTI);
+
             let cycles_before = self.cpu.cycles;
             if let Err(e) = self.cpu.step(&mut self.bus) {
                 warn!("{e}. Emulator will be paused");
@@ -182,11 +361,21 @@ impl Emu {
                 let stall = self.bus.dma_stall;
                 self.bus.dma_stall = 0;
                 if let Some(cart) = self.bus.cart.as_mut() {
-                    self.bus.ppu.step(&mut cart.mapper, stall);
+                    self.bus
+                        .ppu
+                        .step(&mut cart.mapper, &mut self.bus.cdl, stall);
                 }
                 self.cpu.cycles += stall;
             }
 
+            if let Some((scanline, dot)) = self.bus.ppu.sprite_0_hit_at.take()
+                && self.bus.ppu_events.enabled
+            {
+                self.bus
+                    .ppu_events
+                    .push(PpuEventKind::Sprite0Hit, 0x2002, 0x40, scanline, dot);
+            }
+
             let cycles_delta = self.cpu.cycles - cycles_before;
 
             for _ in 0..cycles_delta {
@@ -196,43 +385,174 @@ impl Emu {
                 if self.cycles_accumulator >= self.cycles_per_sample {
                     let sample = self.bus.apu.output();
                     let _ = self.audio_producer.try_push(sample);
+                    if let Some(recorder) = &mut self.recorder {
+                        recorder.push_sample(sample);
+                    }
                     self.cycles_accumulator -= self.cycles_per_sample;
                 }
             }
 
             self.cpu.irq_pending = self.bus.apu.irq_occurred();
 
-            if self.bus.ppu.frame_ready {
+            if let Some(hit) = self.bus.breakpoints.take_hit() {
+                self.publish_frame();
+                self.publish_debug_snapshot();
+                self.pause();
+                self.send_event(Event::BreakpointHit(hit));
+                break;
+            }
+
+            let frame_done = self.bus.ppu.frame_ready;
+            if frame_done {
                 self.bus.ppu.frame_ready = false;
+                self.bus.apply_freezes();
+                self.bus.ppu_events.end_frame();
+                self.record_frame();
+                self.publish_frame();
+                self.publish_debug_snapshot();
+                frame_ready = true;
+            }
 
-                self.frame_tx
-                    .input_buffer_mut()
-                    .copy_from_slice(&self.bus.ppu.screen);
-                self.frame_tx.publish();
-
-                let memory_slice = self
-                    .bus
-                    .read_only_range(self.mem_chunk_addr as u16, MEM_BLOCK_SIZE as u16);
-                let stack_slice = self.bus.read_only_range(0x100, 0x100);
-
-                *self.debug_tx.input_buffer_mut() = DebugSnapshot::new(
-                    &self.cpu,
-                    &self.bus.ppu,
-                    &self.bus.apu,
-                    self.bus.cart.as_ref(),
-                    &memory_slice,
-                    &stack_slice,
-                );
-                self.debug_tx.publish();
+            if let Some(target) = self.step
+                && self.step_reached(target, returned, interrupt, frame_done)
+            {
+                self.step = None;
+                self.bus.breakpoints.resume_at(self.cpu.pc);
+                if !frame_done {
+                    self.publish_frame();
+                    self.publish_debug_snapshot();
+                }
+                break;
+            }
 
-                frame_ready = true;
+            if frame_done {
                 break;
             }
         }
-        self.want_step = false;
         frame_ready
     }
 
+    fn record_frame(&mut self) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+        if let Err(e) = recorder.push_frame(&self.bus.ppu.screen) {
+            error!("Recording stopped: {e}");
+            self.stop_recording();
+        }
+    }
+
+    fn publish_frame(&mut self) {
+        let frame = self.frame_tx.input_buffer_mut();
+        Self::copy_frame(&self.bus, frame);
+        self.frame_tx.publish();
+    }
+
+    /// Copies the finished frame into `frame`, reusing its buffers
+    pub fn copy_frame(bus: &Bus, frame: &mut Frame) {
+        let ppu = &bus.ppu;
+        frame.number = ppu.frame;
+        frame.screen.copy_from_slice(&ppu.screen);
+        frame.tiles.clear();
+        frame.tiles.extend_from_slice(&ppu.tiles);
+        frame.chr_ram.clear();
+        if let Some(cart) = &bus.cart
+            && ppu.record_tiles
+            && cart.header.chr_rom_size == 0
+        {
+            frame
+                .chr_ram
+                .extend_from_slice(cart.mapper.memory(MapperMemory::Chr));
+        }
+    }
+
+    pub fn publish_debug_snapshot(&mut self) {
+        let memory_slice: Vec<u8> = (0..MEM_BLOCK_SIZE)
+            .map(|i| self.bus.peek(self.mem_space, self.mem_chunk_addr + i))
+            .collect();
+        let stack_slice = self.bus.read_only_range(0x100, 0x100);
+
+        let mut snapshot = DebugSnapshot::new(
+            &self.cpu,
+            &self.bus.ppu,
+            &self.bus.apu,
+            self.bus.cart.as_ref(),
+            &memory_slice,
+            &stack_slice,
+            &self.bus.breakpoints,
+        );
+        snapshot.mem_space = self.mem_space;
+        snapshot.mem_chunk_addr = self.mem_chunk_addr;
+        snapshot.mem_space_size = self.bus.space_size(self.mem_space);
+        if self.debug_panels.ram {
+            snapshot.ram = self.bus.mem;
+            snapshot.prg_ram = self.bus.memory(MapperMemory::PrgRam).to_vec();
+        }
+        if let Some(cart) = &self.bus.cart {
+            if self.debug_panels.chr {
+                snapshot.chr = (0..0x2000).map(|addr| cart.mapper.read_chr(addr)).collect();
+            }
+            if self.debug_panels.nametables {
+                snapshot.nametables = (0x2000..0x3000)
+                    .map(|addr| self.bus.ppu.read_vram(addr, &cart.mapper))
+                    .collect();
+            }
+        }
+        snapshot.watch_values = self
+            .watches
+            .iter()
+            .map(|&addr| {
+                let bytes =
+                    std::array::from_fn(|i| self.bus.read_only(addr.wrapping_add(i as u16)));
+                (addr, bytes)
+            })
+            .collect();
+        snapshot.symbols = self.bus.symbols.clone();
+        snapshot.ppu_events_enabled = self.bus.ppu_events.enabled;
+        snapshot.ppu_events = self
+            .bus
+            .ppu_events
+            .events(self.bus.ppu.scanline, self.bus.ppu.dot);
+        snapshot.cdl_enabled = self.bus.cdl.enabled;
+        if self.debug_panels.cdl_stats {
+            snapshot.cdl_stats = self.bus.cdl.stats();
+        }
+        if let Some(cart) = &self.bus.cart {
+            for (i, flags) in snapshot.cdl_chunk.iter_mut().enumerate() {
+                let addr = self.mem_chunk_addr + i;
+                let offset = match self.mem_space {
+                    MemorySpace::CpuBus => cart.mapper.prg_rom_offset(addr as u16),
+                    MemorySpace::PrgRom => Some(addr),
+                    _ => None,
+                };
+                *flags = offset
+                    .map(|offset| self.bus.cdl.prg_flags(offset))
+                    .unwrap_or(PrgFlags::empty());
+            }
+        }
+        if self.debug_panels.disassembly {
+            snapshot.disasm = disassemble(
+                &self.cpu,
+                &self.bus,
+                self.disasm_addr.unwrap_or(self.cpu.pc),
+            );
+        }
+        *self.debug_tx.input_buffer_mut() = snapshot;
+        self.debug_tx.publish();
+    }
+
+    /// Replaces the current symbols with the ones in `paths`
+    pub fn load_symbols(&mut self, paths: &[PathBuf]) {
+        let mut symbols = SymbolTable::default();
+        for path in paths {
+            match symbols.load(path) {
+                Ok(()) => info!("Symbols loaded from \"{}\"", path.display()),
+                Err(e) => error!("Failed to load symbols from \"{}\": {e}", path.display()),
+            }
+        }
+        self.bus.symbols = Arc::new(symbols);
+    }
+
     pub fn dump_memory(&mut self) {
         #[cfg(not(target_arch = "wasm32"))]
         {
@@ -248,3 +568,234 @@ impl Emu {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ringbuf::{HeapRb, traits::Split};
+
+    use super::*;
+
+    const RESET: u16 = 0x8000;
+    const NMI: u16 = 0x8080;
+    /// How long a test ROM gets to report a result, 30 seconds
+    const TEST_ROM_FRAMES: u32 = 30 * 60;
+
+    /// An NROM cart with `code` at $8000 and `nmi` at $8080, paused at reset
+    fn emu(code: &[u8], nmi: &[u8]) -> Emu {
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 2, 1, 0, 0];
+        rom.resize(16, 0);
+        let mut prg = vec![0xEA; 0x8000];
+        prg[..code.len()].copy_from_slice(code);
+        let nmi_start = (NMI - RESET) as usize;
+        prg[nmi_start..nmi_start + nmi.len()].copy_from_slice(nmi);
+        for (vector, addr) in [(0xFFFA, NMI), (0xFFFC, RESET), (0xFFFE, NMI)] {
+            let offset = vector - RESET as usize;
+            prg[offset..offset + 2].copy_from_slice(&addr.to_le_bytes());
+        }
+        rom.extend(prg);
+        rom.resize(rom.len() + 0x2000, 0);
+        let mut emu = emu_with_rom(rom);
+        emu.paused = true;
+        emu
+    }
+
+    fn emu_with_rom(rom: Vec<u8>) -> Emu {
+        let (event_tx, _) = mpsc::channel();
+        let (debug_tx, _) = triple_buffer::triple_buffer(&DebugSnapshot::default());
+        let (frame_tx, _) = triple_buffer::triple_buffer(&Frame::default());
+        let (audio_producer, _) = HeapRb::<f32>::new(4096).split();
+        let mut emu = Emu::new(
+            event_tx,
+            debug_tx,
+            frame_tx,
+            false,
+            audio_producer,
+            44_100.0,
+        );
+        emu.load_rom_from_bytes(rom).unwrap();
+        emu
+    }
+
+    /// Runs until the step finishes, giving up after a few frames
+    fn step(emu: &mut Emu, kind: StepKind) {
+        emu.request_step(kind);
+        for _ in 0..5 {
+            emu.step_frame();
+            if emu.step.is_none() {
+                return;
+            }
+        }
+        panic!("{kind:?} didn't finish");
+    }
+
+    #[test]
+    fn stepping_through_nested_subroutines() {
+        let mut emu = emu(
+            &[
+                0x20, 0x10, 0x80, // $8000: JSR $8010
+                0x4C, 0x03, 0x80, // $8003: JMP $8003
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, //
+                0x20, 0x20, 0x80, // $8010: JSR $8020
+                0x60, // $8013: RTS
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,    //
+                0xEA, // $8020: NOP
+                0x60, // $8021: RTS
+            ],
+            &[0x40],
+        );
+        let sp = emu.cpu.sp;
+
+        // Over the outer call, and everything it calls in turn
+        step(&mut emu, StepKind::Over);
+        assert_eq!((emu.cpu.pc, emu.cpu.sp), (0x8003, sp));
+
+        let mut emu = emu_at_inner(emu);
+        assert_eq!((emu.cpu.pc, emu.cpu.sp), (0x8020, sp.wrapping_sub(4)));
+        // Out of the inner call only
+        step(&mut emu, StepKind::Out);
+        assert_eq!((emu.cpu.pc, emu.cpu.sp), (0x8013, sp.wrapping_sub(2)));
+        step(&mut emu, StepKind::Out);
+        assert_eq!((emu.cpu.pc, emu.cpu.sp), (0x8003, sp));
+
+        // Over a call from inside another one
+        emu.cpu.reset(&mut emu.bus);
+        step(&mut emu, StepKind::Instruction);
+        assert_eq!(emu.cpu.pc, 0x8010);
+        step(&mut emu, StepKind::Over);
+        assert_eq!(emu.cpu.pc, 0x8013);
+        // Anything but a JSR is a single instruction
+        step(&mut emu, StepKind::Over);
+        assert_eq!(emu.cpu.pc, 0x8003);
+    }
+
+    /// Resets and steps into both calls
+    fn emu_at_inner(mut emu: Emu) -> Emu {
+        emu.cpu.reset(&mut emu.bus);
+        step(&mut emu, StepKind::Instruction);
+        step(&mut emu, StepKind::Instruction);
+        emu
+    }
+
+    #[test]
+    fn nmi_during_step_over() {
+        let mut emu = emu(
+            &[
+                0xA9, 0x80, // $8000: LDA #$80
+                0x8D, 0x00, 0x20, // $8002: STA $2000
+                0x20, 0x10, 0x80, // $8005: JSR $8010
+                0x4C, 0x08, 0x80, // $8008: JMP $8008
+                0, 0, 0, 0, 0, //
+                0xA5, 0x10, // $8010: LDA $10
+                0xF0, 0xFC, // $8012: BEQ $8010
+                0x60, // $8014: RTS
+            ],
+            &[
+                0xE6, 0x10, // INC $10
+                0x40, // RTI
+            ],
+        );
+        step(&mut emu, StepKind::Instruction);
+        step(&mut emu, StepKind::Instruction);
+        let sp = emu.cpu.sp;
+
+        // The subroutine spins until the NMI handler runs, which pushes and
+        // pops its own frame without ending the step
+        step(&mut emu, StepKind::Over);
+        assert_eq!(emu.bus.mem[0x10], 1);
+        assert_eq!((emu.cpu.pc, emu.cpu.sp), (0x8008, sp));
+    }
+
+    #[test]
+    fn run_to_an_address_that_is_never_reached() {
+        let mut emu = emu(&[0x4C, 0x00, 0x80], &[0x40]);
+        emu.request_step(StepKind::RunTo(0x9000));
+        for _ in 0..3 {
+            // Frames keep coming while the step waits
+            assert!(emu.step_frame());
+            assert_eq!(emu.step, Some(StepTarget::Address(0x9000)));
+            assert_eq!(emu.cpu.pc, 0x8000);
+        }
+        assert!(emu.paused);
+
+        // Pausing again gives up on it
+        emu.pause();
+        assert_eq!(emu.step, None);
+        assert!(!emu.step_frame());
+    }
+
+    /// Runs every ROM under `$NESSIE_TEST_ROMS` that reports through blargg's
+    /// $6000 protocol, such as `oam_read`. The ROMs don't ship with the source,
+    /// so this only runs with `--ignored`. The cart needs RAM at $6000 for the
+    /// status to land anywhere, NROM builds time out since mapper 0 has none
+    #[test]
+    #[ignore]
+    fn test_roms() {
+        let dir = std::env::var_os("NESSIE_TEST_ROMS")
+            .expect("NESSIE_TEST_ROMS should point at a folder of test ROMs");
+        let mut paths = Vec::new();
+        find_roms(Path::new(&dir), &mut paths);
+        paths.sort();
+        assert!(!paths.is_empty(), "No ROMs in {}", dir.display());
+        let failures: Vec<String> = paths
+            .iter()
+            .filter_map(|path| {
+                run_test_rom(path)
+                    .err()
+                    .map(|e| format!("{}: {e}", path.display()))
+            })
+            .collect();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    fn find_roms(dir: &Path, paths: &mut Vec<PathBuf>) {
+        for entry in std::fs::read_dir(dir).unwrap().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                find_roms(&path, paths);
+            } else if path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("nes"))
+            {
+                paths.push(path);
+            }
+        }
+    }
+
+    /// $6000 holds the status once $6001-$6003 read DE B0 61: $80 while
+    /// running, $81 when it wants a reset, then the result code with 0 for a
+    /// pass and a message from $6004
+    fn run_test_rom(path: &Path) -> Result<()> {
+        let mut emu = emu_with_rom(std::fs::read(path)?);
+        let mut reset_at = None;
+        for frame in 0..TEST_ROM_FRAMES {
+            emu.step_frame();
+            if emu.paused {
+                anyhow::bail!("CPU stopped at ${:04X}", emu.cpu.pc);
+            }
+            let read = |addr| emu.bus.read_only(addr);
+            if [0x6001, 0x6002, 0x6003].map(read) != [0xDE, 0xB0, 0x61] {
+                continue;
+            }
+            match read(0x6000) {
+                0x80 => {}
+                0x81 => {
+                    // The reset has to come at least 100 ms later
+                    if frame >= *reset_at.get_or_insert(frame + 6) {
+                        emu.cpu.reset(&mut emu.bus);
+                        reset_at = None;
+                    }
+                }
+                0x00 => return Ok(()),
+                code => {
+                    let message: Vec<u8> =
+                        (0x6004..0x7000).map(read).take_while(|&c| c != 0).collect();
+                    anyhow::bail!(
+                        "Failed with code {code}: {}",
+                        String::from_utf8_lossy(&message).trim()
+                    );
+                }
+            }
+        }
+        anyhow::bail!("No result after {TEST_ROM_FRAMES} frames")
+    }
+}
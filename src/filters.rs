@@ -0,0 +1,117 @@
+use egui::Color32;
+
+use crate::ntsc::{NTSC_WIDTH, NtscFilter};
+use crate::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+use crate::scale;
+use crate::settings::{VideoFilter, VideoSettings};
+
+/// Masks need a few output pixels per frame pixel to draw their pattern in
+const MASK_MIN_SCALE: usize = 3;
+
+/// Turns a frame into the screen texture: the selected filter or scaler, then
+/// the CRT masks on top
+#[derive(Default)]
+pub struct FilterPipeline {
+    filter: VideoFilter,
+    scanlines: f32,
+    aperture_grille: f32,
+    ntsc: NtscFilter,
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color32>,
+}
+
+impl FilterPipeline {
+    pub fn configure(&mut self, video: &VideoSettings) {
+        self.filter = video.filter;
+        self.scanlines = video.scanlines.clamp(0.0, 1.0);
+        self.aperture_grille = video.aperture_grille.clamp(0.0, 1.0);
+        self.ntsc.set_settings(video.ntsc);
+    }
+
+    fn masked(&self) -> bool {
+        self.scanlines > 0.0 || self.aperture_grille > 0.0
+    }
+
+    /// Filters a frame, given both as 9-bit pixels and converted through the palette
+    pub fn apply(&mut self, frame: &[u16], rgb: &[Color32]) {
+        let (w, h) = (FRAME_WIDTH, FRAME_HEIGHT);
+        (self.pixels, self.width, self.height) = match self.filter {
+            VideoFilter::Nearest if self.masked() => (
+                scale::nearest(rgb, w, h, MASK_MIN_SCALE),
+                w * MASK_MIN_SCALE,
+                h * MASK_MIN_SCALE,
+            ),
+            VideoFilter::Nearest => (rgb.to_vec(), w, h),
+            VideoFilter::Ntsc => {
+                self.ntsc.apply(frame);
+                if self.masked() {
+                    // Already wide enough, only the lines need room for scanlines
+                    let pixels = self
+                        .ntsc
+                        .pixels
+                        .chunks_exact(NTSC_WIDTH)
+                        .flat_map(|row| row.iter().chain(row))
+                        .copied()
+                        .collect();
+                    (pixels, NTSC_WIDTH, h * 2)
+                } else {
+                    (self.ntsc.pixels.clone(), NTSC_WIDTH, h)
+                }
+            }
+            VideoFilter::Scale2x => (scale::scale2x(rgb, w, h), w * 2, h * 2),
+            VideoFilter::Scale3x => (scale::scale3x(rgb, w, h), w * 3, h * 3),
+            VideoFilter::Hq2x => (scale::hqx(rgb, w, h, 2), w * 2, h * 2),
+            VideoFilter::Hq3x => (scale::hqx(rgb, w, h, 3), w * 3, h * 3),
+            VideoFilter::Hq4x => (scale::hqx(rgb, w, h, 4), w * 4, h * 4),
+            VideoFilter::Xbrz2x => (scale::xbrz(rgb, w, h, 2), w * 2, h * 2),
+            VideoFilter::Xbrz3x => (scale::xbrz(rgb, w, h, 3), w * 3, h * 3),
+            VideoFilter::Xbrz4x => (scale::xbrz(rgb, w, h, 4), w * 4, h * 4),
+        };
+        if self.scanlines > 0.0 {
+            self.apply_scanlines();
+        }
+        if self.aperture_grille > 0.0 {
+            self.apply_aperture_grille();
+        }
+    }
+
+    /// Darkens the lower part of every frame line
+    fn apply_scanlines(&mut self) {
+        let lines = self.height / FRAME_HEIGHT;
+        let dim = 1.0 - self.scanlines;
+        let keep = |value: u8| (value as f32 * dim) as u8;
+        for (y, row) in self.pixels.chunks_exact_mut(self.width).enumerate() {
+            if y % lines >= lines.div_ceil(2) {
+                for pixel in row {
+                    *pixel = Color32::from_rgb(keep(pixel.r()), keep(pixel.g()), keep(pixel.b()));
+                }
+            }
+        }
+    }
+
+    /// Vertical red, green and blue stripes, each column dimming the other two channels
+    fn apply_aperture_grille(&mut self) {
+        let dim = 1.0 - self.aperture_grille;
+        let keep = |value: u8| (value as f32 * dim) as u8;
+        for row in self.pixels.chunks_exact_mut(self.width) {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let [r, g, b, _] = pixel.to_array();
+                *pixel = match x % 3 {
+                    0 => Color32::from_rgb(r, keep(g), keep(b)),
+                    1 => Color32::from_rgb(keep(r), g, keep(b)),
+                    _ => Color32::from_rgb(keep(r), keep(g), b),
+                };
+            }
+        }
+    }
+
+    /// Scaled up output is smoothed when drawn, the 1x output stays sharp
+    pub fn texture_options(&self) -> egui::TextureOptions {
+        if self.filter == VideoFilter::Nearest && !self.masked() {
+            egui::TextureOptions::NEAREST
+        } else {
+            egui::TextureOptions::LINEAR
+        }
+    }
+}
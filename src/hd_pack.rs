@@ -0,0 +1,681 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use egui::Color32;
+use log::{info, warn};
+
+use crate::emu::Frame;
+use crate::palette::Palette;
+use crate::ppu::{FRAME_HEIGHT, FRAME_WIDTH, TileRef};
+
+/// The file describing a pack, at the root of its folder
+const DEFINITION_FILE: &str = "hires.txt";
+const MAX_SCALE: usize = 10;
+
+/// How a pack names a tile: by where it sits in CHR ROM, or by its 16 bytes for CHR RAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum TileId {
+    Index(u32),
+    Data([u8; 16]),
+}
+
+impl TileId {
+    fn parse(s: &str) -> Result<Self> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("Invalid tile \"{s}\"");
+        }
+        if s.len() == 32 {
+            let mut data = [0; 16];
+            for (i, byte) in data.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16)
+                    .with_context(|| format!("Invalid tile data \"{s}\""))?;
+            }
+            Ok(TileId::Data(data))
+        } else {
+            u32::from_str_radix(s, 16)
+                .map(TileId::Index)
+                .with_context(|| format!("Invalid tile index \"{s}\""))
+        }
+    }
+
+    fn of(tile: &TileRef, frame: &Frame) -> Self {
+        if frame.chr_ram.is_empty() {
+            return TileId::Index(tile.chr_offset / 16);
+        }
+        let offset = tile.chr_offset as usize;
+        let mut data = [0; 16];
+        if let Some(bytes) = frame.chr_ram.get(offset..offset + 16) {
+            data.copy_from_slice(bytes);
+        }
+        TileId::Data(data)
+    }
+}
+
+/// Backdrop and 3 colors as palette RAM values, written as 8 hex digits
+fn parse_palette(s: &str) -> Result<[u8; 4]> {
+    u32::from_str_radix(s, 16)
+        .map(u32::to_be_bytes)
+        .with_context(|| format!("Invalid palette \"{s}\""))
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T> {
+    s.trim()
+        .parse()
+        .ok()
+        .with_context(|| format!("Invalid number \"{s}\""))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Condition {
+    HorizontalMirror,
+    VerticalMirror,
+    BehindBackground,
+    /// A background tile covers the pixel at `x`, `y`
+    TileAtPosition {
+        x: i32,
+        y: i32,
+        tile: TileId,
+        palette: [u8; 4],
+    },
+    SpriteAtPosition {
+        x: i32,
+        y: i32,
+        tile: TileId,
+        palette: [u8; 4],
+    },
+    /// Like [`Condition::TileAtPosition`], relative to the tile being replaced
+    TileNearby {
+        x: i32,
+        y: i32,
+        tile: TileId,
+        palette: [u8; 4],
+    },
+    SpriteNearby {
+        x: i32,
+        y: i32,
+        tile: TileId,
+        palette: [u8; 4],
+    },
+    /// The frame number modulo `divisor` is at least `compare`
+    FrameRange {
+        divisor: u64,
+        compare: u64,
+    },
+    /// Memory checks need CPU memory the frontend doesn't get, and newer
+    /// condition types aren't known at all, so these never match
+    Unsupported,
+}
+
+impl Condition {
+    fn parse(kind: &str, args: &[&str]) -> Result<Self> {
+        let position = || -> Result<(i32, i32, TileId, [u8; 4])> {
+            let [x, y, tile, palette] = args else {
+                bail!("\"{kind}\" takes x, y, tile and palette");
+            };
+            Ok((
+                parse_number(x)?,
+                parse_number(y)?,
+                TileId::parse(tile)?,
+                parse_palette(palette)?,
+            ))
+        };
+        Ok(match kind {
+            "hmirror" => Condition::HorizontalMirror,
+            "vmirror" => Condition::VerticalMirror,
+            "bgpriority" => Condition::BehindBackground,
+            "tileAtPosition" => {
+                let (x, y, tile, palette) = position()?;
+                Condition::TileAtPosition {
+                    x,
+                    y,
+                    tile,
+                    palette,
+                }
+            }
+            "spriteAtPosition" => {
+                let (x, y, tile, palette) = position()?;
+                Condition::SpriteAtPosition {
+                    x,
+                    y,
+                    tile,
+                    palette,
+                }
+            }
+            "tileNearby" => {
+                let (x, y, tile, palette) = position()?;
+                Condition::TileNearby {
+                    x,
+                    y,
+                    tile,
+                    palette,
+                }
+            }
+            "spriteNearby" => {
+                let (x, y, tile, palette) = position()?;
+                Condition::SpriteNearby {
+                    x,
+                    y,
+                    tile,
+                    palette,
+                }
+            }
+            "frameRange" => {
+                let [divisor, compare] = args else {
+                    bail!("\"frameRange\" takes a divisor and a value to compare with");
+                };
+                let divisor: u64 = parse_number(divisor)?;
+                if divisor == 0 {
+                    bail!("\"frameRange\" divisor can't be 0");
+                }
+                Condition::FrameRange {
+                    divisor,
+                    compare: parse_number(compare)?,
+                }
+            }
+            _ => Condition::Unsupported,
+        })
+    }
+}
+
+/// The tile being replaced and where on screen it is
+struct Placed<'a> {
+    frame: &'a Frame,
+    tile: &'a TileRef,
+    /// Screen position of the tile's top left corner
+    x: i32,
+    y: i32,
+}
+
+impl Placed<'_> {
+    fn check(&self, condition: &Condition) -> bool {
+        let tiles_at = |x: i32, y: i32| {
+            let inside =
+                (0..FRAME_WIDTH as i32).contains(&x) && (0..FRAME_HEIGHT as i32).contains(&y);
+            inside.then(|| &self.frame.tiles[y as usize * FRAME_WIDTH + x as usize])
+        };
+        let matches = |tile: Option<TileRef>, id: &TileId, palette: &[u8; 4]| {
+            tile.is_some_and(|tile| {
+                tile.palette == *palette && TileId::of(&tile, self.frame) == *id
+            })
+        };
+        match *condition {
+            Condition::HorizontalMirror => self.tile.flip_h,
+            Condition::VerticalMirror => self.tile.flip_v,
+            Condition::BehindBackground => self.tile.behind_bg,
+            Condition::TileAtPosition {
+                x,
+                y,
+                tile,
+                palette,
+            } => matches(tiles_at(x, y).and_then(|p| p.bg), &tile, &palette),
+            Condition::SpriteAtPosition {
+                x,
+                y,
+                tile,
+                palette,
+            } => matches(tiles_at(x, y).and_then(|p| p.sprite), &tile, &palette),
+            Condition::TileNearby {
+                x,
+                y,
+                tile,
+                palette,
+            } => matches(
+                tiles_at(self.x + x, self.y + y).and_then(|p| p.bg),
+                &tile,
+                &palette,
+            ),
+            Condition::SpriteNearby {
+                x,
+                y,
+                tile,
+                palette,
+            } => matches(
+                tiles_at(self.x + x, self.y + y).and_then(|p| p.sprite),
+                &tile,
+                &palette,
+            ),
+            Condition::FrameRange { divisor, compare } => self.frame.number % divisor >= compare,
+            Condition::Unsupported => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Replacement {
+    image: usize,
+    /// Top left corner of the replacement in the image
+    x: usize,
+    y: usize,
+    brightness: f32,
+    /// Indices into the pack's conditions, and whether each one is negated
+    conditions: Vec<(usize, bool)>,
+}
+
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color32>,
+}
+
+impl Image {
+    fn load(path: &Path) -> Result<Self> {
+        let image = image::open(path)
+            .with_context(|| format!("Couldn't load \"{}\"", path.display()))?
+            .to_rgba8();
+        let (width, height) = image.dimensions();
+        let pixels = image
+            .pixels()
+            .map(|p| Color32::from_rgba_unmultiplied(p[0], p[1], p[2], p[3]))
+            .collect();
+        Ok(Self {
+            width: width as usize,
+            height: height as usize,
+            pixels,
+        })
+    }
+
+    fn get(&self, x: usize, y: usize) -> Color32 {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x]
+        } else {
+            Color32::TRANSPARENT
+        }
+    }
+}
+
+/// Replacement graphics in the format of Mesen's HD packs: a folder with a
+/// `hires.txt` mapping tile and palette combinations to parts of PNG images
+/// drawn at a multiple of the NES resolution
+pub struct HdPack {
+    path: PathBuf,
+    scale: usize,
+    images: Vec<Image>,
+    conditions: Vec<Condition>,
+    /// Candidates for each tile and palette, the first one whose conditions hold wins
+    tiles: HashMap<(TileId, [u8; 4]), Vec<Replacement>>,
+    /// Used for a tile with any palette when none was given for its own
+    default_tiles: HashMap<TileId, Vec<Replacement>>,
+}
+
+impl HdPack {
+    pub fn load(path: &Path) -> Result<Self> {
+        let definition = path.join(DEFINITION_FILE);
+        let text = fs::read_to_string(&definition)
+            .with_context(|| format!("Couldn't read \"{}\"", definition.display()))?;
+        let mut pack = Self {
+            path: path.to_path_buf(),
+            scale: 1,
+            images: Vec::new(),
+            conditions: Vec::new(),
+            tiles: HashMap::new(),
+            default_tiles: HashMap::new(),
+        };
+        let mut condition_names = HashMap::new();
+        let mut ignored = 0;
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            pack.parse_line(line, &mut condition_names, &mut ignored)
+                .with_context(|| format!("{DEFINITION_FILE} line {}", number + 1))?;
+        }
+
+        if ignored > 0 {
+            warn!("{ignored} lines of the HD pack use features that aren't supported");
+        }
+        info!(
+            "HD pack loaded from \"{}\": {} tiles at {}x",
+            path.display(),
+            pack.tiles
+                .values()
+                .chain(pack.default_tiles.values())
+                .map(Vec::len)
+                .sum::<usize>(),
+            pack.scale
+        );
+        Ok(pack)
+    }
+
+    fn parse_line(
+        &mut self,
+        line: &str,
+        condition_names: &mut HashMap<String, usize>,
+        ignored: &mut usize,
+    ) -> Result<()> {
+        let (conditions, line) = match line.strip_prefix('[') {
+            Some(rest) => {
+                let (names, line) = rest.split_once(']').context("Unclosed condition list")?;
+                let conditions = names
+                    .split('&')
+                    .map(|name| {
+                        let name = name.trim();
+                        let (name, negated) = match name.strip_prefix('!') {
+                            Some(name) => (name, true),
+                            None => (name, false),
+                        };
+                        condition_names
+                            .get(name)
+                            .map(|&index| (index, negated))
+                            .with_context(|| format!("Unknown condition \"{name}\""))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                (conditions, line)
+            }
+            None => (Vec::new(), line),
+        };
+
+        let Some((tag, value)) = line.strip_prefix('<').and_then(|line| line.split_once('>'))
+        else {
+            bail!("Expected a <tag>");
+        };
+        let value = value.trim();
+        let fields: Vec<&str> = value.split(',').map(str::trim).collect();
+
+        match tag {
+            "ver" => {}
+            "scale" => {
+                let scale: usize = parse_number(value)?;
+                if !(1..=MAX_SCALE).contains(&scale) {
+                    bail!("Scale must be between 1 and {MAX_SCALE}");
+                }
+                self.scale = scale;
+            }
+            "img" => {
+                let image = Image::load(&self.path.join(value))?;
+                self.images.push(image);
+            }
+            "condition" => {
+                let [name, kind, args @ ..] = fields.as_slice() else {
+                    bail!("A condition needs a name and a type");
+                };
+                let condition = Condition::parse(kind, args)?;
+                if matches!(condition, Condition::Unsupported) {
+                    *ignored += 1;
+                }
+                self.conditions.push(condition);
+                condition_names.insert(name.to_string(), self.conditions.len() - 1);
+            }
+            "tile" => self.parse_tile(&fields, conditions)?,
+            _ => *ignored += 1,
+        }
+        Ok(())
+    }
+
+    /// `image,tile,palette,x,y,brightness,default`, the last two being optional
+    fn parse_tile(&mut self, fields: &[&str], conditions: Vec<(usize, bool)>) -> Result<()> {
+        let [image, tile, palette, x, y, rest @ ..] = fields else {
+            bail!("A tile needs an image, tile, palette and position");
+        };
+        let image: usize = parse_number(image)?;
+        if image >= self.images.len() {
+            bail!("There is no image {image}");
+        }
+        let replacement = Replacement {
+            image,
+            x: parse_number(x)?,
+            y: parse_number(y)?,
+            brightness: rest.first().map_or(Ok(1.0), |b| parse_number(b))?,
+            conditions,
+        };
+        let tile = TileId::parse(tile)?;
+        let is_default = rest.get(1).is_some_and(|d| d.eq_ignore_ascii_case("y"));
+
+        let add = |candidates: &mut Vec<Replacement>| {
+            // Unconditional replacements are only fallbacks for the conditional ones
+            let index = if replacement.conditions.is_empty() {
+                candidates.len()
+            } else {
+                candidates
+                    .iter()
+                    .position(|r| r.conditions.is_empty())
+                    .unwrap_or(candidates.len())
+            };
+            candidates.insert(index, replacement.clone());
+        };
+        add(self
+            .tiles
+            .entry((tile, parse_palette(palette)?))
+            .or_default());
+        if is_default {
+            add(self.default_tiles.entry(tile).or_default());
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    fn find(&self, frame: &Frame, tile: &TileRef, x: usize, y: usize) -> Option<&Replacement> {
+        let id = TileId::of(tile, frame);
+        let candidates = self
+            .tiles
+            .get(&(id, tile.palette))
+            .or_else(|| self.default_tiles.get(&id))?;
+        let placed = Placed {
+            frame,
+            tile,
+            x: x as i32 - if tile.flip_h { 7 - tile.x } else { tile.x } as i32,
+            y: y as i32 - if tile.flip_v { 7 - tile.y } else { tile.y } as i32,
+        };
+        candidates.iter().find(|r| {
+            r.conditions
+                .iter()
+                .all(|&(index, negated)| placed.check(&self.conditions[index]) != negated)
+        })
+    }
+
+    /// Draws `frame` at the pack's scale, which needs the tiles the PPU recorded.
+    /// Layers are stacked as on hardware, tiles without a replacement keep their NES colors
+    pub fn compose(&self, frame: &Frame, palette: &Palette, out: &mut Vec<Color32>) {
+        let scale = self.scale;
+        let width = FRAME_WIDTH * scale;
+        out.clear();
+        out.resize(width * FRAME_HEIGHT * scale, Color32::BLACK);
+
+        for y in 0..FRAME_HEIGHT {
+            let mut last_bg = None;
+            let mut last_sprite = None;
+            for x in 0..FRAME_WIDTH {
+                let pixel = &frame.tiles[y * FRAME_WIDTH + x];
+                let emphasis = pixel.backdrop & 0x1C0;
+                let block = (y * width + x) * scale;
+                self.fill(out, block, width, palette.emphasized(pixel.backdrop));
+
+                let sprite = pixel.sprite.as_ref();
+                let layers = [
+                    sprite.filter(|s| s.behind_bg).map(|s| (s, true)),
+                    pixel.bg.as_ref().map(|bg| (bg, false)),
+                    sprite.filter(|s| !s.behind_bg).map(|s| (s, true)),
+                ];
+                for (tile, is_sprite) in layers.into_iter().flatten() {
+                    let last = if is_sprite {
+                        &mut last_sprite
+                    } else {
+                        &mut last_bg
+                    };
+                    match self.lookup(last, frame, tile, x, y) {
+                        Some(replacement) => self.draw(replacement, tile, out, block, width),
+                        None if tile.color != 0 => {
+                            let index = emphasis | tile.palette[tile.color as usize] as u16;
+                            self.fill(out, block, width, palette.emphasized(index));
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// [`Self::find`], reusing the last result while pixels come from the same tile
+    fn lookup<'a>(
+        &'a self,
+        last: &mut Option<(TileRef, i32, Option<&'a Replacement>)>,
+        frame: &Frame,
+        tile: &TileRef,
+        x: usize,
+        y: usize,
+    ) -> Option<&'a Replacement> {
+        // The same tile instance has only its column moving along with `x`
+        let key = TileRef {
+            x: 0,
+            color: 0,
+            ..*tile
+        };
+        let origin = x as i32 - if tile.flip_h { 7 - tile.x } else { tile.x } as i32;
+        if let Some((last_key, last_origin, found)) = *last
+            && last_key == key
+            && last_origin == origin
+        {
+            return found;
+        }
+        let found = self.find(frame, tile, x, y);
+        *last = Some((key, origin, found));
+        found
+    }
+
+    fn fill(&self, out: &mut [Color32], block: usize, width: usize, color: Color32) {
+        for row in 0..self.scale {
+            let start = block + row * width;
+            out[start..start + self.scale].fill(color);
+        }
+    }
+
+    /// Blends the part of `replacement` covering the pixel of `tile` over `out`
+    fn draw(
+        &self,
+        replacement: &Replacement,
+        tile: &TileRef,
+        out: &mut [Color32],
+        block: usize,
+        width: usize,
+    ) {
+        let scale = self.scale;
+        let image = &self.images[replacement.image];
+        for row in 0..scale {
+            for col in 0..scale {
+                let sub_x = if tile.flip_h { scale - 1 - col } else { col };
+                let sub_y = if tile.flip_v { scale - 1 - row } else { row };
+                let color = image.get(
+                    replacement.x + tile.x as usize * scale + sub_x,
+                    replacement.y + tile.y as usize * scale + sub_y,
+                );
+                let dst = &mut out[block + row * width + col];
+                *dst = blend(*dst, color, replacement.brightness);
+            }
+        }
+    }
+}
+
+/// `src` over `dst`, with `src` premultiplied and its color scaled by `brightness`
+fn blend(dst: Color32, src: Color32, brightness: f32) -> Color32 {
+    let alpha = src.a() as f32 / 255.0;
+    let channel = |d: u8, s: u8| {
+        (s as f32 * brightness + d as f32 * (1.0 - alpha))
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+    Color32::from_rgb(
+        channel(dst.r(), src.r()),
+        channel(dst.g(), src.g()),
+        channel(dst.b(), src.b()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack() -> HdPack {
+        HdPack {
+            path: PathBuf::new(),
+            scale: 2,
+            images: vec![Image {
+                width: 0,
+                height: 0,
+                pixels: Vec::new(),
+            }],
+            conditions: Vec::new(),
+            tiles: HashMap::new(),
+            default_tiles: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn tile_ids() {
+        assert_eq!(TileId::parse("1F").unwrap(), TileId::Index(0x1F));
+        let data = TileId::parse("000102030405060708090A0B0C0D0E0F").unwrap();
+        assert_eq!(data, TileId::Data(std::array::from_fn(|i| i as u8)));
+        assert!(TileId::parse("").is_err());
+        assert!(TileId::parse("+1F").is_err());
+        // 32 bytes, but not 32 hex digits to slice in pairs
+        let multibyte = format!("a{}a", "\u{e9}".repeat(15));
+        assert_eq!(multibyte.len(), 32);
+        assert!(TileId::parse(&multibyte).is_err());
+    }
+
+    #[test]
+    fn condition_and_tile_lines() {
+        let mut pack = pack();
+        let mut names = HashMap::new();
+        let mut ignored = 0;
+        pack.parse_line(
+            "<condition>nearWall,tileNearby,-8,0,2A,0F162736",
+            &mut names,
+            &mut ignored,
+        )
+        .unwrap();
+        assert_eq!(names.get("nearWall"), Some(&0));
+        assert!(matches!(
+            pack.conditions[0],
+            Condition::TileNearby {
+                x: -8,
+                y: 0,
+                tile: TileId::Index(0x2A),
+                palette: [0x0F, 0x16, 0x27, 0x36],
+            }
+        ));
+
+        pack.parse_line(
+            "[!nearWall]<tile>0,1F,0F162736,16,32,0.5,Y",
+            &mut names,
+            &mut ignored,
+        )
+        .unwrap();
+        let replacement = &pack.tiles[&(TileId::Index(0x1F), [0x0F, 0x16, 0x27, 0x36])][0];
+        assert_eq!((replacement.x, replacement.y), (16, 32));
+        assert_eq!(replacement.brightness, 0.5);
+        assert_eq!(replacement.conditions, [(0, true)]);
+        assert_eq!(pack.default_tiles[&TileId::Index(0x1F)].len(), 1);
+        assert_eq!(ignored, 0);
+
+        pack.parse_line(
+            "<condition>fromTheFuture,someNewCheck,0,0",
+            &mut names,
+            &mut ignored,
+        )
+        .unwrap();
+        assert_eq!(names.get("fromTheFuture"), Some(&1));
+        assert!(matches!(pack.conditions[1], Condition::Unsupported));
+        assert_eq!(ignored, 1);
+
+        assert!(
+            pack.parse_line("[unknown]<tile>0,1F,0F162736,0,0", &mut names, &mut ignored)
+                .is_err()
+        );
+        assert!(
+            pack.parse_line("<tile>1,1F,0F162736,0,0", &mut names, &mut ignored)
+                .is_err()
+        );
+    }
+}
@@ -2,13 +2,28 @@ pub mod app;
 pub mod apu;
 pub mod args;
 pub mod audio;
+pub mod breakpoint;
 pub mod bus;
 pub mod cart;
+pub mod cdl;
+pub mod cheats;
 pub mod cpu;
 pub mod debug;
+pub mod disasm;
 pub mod emu;
+pub mod filters;
+pub mod hd_pack;
 pub mod mapper;
+pub mod ntsc;
+pub mod palette;
 pub mod platform;
 pub mod ppu;
+pub mod ppu_events;
+pub mod ppu_view;
+pub mod ram_search;
+pub mod recording;
+pub mod scale;
 pub mod settings;
+pub mod symbols;
 pub mod ui;
+pub mod watch;
@@ -78,12 +78,26 @@ impl MapperIcon {
     }
 }
 
+/// Raw cartridge memories, for debugger views and edits
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapperMemory {
+    PrgRom,
+    PrgRam,
+    Chr,
+}
+
 pub trait Mapper {
+    /// Offset into PRG ROM currently mapped at `addr`, if any
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize>;
     fn read_prg(&self, addr: u16) -> Option<u8>;
     fn write_prg(&mut self, addr: u16, value: u8);
+    /// Offset into CHR ROM/RAM currently mapped at `addr`
+    fn chr_offset(&self, addr: u16) -> usize;
     fn read_chr(&self, addr: u16) -> u8;
     fn write_chr(&mut self, addr: u16, value: u8);
     fn mirroring(&self) -> Mirroring;
+    fn memory(&self, kind: MapperMemory) -> &[u8];
+    fn memory_mut(&mut self, kind: MapperMemory) -> &mut [u8];
 }
 
 #[derive(Debug, Clone, Savefile)]
@@ -94,6 +108,13 @@ pub enum MapperEnum {
 }
 
 impl MapperEnum {
+    pub fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match self {
+            MapperEnum::Mapper0(m) => m.prg_rom_offset(addr),
+            MapperEnum::Mapper1(m) => m.prg_rom_offset(addr),
+            MapperEnum::Mapper2(m) => m.prg_rom_offset(addr),
+        }
+    }
     pub fn read_prg(&self, addr: u16) -> Option<u8> {
         match self {
             MapperEnum::Mapper0(m) => m.read_prg(addr),
@@ -108,6 +129,13 @@ impl MapperEnum {
             MapperEnum::Mapper2(m) => m.write_prg(addr, value),
         }
     }
+    pub fn chr_offset(&self, addr: u16) -> usize {
+        match self {
+            MapperEnum::Mapper0(m) => m.chr_offset(addr),
+            MapperEnum::Mapper1(m) => m.chr_offset(addr),
+            MapperEnum::Mapper2(m) => m.chr_offset(addr),
+        }
+    }
     pub fn read_chr(&self, addr: u16) -> u8 {
         match self {
             MapperEnum::Mapper0(m) => m.read_chr(addr),
@@ -129,6 +157,20 @@ impl MapperEnum {
             MapperEnum::Mapper2(m) => m.mirroring(),
         }
     }
+    pub fn memory(&self, kind: MapperMemory) -> &[u8] {
+        match self {
+            MapperEnum::Mapper0(m) => m.memory(kind),
+            MapperEnum::Mapper1(m) => m.memory(kind),
+            MapperEnum::Mapper2(m) => m.memory(kind),
+        }
+    }
+    pub fn memory_mut(&mut self, kind: MapperMemory) -> &mut [u8] {
+        match self {
+            MapperEnum::Mapper0(m) => m.memory_mut(kind),
+            MapperEnum::Mapper1(m) => m.memory_mut(kind),
+            MapperEnum::Mapper2(m) => m.memory_mut(kind),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Savefile)]
@@ -163,18 +205,25 @@ impl Mapper0 {
 }
 
 impl Mapper for Mapper0 {
-    fn read_prg(&self, addr: u16) -> Option<u8> {
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
         match addr {
-            0x6000..=0x7FFF => None,
-            0x8000..=0xFFFF => Some(self.prg_rom[(addr as usize - 0x8000) % self.prg_rom.len()]),
+            0x8000..=0xFFFF => Some((addr as usize - 0x8000) % self.prg_rom.len()),
             _ => None,
         }
     }
 
+    fn read_prg(&self, addr: u16) -> Option<u8> {
+        self.prg_rom_offset(addr).map(|offset| self.prg_rom[offset])
+    }
+
     fn write_prg(&mut self, _addr: u16, _value: u8) {}
 
+    fn chr_offset(&self, addr: u16) -> usize {
+        (addr as usize) % self.chr_mem.len()
+    }
+
     fn read_chr(&self, addr: u16) -> u8 {
-        self.chr_mem[(addr as usize) % self.chr_mem.len()]
+        self.chr_mem[self.chr_offset(addr)]
     }
 
     fn write_chr(&mut self, addr: u16, value: u8) {
@@ -189,6 +238,22 @@ impl Mapper for Mapper0 {
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+
+    fn memory(&self, kind: MapperMemory) -> &[u8] {
+        match kind {
+            MapperMemory::PrgRom => &self.prg_rom,
+            MapperMemory::PrgRam => &[],
+            MapperMemory::Chr => &self.chr_mem,
+        }
+    }
+
+    fn memory_mut(&mut self, kind: MapperMemory) -> &mut [u8] {
+        match kind {
+            MapperMemory::PrgRom => &mut self.prg_rom,
+            MapperMemory::PrgRam => &mut [],
+            MapperMemory::Chr => &mut self.chr_mem,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Savefile)]
@@ -226,42 +291,48 @@ impl Mapper1 {
 }
 
 impl Mapper for Mapper1 {
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        if addr < 0x8000 {
+            return None;
+        }
+
+        let prg_mode = (self.control >> 2) & 0x03;
+        let prg_bank = self.prg_bank & 0x0F;
+        let num_banks = (self.prg_rom.len() / 0x4000) as u8;
+
+        let bank = match prg_mode {
+            0 | 1 => {
+                if addr < 0xC000 {
+                    (prg_bank & 0xFE) as usize
+                } else {
+                    ((prg_bank & 0xFE) + 1) as usize
+                }
+            }
+            2 => {
+                if addr < 0xC000 {
+                    0
+                } else {
+                    prg_bank as usize
+                }
+            }
+            3 => {
+                if addr < 0xC000 {
+                    prg_bank as usize
+                } else {
+                    (num_banks - 1) as usize
+                }
+            }
+            _ => unreachable!(),
+        };
+
+        let offset = ((addr & 0x3FFF) as usize) + (bank * 0x4000);
+        Some(offset % self.prg_rom.len())
+    }
+
     fn read_prg(&self, addr: u16) -> Option<u8> {
         match addr {
             0x6000..=0x7FFF => Some(self.prg_ram[(addr - 0x6000) as usize]),
-            0x8000..=0xFFFF => {
-                let prg_mode = (self.control >> 2) & 0x03;
-                let prg_bank = self.prg_bank & 0x0F;
-                let num_banks = (self.prg_rom.len() / 0x4000) as u8;
-
-                let bank = match prg_mode {
-                    0 | 1 => {
-                        if addr < 0xC000 {
-                            (prg_bank & 0xFE) as usize
-                        } else {
-                            ((prg_bank & 0xFE) + 1) as usize
-                        }
-                    }
-                    2 => {
-                        if addr < 0xC000 {
-                            0
-                        } else {
-                            prg_bank as usize
-                        }
-                    }
-                    3 => {
-                        if addr < 0xC000 {
-                            prg_bank as usize
-                        } else {
-                            (num_banks - 1) as usize
-                        }
-                    }
-                    _ => unreachable!(),
-                };
-
-                let offset = ((addr & 0x3FFF) as usize) + (bank * 0x4000);
-                Some(self.prg_rom[offset % self.prg_rom.len()])
-            }
+            0x8000..=0xFFFF => self.prg_rom_offset(addr).map(|offset| self.prg_rom[offset]),
             _ => None,
         }
     }
@@ -302,7 +373,7 @@ impl Mapper for Mapper1 {
         }
     }
 
-    fn read_chr(&self, addr: u16) -> u8 {
+    fn chr_offset(&self, addr: u16) -> usize {
         let chr_mode = (self.control >> 4) & 0x01;
 
         let bank = if chr_mode == 0 {
@@ -320,7 +391,11 @@ impl Mapper for Mapper1 {
         let bank_size = 0x1000;
         let offset = (addr as usize & (bank_size - 1)) + (bank * bank_size);
 
-        self.chr_mem[offset % self.chr_mem.len()]
+        offset % self.chr_mem.len()
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_mem[self.chr_offset(addr)]
     }
 
     fn write_chr(&mut self, addr: u16, value: u8) {
@@ -359,6 +434,22 @@ impl Mapper for Mapper1 {
             _ => unreachable!(),
         }
     }
+
+    fn memory(&self, kind: MapperMemory) -> &[u8] {
+        match kind {
+            MapperMemory::PrgRom => &self.prg_rom,
+            MapperMemory::PrgRam => &self.prg_ram,
+            MapperMemory::Chr => &self.chr_mem,
+        }
+    }
+
+    fn memory_mut(&mut self, kind: MapperMemory) -> &mut [u8] {
+        match kind {
+            MapperMemory::PrgRom => &mut self.prg_rom,
+            MapperMemory::PrgRam => &mut self.prg_ram,
+            MapperMemory::Chr => &mut self.chr_mem,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Savefile)]
@@ -389,19 +480,24 @@ impl Mapper2 {
 }
 
 impl Mapper for Mapper2 {
-    fn read_prg(&self, addr: u16) -> Option<u8> {
-        Some(match addr {
-            0x8000..=0xBFFF => {
-                let offset = self.bank_select as usize * 0x4000 + (addr as usize - 0x8000);
-                self.prg_rom[offset % self.prg_rom.len()]
-            }
+    fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        let offset = match addr {
+            0x8000..=0xBFFF => self.bank_select as usize * 0x4000 + (addr as usize - 0x8000),
             0xC000..=0xFFFF => {
                 let last_bank = (self.num_banks as usize - 1) * 0x4000;
-                let offset = last_bank + (addr as usize - 0xC000);
-                self.prg_rom[offset % self.prg_rom.len()]
+                last_bank + (addr as usize - 0xC000)
             }
-            _ => 0,
-        })
+            _ => return None,
+        };
+        Some(offset % self.prg_rom.len())
+    }
+
+    fn read_prg(&self, addr: u16) -> Option<u8> {
+        Some(
+            self.prg_rom_offset(addr)
+                .map(|offset| self.prg_rom[offset])
+                .unwrap_or(0),
+        )
     }
 
     fn write_prg(&mut self, addr: u16, data: u8) {
@@ -410,12 +506,16 @@ impl Mapper for Mapper2 {
         }
     }
 
+    fn chr_offset(&self, addr: u16) -> usize {
+        addr as usize % self.chr_rom.len()
+    }
+
     fn read_chr(&self, addr: u16) -> u8 {
         if addr < 0x2000 {
             if self.chr_rom.is_empty() {
                 return 0;
             }
-            self.chr_rom[addr as usize % self.chr_rom.len()]
+            self.chr_rom[self.chr_offset(addr)]
         } else {
             0
         }
@@ -431,4 +531,20 @@ impl Mapper for Mapper2 {
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+
+    fn memory(&self, kind: MapperMemory) -> &[u8] {
+        match kind {
+            MapperMemory::PrgRom => &self.prg_rom,
+            MapperMemory::PrgRam => &[],
+            MapperMemory::Chr => &self.chr_rom,
+        }
+    }
+
+    fn memory_mut(&mut self, kind: MapperMemory) -> &mut [u8] {
+        match kind {
+            MapperMemory::PrgRom => &mut self.prg_rom,
+            MapperMemory::PrgRam => &mut [],
+            MapperMemory::Chr => &mut self.chr_rom,
+        }
+    }
 }
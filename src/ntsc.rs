@@ -0,0 +1,127 @@
+use std::f32::consts::PI;
+
+use egui::Color32;
+use serde::{Deserialize, Serialize};
+
+use crate::palette::{
+    COMPOSITE_PHASES, EMPHASIS_PALETTE_SIZE, HUE_OFFSET, composite_level, yiq_to_rgb,
+};
+use crate::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+
+/// Every 3 NES pixels become 7 output pixels, as in blargg's nes_ntsc
+pub const NTSC_WIDTH: usize = (FRAME_WIDTH - 1) / 3 * 7 + 7;
+/// Signal samples per pixel: a dot lasts 8 of the 12 subcarrier phases
+const SAMPLES_PER_PIXEL: usize = 8;
+const LINE_SAMPLES: usize = FRAME_WIDTH * SAMPLES_PER_PIXEL;
+/// A scanline is 341 dots, which leaves the next one starting 4 phases later
+const LINE_PHASE_STEP: usize = 341 * SAMPLES_PER_PIXEL % COMPOSITE_PHASES;
+/// Chroma is band-limited to roughly 3 pixels, two subcarrier cycles
+const CHROMA_WINDOW: usize = COMPOSITE_PHASES * 2;
+
+/// Picture controls, each ranging from -1 to 1 with 0 the unadjusted signal
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NtscSettings {
+    /// Narrows the luma filter, letting through more detail and more chroma crosstalk
+    pub sharpness: f32,
+    pub saturation: f32,
+    /// -1 and 1 both turn the hue by half a turn
+    pub hue: f32,
+}
+
+/// Re-encodes frames as a composite signal and decodes them again like a TV would
+pub struct NtscFilter {
+    settings: NtscSettings,
+    /// Composite level of every 9-bit pixel at each subcarrier phase
+    levels: Vec<[f32; COMPOSITE_PHASES]>,
+    /// Demodulation carriers with the hue adjustment applied
+    carrier: [(f32, f32); COMPOSITE_PHASES],
+    /// Running sums of the signal, and of it multiplied by each carrier, over one line
+    luma_sum: Vec<f32>,
+    i_sum: Vec<f32>,
+    q_sum: Vec<f32>,
+    /// Counts frames for the dot crawl
+    field: usize,
+    pub pixels: Vec<Color32>,
+}
+
+impl Default for NtscFilter {
+    fn default() -> Self {
+        let levels = (0..EMPHASIS_PALETTE_SIZE)
+            .map(|pixel| std::array::from_fn(|phase| composite_level(pixel as u16, phase)))
+            .collect();
+        let mut filter = Self {
+            settings: NtscSettings::default(),
+            levels,
+            carrier: [(0.0, 0.0); COMPOSITE_PHASES],
+            luma_sum: vec![0.0; LINE_SAMPLES + 1],
+            i_sum: vec![0.0; LINE_SAMPLES + 1],
+            q_sum: vec![0.0; LINE_SAMPLES + 1],
+            field: 0,
+            pixels: vec![Color32::BLACK; NTSC_WIDTH * FRAME_HEIGHT],
+        };
+        filter.set_settings(NtscSettings::default());
+        filter
+    }
+}
+
+impl NtscFilter {
+    pub fn set_settings(&mut self, settings: NtscSettings) {
+        self.settings = settings;
+        let hue = settings.hue.clamp(-1.0, 1.0) * 6.0;
+        self.carrier = std::array::from_fn(|phase| {
+            let angle = PI * (phase as f32 + HUE_OFFSET + hue) / 6.0;
+            (angle.cos(), angle.sin())
+        });
+    }
+
+    /// Filters a frame of 9-bit pixels into `pixels`, `NTSC_WIDTH` wide
+    pub fn apply(&mut self, frame: &[u16]) {
+        // Odd frames are a dot shorter with rendering on, so the picture alternates
+        // between two phases instead of cycling through three
+        self.field ^= 1;
+        let luma_window = (COMPOSITE_PHASES as f32
+            * (1.0 - self.settings.sharpness.clamp(-1.0, 1.0) * 0.5))
+            .round() as usize;
+        let saturation = 1.0 + self.settings.saturation.clamp(-1.0, 1.0);
+
+        for (line, (row, out)) in frame
+            .chunks_exact(FRAME_WIDTH)
+            .zip(self.pixels.chunks_exact_mut(NTSC_WIDTH))
+            .enumerate()
+        {
+            let line_phase = (self.field + line) * LINE_PHASE_STEP;
+            let (mut luma, mut i, mut q) = (0.0, 0.0, 0.0);
+            for (x, &pixel) in row.iter().enumerate() {
+                let levels = &self.levels[pixel as usize % EMPHASIS_PALETTE_SIZE];
+                for sample in 0..SAMPLES_PER_PIXEL {
+                    let at = x * SAMPLES_PER_PIXEL + sample;
+                    let phase = (line_phase + at) % COMPOSITE_PHASES;
+                    let signal = levels[phase];
+                    let (cos, sin) = self.carrier[phase];
+                    luma += signal;
+                    i += signal * cos;
+                    q += signal * sin;
+                    self.luma_sum[at + 1] = luma;
+                    self.i_sum[at + 1] = i;
+                    self.q_sum[at + 1] = q;
+                }
+            }
+
+            // Averages a window of samples around `center`, past the line's ends being black
+            let window = |sums: &[f32], center: f32, width: usize| {
+                let start = (center - width as f32 / 2.0).round() as isize;
+                let clamp = |at: isize| at.clamp(0, LINE_SAMPLES as isize) as usize;
+                (sums[clamp(start + width as isize)] - sums[clamp(start)]) / width as f32
+            };
+            for (x, out) in out.iter_mut().enumerate() {
+                let center = (x as f32 + 0.5) * (3 * SAMPLES_PER_PIXEL) as f32 / 7.0;
+                *out = yiq_to_rgb(
+                    window(&self.luma_sum, center, luma_window),
+                    window(&self.i_sum, center, CHROMA_WINDOW) * saturation,
+                    window(&self.q_sum, center, CHROMA_WINDOW) * saturation,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use std::f32::consts::PI;
+
+use anyhow::{Result, bail};
+use egui::Color32;
+use serde::{Deserialize, Serialize};
+
+/// Colors the PPU can output
+pub const PALETTE_SIZE: usize = 64;
+/// Every color combined with the 8 emphasis settings of PPUMASK bits 5-7
+pub const EMPHASIS_PALETTE_SIZE: usize = PALETTE_SIZE * 8;
+/// How much each emphasis bit dims the channels it does not emphasize
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+
+static PALETTE_2C02: [u32; PALETTE_SIZE] = [
+    0xFF666666, 0xFF002A88, 0xFF1412A7, 0xFF3B00A4, 0xFF5C007E, 0xFF6E0040, 0xFF6C0600, 0xFF561D00,
+    0xFF333500, 0xFF0B4800, 0xFF005200, 0xFF004F08, 0xFF00404D, 0xFF000000, 0xFF000000, 0xFF000000,
+    0xFFADADAD, 0xFF155FD9, 0xFF4240FF, 0xFF7527FE, 0xFFA01ACC, 0xFFB71E7B, 0xFFB53120, 0xFF994E00,
+    0xFF6B6D00, 0xFF388700, 0xFF0C9300, 0xFF008F32, 0xFF007C8D, 0xFF000000, 0xFF000000, 0xFF000000,
+    0xFFFFFEFF, 0xFF64B0FF, 0xFF9290FF, 0xFFC676FF, 0xFFF36AFF, 0xFFFE6ECC, 0xFFFE8170, 0xFFEA9E22,
+    0xFFBCBE00, 0xFF88D800, 0xFF5CE430, 0xFF45E082, 0xFF48CDDE, 0xFF4F4F4F, 0xFF000000, 0xFF000000,
+    0xFFFFFEFF, 0xFFC0DFFF, 0xFFD3D2FF, 0xFFE8C8FF, 0xFFFBC2FF, 0xFFFEC4EA, 0xFFFECCC5, 0xFFF7D8A5,
+    0xFFE4E594, 0xFFCFEF96, 0xFFBDF4AB, 0xFFB3F3CC, 0xFFB5EBF2, 0xFFB8B8B8, 0xFF000000, 0xFF000000,
+];
+
+/// The RGB PPU's DAC levels, one octal digit (0-7) per channel
+static PALETTE_2C03: [u16; PALETTE_SIZE] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, 0o320, 0o120, 0o031, 0o040, 0o022,
+    0o000, 0o000, 0o000, 0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, 0o430, 0o140,
+    0o040, 0o053, 0o044, 0o000, 0o000, 0o000, 0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740,
+    0o750, 0o660, 0o360, 0o070, 0o276, 0o077, 0o000, 0o000, 0o000, 0o777, 0o567, 0o657, 0o757,
+    0o747, 0o755, 0o764, 0o772, 0o773, 0o572, 0o473, 0o276, 0o467, 0o000, 0o000, 0o000,
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaletteKind {
+    /// Measured from a composite NES
+    #[default]
+    Ppu2C02,
+    /// Arcade and PlayChoice-10 RGB PPU
+    Rgb2C03,
+    /// Decoded straight from the NTSC signal levels, with no picture adjustments
+    CompositeDirect,
+    /// Loaded from a .pal file
+    Custom,
+}
+
+impl PaletteKind {
+    pub const ALL: [PaletteKind; 4] = [
+        PaletteKind::Ppu2C02,
+        PaletteKind::Rgb2C03,
+        PaletteKind::CompositeDirect,
+        PaletteKind::Custom,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PaletteKind::Ppu2C02 => "2C02",
+            PaletteKind::Rgb2C03 => "2C03 RGB",
+            PaletteKind::CompositeDirect => "Composite direct",
+            PaletteKind::Custom => "Custom .pal",
+        }
+    }
+}
+
+/// A color replacing one entry of the selected palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaletteEdit {
+    pub index: u8,
+    pub color: [u8; 3],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    /// Indexed by `emphasis << 6 | color`
+    colors: [Color32; EMPHASIS_PALETTE_SIZE],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::builtin(PaletteKind::Ppu2C02)
+    }
+}
+
+impl Palette {
+    /// Falls back to the 2C02 palette for `Custom`, which needs a file
+    pub fn builtin(kind: PaletteKind) -> Self {
+        match kind {
+            PaletteKind::Ppu2C02 | PaletteKind::Custom => Self::from_base(|i| {
+                let [_, r, g, b] = PALETTE_2C02[i].to_be_bytes();
+                Color32::from_rgb(r, g, b)
+            }),
+            PaletteKind::Rgb2C03 => Self::from_base(|i| {
+                let level = |shift: u16| (((PALETTE_2C03[i] >> shift) & 7) * 255 / 7) as u8;
+                Color32::from_rgb(level(6), level(3), level(0))
+            }),
+            PaletteKind::CompositeDirect => Self {
+                colors: std::array::from_fn(|i| ntsc_color(i as u16)),
+            },
+        }
+    }
+
+    /// Reads a 192-byte (64 colors) or 1536-byte (with emphasis) .pal file
+    pub fn from_pal(bytes: &[u8]) -> Result<Self> {
+        let color = |i: usize| Color32::from_rgb(bytes[i * 3], bytes[i * 3 + 1], bytes[i * 3 + 2]);
+        match bytes.len() {
+            192 => Ok(Self::from_base(color)),
+            1536 => Ok(Self {
+                colors: std::array::from_fn(color),
+            }),
+            len => bail!(
+                "Palette files are 192 or 1536 bytes long, this one is {} bytes",
+                len
+            ),
+        }
+    }
+
+    /// Full 1536-byte .pal contents, emphasis included
+    pub fn to_pal(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .flat_map(|c| [c.r(), c.g(), c.b()])
+            .collect()
+    }
+
+    fn from_base(base: impl Fn(usize) -> Color32) -> Self {
+        Self {
+            colors: std::array::from_fn(|i| emphasize(base(i % PALETTE_SIZE), i / PALETTE_SIZE)),
+        }
+    }
+
+    /// Color of a 6-bit palette index, without emphasis
+    #[inline]
+    pub fn color(&self, index: u8) -> Color32 {
+        self.colors[(index & 0x3F) as usize]
+    }
+
+    /// Color of a 9-bit index carrying the emphasis bits above the palette index
+    #[inline]
+    pub fn emphasized(&self, index: u16) -> Color32 {
+        self.colors[index as usize % EMPHASIS_PALETTE_SIZE]
+    }
+
+    /// Replaces a base color, deriving its emphasized versions from it
+    pub fn set(&mut self, index: u8, color: Color32) {
+        let index = (index & 0x3F) as usize;
+        for emphasis in 0..8 {
+            self.colors[emphasis * PALETTE_SIZE + index] = emphasize(color, emphasis);
+        }
+    }
+
+    pub fn apply_edits(&mut self, edits: &[PaletteEdit]) {
+        for edit in edits {
+            let [r, g, b] = edit.color;
+            self.set(edit.index, Color32::from_rgb(r, g, b));
+        }
+    }
+}
+
+/// Approximates PPUMASK emphasis on an RGB color: every set bit dims the
+/// channels other than its own
+fn emphasize(color: Color32, emphasis: usize) -> Color32 {
+    if emphasis == 0 {
+        return color;
+    }
+    let channel = |value: u8, bit: usize| {
+        if emphasis & !(1 << bit) != 0 {
+            (value as f32 * EMPHASIS_ATTENUATION) as u8
+        } else {
+            value
+        }
+    };
+    Color32::from_rgb(
+        channel(color.r(), 0),
+        channel(color.g(), 1),
+        channel(color.b(), 2),
+    )
+}
+
+/// Subcarrier phases per color cycle, the PPU's signal generator switching at 12x the color clock
+pub const COMPOSITE_PHASES: usize = 12;
+/// Lines up hue 8 with the color burst
+pub const HUE_OFFSET: f32 = 3.9;
+
+/// Level of the PPU's composite output for a 9-bit pixel at one subcarrier
+/// phase, scaled so black is 0 and white is 1
+pub fn composite_level(pixel: u16, phase: usize) -> f32 {
+    // Voltages relative to sync for luma levels 0-3, low then high half of the wave
+    const LEVELS: [f32; 8] = [0.350, 0.518, 0.962, 1.550, 1.094, 1.506, 1.962, 1.962];
+    const BLACK: f32 = 0.518;
+    const WHITE: f32 = 1.962;
+
+    let hue = (pixel & 0x0F) as usize;
+    let emphasis = (pixel >> 6) & 7;
+    // Hues 14 and 15 are forced to the black level
+    let level = if hue > 13 {
+        1
+    } else {
+        ((pixel >> 4) & 3) as usize
+    };
+    let in_phase = |hue: usize| (hue + phase) % COMPOSITE_PHASES < 6;
+
+    let low = LEVELS[level];
+    let high = LEVELS[level + 4];
+    let mut signal = match hue {
+        0 => high,
+        13.. => low,
+        _ if in_phase(hue) => high,
+        _ => low,
+    };
+    if (emphasis & 1 != 0 && in_phase(0))
+        || (emphasis & 2 != 0 && in_phase(4))
+        || (emphasis & 4 != 0 && in_phase(8))
+    {
+        signal *= EMPHASIS_ATTENUATION;
+    }
+    (signal - BLACK) / (WHITE - BLACK)
+}
+
+/// Converts demodulated YIQ to RGB, clamping to the displayable range
+#[inline]
+pub fn yiq_to_rgb(y: f32, i: f32, q: f32) -> Color32 {
+    let to_u8 = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Color32::from_rgb(
+        to_u8(y + 0.946882 * i + 0.623557 * q),
+        to_u8(y - 0.274788 * i - 0.635691 * q),
+        to_u8(y - 1.108545 * i + 1.709007 * q),
+    )
+}
+
+/// Decodes a 9-bit pixel by sampling the PPU's composite output over one
+/// color subcarrier cycle, after Bisqwit's NTSC palette generator
+fn ntsc_color(pixel: u16) -> Color32 {
+    let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+    for phase in 0..COMPOSITE_PHASES {
+        let signal = composite_level(pixel, phase) / COMPOSITE_PHASES as f32;
+        let angle = PI * (phase as f32 + HUE_OFFSET) / 6.0;
+        y += signal;
+        i += signal * angle.cos();
+        q += signal * angle.sin();
+    }
+    yiq_to_rgb(y, i, q)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_pal_file() {
+        let mut bytes = [0; 192];
+        bytes[..3].copy_from_slice(&[200, 100, 50]);
+        bytes[0x3F * 3..].copy_from_slice(&[1, 2, 3]);
+        let palette = Palette::from_pal(&bytes).unwrap();
+        assert_eq!(palette.color(0x00), Color32::from_rgb(200, 100, 50));
+        assert_eq!(palette.color(0x3F), Color32::from_rgb(1, 2, 3));
+        // Out of range indices wrap to the 64 colors
+        assert_eq!(palette.color(0x40), palette.color(0x00));
+
+        // Red emphasis keeps red and dims the rest
+        assert_eq!(palette.emphasized(1 << 6), Color32::from_rgb(200, 74, 37));
+        // All three dim everything
+        assert_eq!(palette.emphasized(7 << 6), Color32::from_rgb(149, 74, 37));
+    }
+
+    #[test]
+    fn emphasis_pal_file() {
+        let bytes: Vec<u8> = (0..1536).map(|i| (i % 251) as u8).collect();
+        let palette = Palette::from_pal(&bytes).unwrap();
+        let color = |i: usize| Color32::from_rgb(bytes[i * 3], bytes[i * 3 + 1], bytes[i * 3 + 2]);
+        assert_eq!(palette.emphasized(0x1FF), color(0x1FF));
+        assert_eq!(palette.emphasized(0x045), color(0x045));
+        assert_eq!(palette.to_pal(), bytes);
+    }
+
+    #[test]
+    fn bad_pal_sizes() {
+        for len in [0, 191, 193, 1535, 1537] {
+            assert!(Palette::from_pal(&vec![0; len]).is_err());
+        }
+    }
+
+    #[test]
+    fn edits_replace_every_emphasis() {
+        let mut palette = Palette::default();
+        palette.apply_edits(&[PaletteEdit {
+            index: 0x0D,
+            color: [100, 100, 100],
+        }]);
+        assert_eq!(palette.color(0x0D), Color32::from_rgb(100, 100, 100));
+        assert_eq!(
+            palette.emphasized(4 << 6 | 0x0D),
+            Color32::from_rgb(74, 74, 100)
+        );
+        let saved = Palette::from_pal(&palette.to_pal()).unwrap();
+        assert_eq!(saved, palette);
+    }
+
+    #[test]
+    fn rgb_ppu_levels() {
+        let palette = Palette::builtin(PaletteKind::Rgb2C03);
+        assert_eq!(palette.color(0x20), Color32::WHITE);
+        assert_eq!(palette.color(0x0F), Color32::BLACK);
+        // 0o014: no red, a seventh of green, four sevenths of blue
+        assert_eq!(palette.color(0x01), Color32::from_rgb(0, 36, 145));
+    }
+}
@@ -8,7 +8,6 @@ use std::{
 
 use anyhow::{Context, Result};
 use directories::ProjectDirs;
-use egui::Color32;
 use log::{error, info};
 use rfd::FileDialog;
 use ringbuf::{HeapProd, HeapRb, traits::Split};
@@ -17,15 +16,15 @@ use savefile::{load_file, save_file};
 use crate::args::get_args;
 use crate::audio::Audio;
 use crate::debug::DebugSnapshot;
-use crate::emu::{Command, Emu, EmuState, Event};
+use crate::emu::{Command, Emu, EmuState, Event, Frame, StepKind};
 use crate::platform::FileDataSource;
-use crate::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+use crate::recording::RecordingFormat;
 
 pub struct PlatformRunner {
     pub command_tx: Option<mpsc::Sender<Command>>,
     pub event_rx: Option<mpsc::Receiver<Event>>,
     pub debug_rx: Option<triple_buffer::Output<DebugSnapshot>>,
-    pub frame_rx: Option<triple_buffer::Output<Vec<Color32>>>,
+    pub frame_rx: Option<triple_buffer::Output<Frame>>,
     pub emu_thread_handle: Option<JoinHandle<()>>,
     pub audio: Option<Audio>,
     pub running: bool,
@@ -71,8 +70,7 @@ impl PlatformRunner {
         let (command_tx, command_rx) = mpsc::channel();
         let (event_rx_tx, event_rx) = mpsc::channel();
         let (debug_tx, debug_rx) = triple_buffer::triple_buffer(&DebugSnapshot::default());
-        let (frame_tx, frame_rx) =
-            triple_buffer::triple_buffer(&vec![Color32::BLACK; FRAME_WIDTH * FRAME_HEIGHT]);
+        let (frame_tx, frame_rx) = triple_buffer::triple_buffer(&Frame::default());
 
         self.command_tx = Some(command_tx);
         self.event_rx = Some(event_rx);
@@ -142,9 +140,9 @@ impl PlatformRunner {
         }
     }
 
-    pub fn step(&mut self) {
+    pub fn step(&mut self, kind: StepKind) {
         if self.running && self.paused {
-            self.send_command(Command::Step);
+            self.send_command(Command::Step(kind));
         }
     }
 
@@ -160,6 +158,11 @@ impl PlatformRunner {
         let mut events = Vec::new();
         if let Some(rx) = &self.event_rx {
             while let Ok(event) = rx.try_recv() {
+                match event {
+                    Event::Paused => self.paused = true,
+                    Event::Resumed => self.paused = false,
+                    _ => {}
+                }
                 events.push(event);
             }
         }
@@ -170,7 +173,7 @@ impl PlatformRunner {
         self.debug_rx.as_mut().map(|rx| rx.read().clone())
     }
 
-    pub fn get_frame_data(&mut self) -> Option<&[Color32]> {
+    pub fn get_frame_data(&mut self) -> Option<&Frame> {
         if let Some(rx) = &mut self.frame_rx {
             if rx.update() {
                 Some(rx.output_buffer())
@@ -199,6 +202,44 @@ impl PlatformRunner {
             }
         }
     }
+
+    pub fn pick_cdl_file(&self, save: bool) {
+        let fd = FileDialog::new().add_filter("Code/Data Log", &["cdl"]);
+        if save {
+            if let Some(path) = fd.save_file() {
+                self.send_command(Command::SaveCdl(path));
+            }
+        } else if let Some(path) = fd.pick_file() {
+            self.send_command(Command::LoadCdl(path));
+        }
+    }
+
+    pub fn pick_png_file(&self, name: &str) -> Option<PathBuf> {
+        FileDialog::new()
+            .add_filter("PNG image", &["png"])
+            .set_file_name(name)
+            .save_file()
+    }
+
+    pub fn pick_recording_file(&self, format: RecordingFormat) -> Option<PathBuf> {
+        FileDialog::new()
+            .add_filter(format.name(), &[format.extension()])
+            .set_file_name(format!("recording.{}", format.extension()))
+            .save_file()
+    }
+
+    pub fn pick_hd_pack_folder(&self) -> Option<PathBuf> {
+        FileDialog::new().pick_folder()
+    }
+
+    pub fn pick_symbol_files(&self) {
+        if let Some(paths) = FileDialog::new()
+            .add_filter("Symbols", &["dbg", "nl", "mlb"])
+            .pick_files()
+        {
+            self.send_command(Command::LoadSymbols(paths));
+        }
+    }
 }
 
 impl Default for PlatformRunner {
@@ -227,19 +268,97 @@ fn process_command(command: Command, emu: &mut Emu) {
             }
             FileDataSource::Bytes(_) => error!("Cannot load state from bytes on native"),
         },
-        Command::Step => {
-            emu.want_step = true;
+        Command::Step(kind) => {
+            emu.request_step(kind);
         }
-        Command::MemoryAddress(addr) => {
+        Command::MemoryAddress(space, addr) => {
+            emu.mem_space = space;
             emu.mem_chunk_addr = addr;
+            if emu.paused {
+                emu.publish_debug_snapshot();
+            }
+        }
+        Command::WriteMemory(space, addr, value) => {
+            emu.bus.poke(space, addr, value);
+            if emu.paused {
+                emu.publish_debug_snapshot();
+            }
+        }
+        Command::DisassemblyAddress(addr) => {
+            emu.disasm_addr = addr;
+            if emu.paused {
+                emu.publish_debug_snapshot();
+            }
+        }
+        Command::SetDebugPanels(panels) => {
+            emu.debug_panels = panels;
+            if emu.paused {
+                emu.publish_debug_snapshot();
+            }
         }
         Command::DumpMemory => {
             emu.dump_memory();
         }
+        Command::SetCdlLogging(enabled) => {
+            emu.bus.cdl.enabled = enabled;
+        }
+        Command::ResetCdl => {
+            emu.bus.cdl.reset();
+        }
+        Command::SetPpuEventLogging(enabled) => {
+            emu.bus.ppu_events.enabled = enabled;
+            emu.bus.ppu_events.reset();
+            if emu.paused {
+                emu.publish_debug_snapshot();
+            }
+        }
+        Command::SetRenderOptions(options) => {
+            emu.bus.ppu.render_options = options;
+        }
+        Command::SetOamDecay(enabled) => {
+            emu.bus.ppu.set_oam_decay(enabled);
+        }
+        Command::SetRecordTiles(enabled) => {
+            emu.bus.ppu.set_record_tiles(enabled);
+        }
+        Command::StartRecording(format, path, palette) => {
+            emu.start_recording(format, path, *palette);
+        }
+        Command::StopRecording => {
+            emu.stop_recording();
+        }
+        Command::SaveCdl(path) => {
+            fs::write(&path, emu.bus.cdl.to_bytes())
+                .unwrap_or_else(|e| error!("Failed to save CDL: {e}"));
+        }
+        Command::LoadCdl(path) => {
+            load_cdl(emu, &path).unwrap_or_else(|e| error!("Failed to load CDL: {e}"));
+        }
+        Command::LoadSymbols(paths) => {
+            emu.load_symbols(&paths);
+            if emu.paused {
+                emu.publish_debug_snapshot();
+            }
+        }
         Command::ControllerInputs(input) => {
             emu.bus.controller1.realtime = (input & 0xFF) as u8;
             emu.bus.controller2.realtime = (input >> 8 & 0xFF) as u8;
         }
+        Command::SetBreakpoints(breakpoints) => {
+            emu.bus.breakpoints.set(breakpoints);
+            if emu.paused {
+                emu.publish_debug_snapshot();
+            }
+        }
+        Command::SetCheats(cheats) => {
+            emu.bus.cheats.set(&cheats);
+        }
+        Command::SetWatches(addrs) => {
+            emu.watches = addrs;
+            if emu.paused {
+                emu.publish_debug_snapshot();
+            }
+        }
     }
 }
 
@@ -247,7 +366,7 @@ pub fn emu_thread(
     command_rx: mpsc::Receiver<Command>,
     event_tx: mpsc::Sender<Event>,
     debug_tx: triple_buffer::Input<DebugSnapshot>,
-    frame_tx: triple_buffer::Input<Vec<Color32>>,
+    frame_tx: triple_buffer::Input<Frame>,
     rom: FileDataSource,
     audio_producer: HeapProd<f32>,
     sample_rate: f32,
@@ -276,7 +395,7 @@ pub fn emu_thread(
 
     loop {
         if emu.paused
-            && !emu.want_step
+            && emu.step.is_none()
             && let Ok(command) = command_rx.recv_timeout(Duration::from_millis(8))
         {
             process_command(command, &mut emu);
@@ -335,6 +454,13 @@ fn load_state(emu: &mut Emu, path: &PathBuf) -> Result<()> {
     Ok(())
 }
 
+fn load_cdl(emu: &mut Emu, path: &PathBuf) -> Result<()> {
+    let bytes = fs::read(path).context("Failed to read file")?;
+    emu.bus.cdl.load_bytes(&bytes)?;
+    info!("CDL loaded from \"{}\"", path.display());
+    Ok(())
+}
+
 pub enum ProjDirKind {
     Cache,
     Config,
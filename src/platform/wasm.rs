@@ -1,5 +1,4 @@
 use eframe::wasm_bindgen::JsCast as _;
-use egui::Color32;
 use log::error;
 use rfd::AsyncFileDialog;
 use ringbuf::{HeapRb, traits::Split};
@@ -11,9 +10,8 @@ use web_sys::{Blob, BlobPropertyBag, Url};
 
 use crate::audio::Audio;
 use crate::debug::DebugSnapshot;
-use crate::emu::{Command, Emu, EmuState, Event};
+use crate::emu::{Command, Emu, EmuState, Event, Frame, StepKind};
 use crate::platform::FileDataSource;
-use crate::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
 use anyhow::Result;
 
 pub struct PlatformRunner {
@@ -22,7 +20,7 @@ pub struct PlatformRunner {
     pub running: bool,
     pub paused: bool,
     pub pending_events: Vec<Event>,
-    pub last_frame: Vec<Color32>,
+    pub last_frame: Frame,
     pub rom_loader_rx: Option<mpsc::Receiver<Vec<u8>>>,
     pub state_file_loader_rx: Option<mpsc::Receiver<Vec<u8>>>,
     event_rx: Option<mpsc::Receiver<Event>>,
@@ -36,7 +34,7 @@ impl PlatformRunner {
             running: false,
             paused: false,
             pending_events: Vec::new(),
-            last_frame: vec![Color32::BLACK; FRAME_WIDTH * FRAME_HEIGHT],
+            last_frame: Frame::default(),
             rom_loader_rx: None,
             state_file_loader_rx: None,
             event_rx: None,
@@ -61,8 +59,7 @@ impl PlatformRunner {
 
         let (tx, rx) = mpsc::channel();
         let (debug_tx, _debug_rx) = triple_buffer::triple_buffer(&DebugSnapshot::default());
-        let (frame_tx, _frame_rx) =
-            triple_buffer::triple_buffer(&vec![Color32::BLACK; FRAME_WIDTH * FRAME_HEIGHT]);
+        let (frame_tx, _frame_rx) = triple_buffer::triple_buffer(&Frame::default());
 
         let mut emu = Emu::new(tx, debug_tx, frame_tx, false, producer, sample_rate);
 
@@ -107,9 +104,9 @@ impl PlatformRunner {
         }
     }
 
-    pub fn step(&mut self) {
+    pub fn step(&mut self, kind: StepKind) {
         if let Some(emu) = &mut self.emu {
-            emu.want_step = true;
+            emu.request_step(kind);
         }
     }
 
@@ -125,8 +122,8 @@ impl PlatformRunner {
                 Command::Resume => {
                     self.resume();
                 }
-                Command::Step => {
-                    self.step();
+                Command::Step(kind) => {
+                    self.step(kind);
                 }
                 Command::SaveState => {
                     save_state(self.emu.as_mut().unwrap())
@@ -143,12 +140,57 @@ impl PlatformRunner {
                     emu.bus.controller1.realtime = (input & 0xFF) as u8;
                     emu.bus.controller2.realtime = (input >> 8 & 0xFF) as u8;
                 }
-                Command::MemoryAddress(addr) => {
+                Command::MemoryAddress(space, addr) => {
+                    emu.mem_space = space;
                     emu.mem_chunk_addr = addr;
                 }
+                Command::WriteMemory(space, addr, value) => {
+                    emu.bus.poke(space, addr, value);
+                }
+                Command::DisassemblyAddress(addr) => {
+                    emu.disasm_addr = addr;
+                }
+                Command::SetDebugPanels(panels) => {
+                    emu.debug_panels = panels;
+                }
                 Command::DumpMemory => {
                     emu.dump_memory();
                 }
+                Command::LoadSymbols(_) => error!("Cannot load symbols from a path on WASM"),
+                Command::SetCdlLogging(enabled) => {
+                    emu.bus.cdl.enabled = enabled;
+                }
+                Command::ResetCdl => {
+                    emu.bus.cdl.reset();
+                }
+                Command::SetPpuEventLogging(enabled) => {
+                    emu.bus.ppu_events.enabled = enabled;
+                    emu.bus.ppu_events.reset();
+                }
+                Command::SetRenderOptions(options) => {
+                    emu.bus.ppu.render_options = options;
+                }
+                Command::SetOamDecay(enabled) => {
+                    emu.bus.ppu.set_oam_decay(enabled);
+                }
+                Command::SetRecordTiles(enabled) => {
+                    emu.bus.ppu.set_record_tiles(enabled);
+                }
+                Command::StartRecording(..) | Command::StopRecording => {
+                    error!("Cannot record to a file on WASM")
+                }
+                Command::SaveCdl(_) | Command::LoadCdl(_) => {
+                    error!("Cannot access CDL files from a path on WASM")
+                }
+                Command::SetBreakpoints(breakpoints) => {
+                    emu.bus.breakpoints.set(breakpoints);
+                }
+                Command::SetCheats(cheats) => {
+                    emu.bus.cheats.set(&cheats);
+                }
+                Command::SetWatches(addrs) => {
+                    emu.watches = addrs;
+                }
             }
         }
     }
@@ -178,7 +220,7 @@ impl PlatformRunner {
 
         if let Some(emu) = &mut self.emu {
             if emu.step_frame() {
-                self.last_frame.copy_from_slice(&emu.bus.ppu.screen);
+                Emu::copy_frame(&emu.bus, &mut self.last_frame);
             }
         }
 
@@ -206,7 +248,7 @@ impl PlatformRunner {
         std::mem::take(&mut self.pending_events)
     }
 
-    pub fn get_frame_data(&mut self) -> Option<&[Color32]> {
+    pub fn get_frame_data(&mut self) -> Option<&Frame> {
         if self.running {
             Some(&self.last_frame)
         } else {
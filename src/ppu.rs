@@ -1,23 +1,19 @@
-use egui::Color32;
 use modular_bitfield::prelude::*;
 
 use savefile::prelude::*;
+use serde::{Deserialize, Serialize};
 
+use crate::cdl::{ChrFlags, CodeDataLog};
 use crate::mapper::MapperEnum;
 
 pub const FRAME_WIDTH: usize = 256;
 pub const FRAME_HEIGHT: usize = 240;
-
-static PALETTE_COLORS: [u32; 64] = [
-    0xFF666666, 0xFF002A88, 0xFF1412A7, 0xFF3B00A4, 0xFF5C007E, 0xFF6E0040, 0xFF6C0600, 0xFF561D00,
-    0xFF333500, 0xFF0B4800, 0xFF005200, 0xFF004F08, 0xFF00404D, 0xFF000000, 0xFF000000, 0xFF000000,
-    0xFFADADAD, 0xFF155FD9, 0xFF4240FF, 0xFF7527FE, 0xFFA01ACC, 0xFFB71E7B, 0xFFB53120, 0xFF994E00,
-    0xFF6B6D00, 0xFF388700, 0xFF0C9300, 0xFF008F32, 0xFF007C8D, 0xFF000000, 0xFF000000, 0xFF000000,
-    0xFFFFFEFF, 0xFF64B0FF, 0xFF9290FF, 0xFFC676FF, 0xFFF36AFF, 0xFFFE6ECC, 0xFFFE8170, 0xFFEA9E22,
-    0xFFBCBE00, 0xFF88D800, 0xFF5CE430, 0xFF45E082, 0xFF48CDDE, 0xFF4F4F4F, 0xFF000000, 0xFF000000,
-    0xFFFFFEFF, 0xFFC0DFFF, 0xFFD3D2FF, 0xFFE8C8FF, 0xFFFBC2FF, 0xFFFEC4EA, 0xFFFECCC5, 0xFFF7D8A5,
-    0xFFE4E594, 0xFFCFEF96, 0xFFBDF4AB, 0xFFB3F3CC, 0xFFB5EBF2, 0xFFB8B8B8, 0xFF000000, 0xFF000000,
-];
+/// Palette index of black, shown before the first frame
+pub const BLANK_PIXEL: u16 = 0x0F;
+/// Frames a bit of the PPU open bus holds its value after last being driven, about 600 ms
+const OPEN_BUS_DECAY_FRAMES: u64 = 36;
+/// Dots an OAM row keeps its contents without being read or written, about 3000 CPU cycles
+const OAM_DECAY_DOTS: u64 = 9000;
 
 #[bitfield(bytes = 1)]
 #[derive(Debug, Clone, Default, Copy, Savefile)]
@@ -60,6 +56,72 @@ pub struct PpuStatus {
     pub vblank: bool,
 }
 
+/// Frontend overrides of what gets drawn, on top of whatever PPUMASK says.
+/// Only the picture changes, sprite 0 hit and overflow behave as on hardware
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderOptions {
+    /// Draws every sprite on a line instead of the first 8
+    pub no_sprite_limit: bool,
+    pub hide_bg: bool,
+    pub hide_sprites: bool,
+    /// Blanks the leftmost 8 pixels as if PPUMASK clipped both layers
+    pub hide_left_column: bool,
+}
+
+/// A tile a pixel was drawn from, what HD packs look replacements up by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TileRef {
+    /// Offset of the tile's first byte in CHR ROM/RAM
+    pub chr_offset: u32,
+    /// Palette RAM entries it was drawn with: the backdrop, then its 3 colors
+    pub palette: [u8; 4],
+    /// Pixel of the tile the dot landed on, before flipping
+    pub x: u8,
+    pub y: u8,
+    /// 2-bit pattern value of that pixel, 0 being transparent
+    pub color: u8,
+    pub flip_h: bool,
+    pub flip_v: bool,
+    /// Sprite drawn behind the background
+    pub behind_bg: bool,
+}
+
+/// The layers that went into one pixel, kept while [`Ppu::record_tiles`] is set
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PixelTiles {
+    /// Backdrop color, with the emphasis bits every layer of the pixel shares
+    pub backdrop: u16,
+    pub bg: Option<TileRef>,
+    /// The frontmost opaque sprite, or the first one covering the pixel if none is
+    pub sprite: Option<TileRef>,
+}
+
+/// Where a background tile's pattern came from, following it through the shifters
+#[derive(Debug, Clone, Copy, Default)]
+struct BgTile {
+    chr_offset: u32,
+    row: u8,
+    palette: u8,
+}
+
+/// Where a sprite's pattern row on the current line came from
+#[derive(Debug, Clone, Copy, Default)]
+struct SpriteRow {
+    chr_offset: u32,
+    row: u8,
+}
+
+/// Scroll and rendering registers a visible scanline was drawn with
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScanlineRegisters {
+    pub v: u16,
+    pub t: u16,
+    pub x: u8,
+    pub ctrl: PpuCtrl,
+    pub mask: PpuMask,
+}
+
 #[derive(Debug, Clone, Copy, Default, Savefile)]
 struct Sprite {
     y: u8,
@@ -67,7 +129,7 @@ struct Sprite {
     attributes: u8,
     x: u8,
 
-    index: u8,
+    sprite_0: bool,
 }
 
 impl Sprite {
@@ -90,6 +152,26 @@ impl Sprite {
     }
 }
 
+/// Progress of the sprite evaluation that fills secondary OAM over dots 65-256
+#[derive(Debug, Clone, Copy, Default, Savefile)]
+struct SpriteEval {
+    /// Sprite and byte of OAM being looked at, the high and low bits of OAMADDR
+    n: u8,
+    m: u8,
+    /// Next byte of secondary OAM written to
+    secondary_addr: u8,
+    /// The sprite being copied covers the next line
+    in_range: bool,
+    /// Every sprite has been looked at, OAM is only read from now on
+    done: bool,
+    /// Bytes left to read of the sprite that set the overflow flag
+    overflow_bytes: u8,
+    /// Sprite 0 is in secondary OAM
+    sprite_0: bool,
+    /// Sprites found for the next line, set at dot 256
+    count: u8,
+}
+
 #[derive(Debug, Savefile)]
 pub struct Ppu {
     pub scanline: u16,
@@ -97,7 +179,8 @@ pub struct Ppu {
     pub frame: u64,
     pub frame_ready: bool,
     pub open_bus: u8,
-    pub open_bus_decay_timer: u64,
+    /// Frame each bit of `open_bus` was last driven on
+    open_bus_refresh: [u64; 8],
     pub ctrl: PpuCtrl,
     pub mask: PpuMask,
     pub status: PpuStatus,
@@ -132,9 +215,55 @@ pub struct Ppu {
 
     #[savefile_introspect_ignore]
     #[savefile_ignore]
-    pub screen: Vec<Color32>,
+    /// One 9-bit pixel per dot: PPUMASK emphasis bits 5-7 above the 6-bit palette index
+    pub screen: Vec<u16>,
+    /// Scanline and dot of the frame's sprite 0 hit, until the event log collects it
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    pub sprite_0_hit_at: Option<(u16, u16)>,
+    /// One entry per visible scanline, captured at dot 257 of the line before
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    pub scanline_registers: Vec<ScanlineRegisters>,
+    /// Captured on the pre-render line, held back until the new frame is
+    /// underway so the finished frame's entry stays intact
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    next_first_line: ScanlineRegisters,
+
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    pub render_options: RenderOptions,
+    /// Fills `tiles` as the frame is drawn, for HD packs
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    pub record_tiles: bool,
+    /// What each pixel of `screen` was drawn from, empty unless `record_tiles` is set
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    pub tiles: Vec<PixelTiles>,
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    bg_next_tile: BgTile,
+    /// The tiles in the high and low halves of the background shifters
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    bg_tiles: [BgTile; 2],
+
+    /// Turns on the decay of OAM rows that are left alone for too long
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    pub oam_decay: bool,
+    /// Dots ticked since power on, timing OAM decay
+    clock: u64,
+    /// Value of `clock` when each 8 byte row of OAM was last accessed
+    oam_refresh: [u64; 32],
 
     secondary_oam: [u8; 32],
+    sprite_eval: SpriteEval,
+    /// Byte sprite evaluation and fetching last moved around, which is what
+    /// $2004 reads while rendering
+    oam_latch: u8,
     sprites: [Sprite; 8],
     sprite_height: u16,
 
@@ -144,6 +273,20 @@ pub struct Ppu {
     #[savefile_introspect_ignore]
     #[savefile_ignore]
     sprite_pattern_hi: [u8; 8],
+    /// Sprites past the first 8 on the line, only found with the limit lifted
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    extra_sprites: Vec<Sprite>,
+    /// Low and high pattern bytes of each of `extra_sprites`
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    extra_sprite_patterns: Vec<(u8, u8)>,
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    sprite_rows: [SpriteRow; 8],
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    extra_sprite_rows: Vec<SpriteRow>,
 }
 
 impl Clone for Ppu {
@@ -154,7 +297,7 @@ impl Clone for Ppu {
             frame: self.frame,
             frame_ready: self.frame_ready,
             open_bus: 0,
-            open_bus_decay_timer: 0,
+            open_bus_refresh: [0; 8],
             ctrl: self.ctrl,
             mask: self.mask,
             status: self.status,
@@ -181,11 +324,29 @@ impl Clone for Ppu {
             suppress_vbl: self.suppress_vbl,
             nmi_delay: self.nmi_delay,
             screen: Vec::new(),
+            sprite_0_hit_at: None,
+            scanline_registers: self.scanline_registers.clone(),
+            next_first_line: self.next_first_line,
+            render_options: self.render_options,
+            // Without the buffer to record into
+            record_tiles: false,
+            tiles: Vec::new(),
+            bg_next_tile: self.bg_next_tile,
+            bg_tiles: self.bg_tiles,
+            oam_decay: self.oam_decay,
+            clock: self.clock,
+            oam_refresh: self.oam_refresh,
             secondary_oam: self.secondary_oam,
+            sprite_eval: self.sprite_eval,
+            oam_latch: self.oam_latch,
             sprites: self.sprites,
             sprite_height: self.sprite_height,
             sprite_pattern_lo: [0; 8],
             sprite_pattern_hi: [0; 8],
+            extra_sprites: Vec::new(),
+            extra_sprite_patterns: Vec::new(),
+            sprite_rows: Default::default(),
+            extra_sprite_rows: Vec::new(),
         }
     }
 }
@@ -198,7 +359,7 @@ impl Default for Ppu {
             frame: 0,
             frame_ready: false,
             open_bus: 0,
-            open_bus_decay_timer: 0,
+            open_bus_refresh: [0; 8],
             ctrl: Default::default(),
             mask: Default::default(),
             status: Default::default(),
@@ -224,12 +385,29 @@ impl Default for Ppu {
             suppress_nmi: false,
             suppress_vbl: false,
             nmi_delay: false,
-            screen: vec![Color32::BLACK; FRAME_WIDTH * FRAME_HEIGHT],
+            screen: vec![BLANK_PIXEL; FRAME_WIDTH * FRAME_HEIGHT],
+            sprite_0_hit_at: None,
+            scanline_registers: vec![Default::default(); FRAME_HEIGHT],
+            next_first_line: Default::default(),
+            render_options: Default::default(),
+            record_tiles: false,
+            tiles: Vec::new(),
+            bg_next_tile: Default::default(),
+            bg_tiles: Default::default(),
+            oam_decay: false,
+            clock: 0,
+            oam_refresh: [0; 32],
             secondary_oam: [0xFF; 32],
+            sprite_eval: Default::default(),
+            oam_latch: 0,
             sprites: [Sprite::default(); 8],
             sprite_height: 8,
             sprite_pattern_lo: [0; 8],
             sprite_pattern_hi: [0; 8],
+            extra_sprites: Vec::new(),
+            extra_sprite_patterns: Vec::new(),
+            sprite_rows: Default::default(),
+            extra_sprite_rows: Vec::new(),
         }
     }
 }
@@ -252,6 +430,7 @@ impl Ppu {
         self.suppress_vbl = false;
         self.nmi_delay = false;
         self.sprite_height = 8;
+        self.sprite_eval = Default::default();
         self.bg_next_tile_id = 0;
         self.bg_next_tile_attrib = 0;
         self.bg_next_tile_lsb = 0;
@@ -262,15 +441,18 @@ impl Ppu {
         self.bg_shifter_attrib_hi = 0;
         self.sprite_pattern_lo = [0; 8];
         self.sprite_pattern_hi = [0; 8];
+        self.extra_sprites.clear();
+        self.extra_sprite_patterns.clear();
+        self.extra_sprite_rows.clear();
     }
 
-    pub fn step(&mut self, mapper: &mut MapperEnum, cpu_cycles: u32) {
+    pub fn step(&mut self, mapper: &mut MapperEnum, cdl: &mut CodeDataLog, cpu_cycles: u32) {
         for _ in 0..(cpu_cycles * 3) {
-            self.tick(mapper);
+            self.tick(mapper, cdl);
         }
     }
 
-    pub fn tick(&mut self, mapper: &mut MapperEnum) {
+    pub fn tick(&mut self, mapper: &mut MapperEnum, cdl: &mut CodeDataLog) {
         if self.scanline == 241 && self.dot == 1 {
             if !self.suppress_vbl {
                 self.status.set_vblank(true);
@@ -282,31 +464,34 @@ impl Ppu {
             self.suppress_nmi = false;
         }
 
+        self.clock += 1;
+
         if self.scanline < 240 || self.scanline == 261 {
             if (self.dot >= 1 && self.dot <= 256) || (self.dot >= 321 && self.dot <= 336) {
                 self.update_shifters();
-                self.process_bg_pipeline(mapper);
+                self.process_bg_pipeline(mapper, cdl);
             }
 
-            if self.scanline < 240 {
-                if self.dot == 1 {
-                    self.sprite_height = if self.ctrl.sprite_size() != 0 { 16 } else { 8 };
-                    self.clear_secondary_oam();
-                    self.fetch_sprites();
-
-                    self.load_sprite_patterns(mapper);
+            if self.mask.rendering_enabled() {
+                if self.scanline == 261 && self.dot == 1 {
+                    self.corrupt_oam();
                 }
-
-                if self.dot >= 1 && self.dot <= 256 {
-                    self.render_pixel();
+                if self.scanline < 240 && self.dot >= 1 && self.dot <= 256 {
+                    self.evaluate_sprites();
+                }
+                if self.dot >= 257 && self.dot <= 320 {
+                    self.fetch_sprite(mapper, cdl);
                 }
             }
 
+            if self.scanline < 240 && self.dot >= 1 && self.dot <= 256 {
+                self.render_pixel();
+            }
+
             if self.mask.rendering_enabled() {
                 if self.dot == 256 {
                     self.increment_y();
                 } else if self.dot == 257 {
-                    self.oam_addr = 0;
                     self.copy_horizontal();
                 } else if self.scanline == 261 && self.dot >= 280 && self.dot <= 304 {
                     self.copy_vertical();
@@ -314,6 +499,10 @@ impl Ppu {
             }
         }
 
+        if self.dot == 257 && (self.scanline < FRAME_HEIGHT as u16 - 1 || self.scanline == 261) {
+            self.capture_scanline_registers();
+        }
+
         if self.scanline == 261 && self.dot == 1 {
             self.status.set_vblank(false);
             self.status.set_sprite_0_hit(false);
@@ -348,7 +537,7 @@ impl Ppu {
         }
     }
 
-    fn process_bg_pipeline(&mut self, mapper: &mut MapperEnum) {
+    fn process_bg_pipeline(&mut self, mapper: &mut MapperEnum, cdl: &mut CodeDataLog) {
         if !self.mask.rendering_enabled() {
             return;
         }
@@ -370,13 +559,17 @@ impl Ppu {
                 let pattern_table = (self.ctrl.bg_pattern_table() as u16) << 12;
                 let fine_y = (self.v >> 12) & 0x07;
                 let addr = pattern_table | ((self.bg_next_tile_id as u16) << 4) | fine_y;
-                self.bg_next_tile_lsb = self.read_vram(addr, mapper);
+                self.bg_next_tile_lsb = self.fetch_pattern(addr, mapper, cdl);
+                if self.record_tiles {
+                    self.bg_next_tile.chr_offset = mapper.chr_offset(addr & !0x0F) as u32;
+                    self.bg_next_tile.row = fine_y as u8;
+                }
             }
             6 => {
                 let pattern_table = (self.ctrl.bg_pattern_table() as u16) << 12;
                 let fine_y = (self.v >> 12) & 0x07;
                 let addr = pattern_table | ((self.bg_next_tile_id as u16) << 4) | fine_y;
-                self.bg_next_tile_msb = self.read_vram(addr + 8, mapper);
+                self.bg_next_tile_msb = self.fetch_pattern(addr + 8, mapper, cdl);
             }
             7 => self.increment_coarse_x(),
             _ => {}
@@ -412,88 +605,311 @@ impl Ppu {
 
         self.bg_shifter_attrib_lo = (self.bg_shifter_attrib_lo & 0xFF00) | attrib_lo;
         self.bg_shifter_attrib_hi = (self.bg_shifter_attrib_hi & 0xFF00) | attrib_hi;
+
+        if self.record_tiles {
+            self.bg_next_tile.palette = self.bg_next_tile_attrib;
+            self.bg_tiles = [self.bg_tiles[1], self.bg_next_tile];
+        }
+    }
+
+    /// The sprites drawn on this line with their pattern bytes, in priority order
+    fn line_sprites(&self) -> impl Iterator<Item = (&Sprite, u8, u8)> {
+        let limited = self
+            .sprites
+            .iter()
+            .zip(self.sprite_pattern_lo.iter().zip(&self.sprite_pattern_hi))
+            .take_while(|(s, _)| s.y != 0xFF)
+            .map(|(s, (&lo, &hi))| (s, lo, hi));
+        let extra = self
+            .extra_sprites
+            .iter()
+            .zip(&self.extra_sprite_patterns)
+            .map(|(s, &(lo, hi))| (s, lo, hi));
+        limited.chain(extra)
+    }
+
+    /// [`Self::line_sprites`] along with where each one's pattern row came from
+    fn line_sprite_rows(&self) -> impl Iterator<Item = ((&Sprite, u8, u8), &SpriteRow)> {
+        self.line_sprites()
+            .zip(self.sprite_rows.iter().chain(&self.extra_sprite_rows))
     }
 
-    fn clear_secondary_oam(&mut self) {
-        self.secondary_oam.fill(0xFF);
-        for s in self.sprites.iter_mut() {
-            s.y = 0xFF;
+    /// If OAMADDR is 8 or more when rendering starts, the row it points into
+    /// is copied over the first one
+    fn corrupt_oam(&mut self) {
+        if self.oam_addr < 8 {
+            return;
         }
+        let row = (self.oam_addr & 0xF8) as usize;
+        self.oam.copy_within(row..row + 8, 0);
     }
 
-    fn fetch_sprites(&mut self) {
-        let height = self.sprite_height;
-        let mut n = 0usize;
-
-        for i in 0..64usize {
-            let base = i * 4;
-            let y = self.oam[base] as u16;
-
-            if self.scanline > y && self.scanline <= y.saturating_add(height) {
-                if n < 8 {
-                    let dst = n * 4;
-                    self.secondary_oam[dst..dst + 4].copy_from_slice(&self.oam[base..base + 4]);
-                    self.sprites[n] = Sprite {
-                        y: self.oam[base],
-                        tile_index: self.oam[base + 1],
-                        attributes: self.oam[base + 2],
-                        x: self.oam[base + 3],
-                        index: i as u8,
-                    };
-                    n += 1;
-                } else {
-                    self.status.set_sprite_overflow(true);
-                    break;
+    /// OAM as the sprite evaluation and $2004 see it, with rows decayed when enabled
+    fn read_oam(&mut self, addr: u8) -> u8 {
+        let row = (addr >> 3) as usize;
+        if self.oam_decay {
+            if self.clock.saturating_sub(self.oam_refresh[row]) > OAM_DECAY_DOTS {
+                // The row's cells have lost their charge, so it stays decayed
+                // until written again
+                self.oam[row * 8..row * 8 + 8].fill(0x10);
+            }
+            self.oam_refresh[row] = self.clock;
+        }
+        self.oam[addr as usize]
+    }
+
+    fn write_oam(&mut self, addr: u8, value: u8) {
+        // Bits 2-4 of the attribute byte don't exist
+        let value = if addr & 0x03 == 2 {
+            value & 0xE3
+        } else {
+            value
+        };
+        self.oam[addr as usize] = value;
+        self.oam_refresh[(addr >> 3) as usize] = self.clock;
+    }
+
+    pub fn set_record_tiles(&mut self, enabled: bool) {
+        self.record_tiles = enabled;
+        self.tiles = if enabled {
+            vec![PixelTiles::default(); FRAME_WIDTH * FRAME_HEIGHT]
+        } else {
+            Vec::new()
+        };
+    }
+
+    pub fn set_oam_decay(&mut self, enabled: bool) {
+        self.oam_decay = enabled;
+        self.oam_refresh = [self.clock; 32];
+    }
+
+    /// One dot of clearing secondary OAM (1-64) or of filling it with the
+    /// sprites on the next line (65-256), reading on odd dots and writing on even ones
+    fn evaluate_sprites(&mut self) {
+        if self.dot <= 64 {
+            self.oam_latch = 0xFF;
+            self.secondary_oam[((self.dot - 1) >> 1) as usize] = 0xFF;
+            return;
+        }
+
+        if self.dot == 65 {
+            self.sprite_height = if self.ctrl.sprite_size() != 0 { 16 } else { 8 };
+            // Evaluation starts wherever OAMADDR points, not necessarily at sprite 0
+            self.sprite_eval = SpriteEval {
+                n: self.oam_addr >> 2,
+                m: self.oam_addr & 0x03,
+                ..Default::default()
+            };
+        }
+
+        if self.dot & 1 == 1 {
+            self.oam_latch = self.read_oam(self.oam_addr);
+        } else {
+            self.sprite_eval_write();
+            let eval = &self.sprite_eval;
+            self.oam_addr = (eval.n << 2) | (eval.m & 0x03);
+        }
+
+        if self.dot == 256 {
+            self.sprite_eval.count = self.sprite_eval.secondary_addr >> 2;
+        }
+    }
+
+    fn sprite_eval_write(&mut self) {
+        let latch = self.oam_latch;
+        let covers_next_line = self.scanline.wrapping_sub(latch as u16) < self.sprite_height;
+        let eval = &mut self.sprite_eval;
+
+        if eval.done {
+            eval.n = (eval.n + 1) & 0x3F;
+            if eval.secondary_addr >= 0x20 {
+                self.oam_latch = self.secondary_oam[(eval.secondary_addr & 0x1F) as usize];
+            }
+            return;
+        }
+
+        if !eval.in_range && covers_next_line {
+            eval.in_range = true;
+        }
+
+        if eval.secondary_addr < 0x20 {
+            self.secondary_oam[eval.secondary_addr as usize] = latch;
+            if eval.in_range {
+                eval.m += 1;
+                eval.secondary_addr += 1;
+                if eval.n == 0 {
+                    eval.sprite_0 = true;
+                }
+                if eval.secondary_addr & 0x03 == 0 {
+                    eval.in_range = false;
+                    eval.m = 0;
+                    eval.n = (eval.n + 1) & 0x3F;
+                    eval.done = eval.n == 0;
                 }
+            } else {
+                eval.n = (eval.n + 1) & 0x3F;
+                eval.done = eval.n == 0;
             }
+            return;
         }
 
-        for i in n..8 {
-            self.sprites[i].y = 0xFF;
+        // With 8 sprites found, writes to secondary OAM turn into reads
+        self.oam_latch = self.secondary_oam[(eval.secondary_addr & 0x1F) as usize];
+        if eval.in_range {
+            self.status.set_sprite_overflow(true);
+            eval.m += 1;
+            if eval.m == 4 {
+                eval.n = (eval.n + 1) & 0x3F;
+                eval.m = 0;
+            }
+            if eval.overflow_bytes == 0 {
+                eval.overflow_bytes = 3;
+            } else {
+                eval.overflow_bytes -= 1;
+                if eval.overflow_bytes == 0 {
+                    eval.done = true;
+                    eval.m = 0;
+                }
+            }
+        } else {
+            // The hardware bug: both the sprite and the byte move on, so the
+            // next sprite's Y is taken from the wrong byte
+            eval.n = (eval.n + 1) & 0x3F;
+            eval.m = (eval.m + 1) & 0x03;
+            eval.done = eval.n == 0;
         }
     }
 
-    fn load_sprite_patterns(&mut self, mapper: &mut MapperEnum) {
-        let height = self.sprite_height;
+    /// One dot of loading the sprites found by the evaluation, 8 dots per sprite
+    fn fetch_sprite(&mut self, mapper: &mut MapperEnum, cdl: &mut CodeDataLog) {
+        self.oam_addr = 0;
 
-        for i in 0..8usize {
-            let sprite = self.sprites[i];
-            if sprite.y == 0xFF {
-                self.sprite_pattern_lo[i] = 0;
-                self.sprite_pattern_hi[i] = 0;
-                continue;
+        let slot = ((self.dot - 257) / 8) as usize;
+        let step = ((self.dot - 257) % 8) as usize;
+        self.oam_latch = self.secondary_oam[slot * 4 + step.min(3)];
+        if step != 7 {
+            return;
+        }
+
+        // No sprites were evaluated on the pre-render line, so none show on line 0
+        if self.scanline == 261 || slot >= self.sprite_eval.count as usize {
+            self.sprites[slot].y = 0xFF;
+            self.sprite_pattern_lo[slot] = 0;
+            self.sprite_pattern_hi[slot] = 0;
+        } else {
+            let bytes = &self.secondary_oam[slot * 4..slot * 4 + 4];
+            let sprite = Sprite {
+                y: bytes[0],
+                tile_index: bytes[1],
+                attributes: bytes[2],
+                x: bytes[3],
+                sprite_0: slot == 0 && self.sprite_eval.sprite_0,
+            };
+            self.sprites[slot] = sprite;
+            (self.sprite_pattern_lo[slot], self.sprite_pattern_hi[slot]) =
+                self.fetch_sprite_pattern(sprite, mapper, cdl);
+            if self.record_tiles {
+                self.sprite_rows[slot] = self.sprite_row(sprite, mapper);
             }
+        }
 
-            let mut fine_y = (self.scanline.wrapping_sub(sprite.y as u16).wrapping_sub(1)) as u8;
+        if slot == 7 {
+            self.fetch_extra_sprites(mapper, cdl);
+        }
+    }
 
-            let mut tile_index = sprite.tile_index as u16;
-            let pattern_table: u16;
+    /// Sprites past the first 8 covering the next line, found when the limit is lifted
+    fn fetch_extra_sprites(&mut self, mapper: &mut MapperEnum, cdl: &mut CodeDataLog) {
+        self.extra_sprites.clear();
+        if !self.render_options.no_sprite_limit
+            || self.scanline == 261
+            || self.sprite_eval.count < 8
+        {
+            self.extra_sprite_patterns.clear();
+            self.extra_sprite_rows.clear();
+            return;
+        }
 
-            if height == 16 {
-                pattern_table = (tile_index & 0x01) << 12;
-                tile_index &= 0xFE;
+        let (scanline, height) = (self.scanline, self.sprite_height);
+        let in_range = self
+            .oam
+            .chunks_exact(4)
+            .filter(|bytes| scanline.wrapping_sub(bytes[0] as u16) < height)
+            .skip(8)
+            .map(|bytes| Sprite {
+                y: bytes[0],
+                tile_index: bytes[1],
+                attributes: bytes[2],
+                x: bytes[3],
+                sprite_0: false,
+            });
+        self.extra_sprites.extend(in_range);
+        self.extra_sprite_patterns = self
+            .extra_sprites
+            .iter()
+            .map(|&sprite| self.fetch_sprite_pattern(sprite, mapper, cdl))
+            .collect();
+        if self.record_tiles {
+            self.extra_sprite_rows = self
+                .extra_sprites
+                .iter()
+                .map(|&sprite| self.sprite_row(sprite, mapper))
+                .collect();
+        }
+    }
 
-                if sprite.flip_v() {
-                    if fine_y < 8 {
-                        tile_index += 1;
-                    }
+    /// Low and high pattern bytes of the row of `sprite` on the next scanline
+    fn fetch_sprite_pattern(
+        &self,
+        sprite: Sprite,
+        mapper: &mut MapperEnum,
+        cdl: &mut CodeDataLog,
+    ) -> (u8, u8) {
+        let addr = self.sprite_pattern_addr(sprite);
+        (
+            self.fetch_pattern(addr, mapper, cdl),
+            self.fetch_pattern(addr + 8, mapper, cdl),
+        )
+    }
+
+    fn sprite_row(&self, sprite: Sprite, mapper: &MapperEnum) -> SpriteRow {
+        let addr = self.sprite_pattern_addr(sprite);
+        SpriteRow {
+            chr_offset: mapper.chr_offset(addr & !0x0F) as u32,
+            row: (addr & 0x07) as u8,
+        }
+    }
+
+    /// Address of the low pattern byte of the row of `sprite` on the next scanline
+    fn sprite_pattern_addr(&self, sprite: Sprite) -> u16 {
+        let height = self.sprite_height;
+        let mut fine_y = self.scanline.wrapping_sub(sprite.y as u16) as u8;
 
-                    fine_y = 7 - (fine_y & 7);
-                } else if fine_y >= 8 {
-                    fine_y -= 8;
+        let mut tile_index = sprite.tile_index as u16;
+        let pattern_table: u16;
+
+        if height == 16 {
+            pattern_table = (tile_index & 0x01) << 12;
+            tile_index &= 0xFE;
+
+            if sprite.flip_v() {
+                if fine_y < 8 {
                     tile_index += 1;
                 }
-            } else {
-                pattern_table = (self.ctrl.sprite_pattern_table() as u16) << 12;
-                if sprite.flip_v() {
-                    fine_y = 7 - fine_y;
-                }
-            }
 
-            let addr = pattern_table | (tile_index << 4) | (fine_y as u16);
-            self.sprite_pattern_lo[i] = self.read_vram(addr, mapper);
-            self.sprite_pattern_hi[i] = self.read_vram(addr + 8, mapper);
+                fine_y = 7 - (fine_y & 7);
+            } else if fine_y >= 8 {
+                fine_y -= 8;
+                tile_index += 1;
+            }
+        } else {
+            pattern_table = (self.ctrl.sprite_pattern_table() as u16) << 12;
+            if sprite.flip_v() {
+                fine_y = 7 - fine_y;
+            }
         }
+
+        pattern_table | (tile_index << 4) | (fine_y as u16)
     }
 
     #[inline]
@@ -530,11 +946,7 @@ impl Ppu {
             return (0, 0, false, false);
         }
 
-        for (i, sprite) in self.sprites.iter().enumerate() {
-            if sprite.y == 0xFF {
-                break;
-            }
-
+        for (sprite, lo, hi) in self.line_sprites() {
             let sx = sprite.x as u16;
             if dot_x < sx || dot_x >= sx + 8 {
                 continue;
@@ -545,8 +957,6 @@ impl Ppu {
                 fine_x = 7 - fine_x;
             }
 
-            let lo = self.sprite_pattern_lo[i];
-            let hi = self.sprite_pattern_hi[i];
             let bit = 7 - fine_x;
             let pixel = (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1);
 
@@ -555,7 +965,7 @@ impl Ppu {
                     pixel,
                     (sprite.palette() << 2) | pixel,
                     sprite.priority(),
-                    sprite.index == 0,
+                    sprite.sprite_0,
                 );
             }
         }
@@ -579,10 +989,26 @@ impl Ppu {
             let left_clipped =
                 screen_x < 8 && (!self.mask.show_bg_left() || !self.mask.show_sprites_left());
             if !left_clipped {
+                if !self.status.sprite_0_hit() {
+                    self.sprite_0_hit_at = Some((self.scanline, self.dot));
+                }
                 self.status.set_sprite_0_hit(true);
             }
         }
 
+        let options = self.render_options;
+        let hidden_column = options.hide_left_column && screen_x < 8;
+        let bg_pixel = if options.hide_bg || hidden_column {
+            0
+        } else {
+            bg_pixel
+        };
+        let sp_pixel = if options.hide_sprites || hidden_column {
+            0
+        } else {
+            sp_pixel
+        };
+
         let bg_palette_addr = if bg_pixel > 0 {
             (bg_palette << 2) | bg_pixel
         } else {
@@ -602,16 +1028,104 @@ impl Ppu {
             }
         };
 
-        let palette_addr = if (final_index & 0x03) == 0 {
+        let palette_addr = if !self.mask.rendering_enabled() && self.v & 0x3F00 == 0x3F00 {
+            // With rendering off the backdrop is whichever entry `v` points at
+            (self.v & 0x1F) as u8
+        } else if (final_index & 0x03) == 0 {
             0
         } else {
             final_index & 0x1F
         };
 
+        // Greyscale keeps only the luma bits, emphasis is applied by the frontend's palette
         let grey_mask: u8 = if self.mask.greyscale() { 0x30 } else { 0x3F };
-        let color_index = self.palette[palette_addr as usize] & grey_mask;
+        let color_index = self.palette[palette_index(palette_addr as u16)] & grey_mask;
+        let emphasis = (self.mask.into_bytes()[0] & 0xE0) as u16;
+
+        self.screen[screen_y * FRAME_WIDTH + screen_x] = (emphasis << 1) | color_index as u16;
+
+        if self.record_tiles {
+            let bg_shown = self.mask.show_bg()
+                && (screen_x >= 8 || self.mask.show_bg_left())
+                && !options.hide_bg
+                && !hidden_column;
+            let backdrop = if final_index == 0 {
+                self.screen[screen_y * FRAME_WIDTH + screen_x]
+            } else {
+                (emphasis << 1) | (self.palette[0] & grey_mask) as u16
+            };
+            let sprite = if options.hide_sprites || hidden_column {
+                None
+            } else {
+                self.sprite_tile(sp_pixel != 0, grey_mask)
+            };
+            self.tiles[screen_y * FRAME_WIDTH + screen_x] = PixelTiles {
+                backdrop,
+                bg: bg_shown.then(|| {
+                    // The shifters hold the previous tile until fine X plus the
+                    // dots since the last reload reach the next one
+                    let column = self.x + ((self.dot - 1) & 7) as u8;
+                    let (tile, x) = if column >= 8 {
+                        (self.bg_tiles[1], column - 8)
+                    } else {
+                        (self.bg_tiles[0], column)
+                    };
+                    TileRef {
+                        chr_offset: tile.chr_offset,
+                        palette: self.tile_palette(tile.palette << 2, grey_mask),
+                        x,
+                        y: tile.row,
+                        color: bg_pixel,
+                        ..Default::default()
+                    }
+                }),
+                sprite,
+            };
+        }
+    }
 
-        self.screen[screen_y * FRAME_WIDTH + screen_x] = Self::get_color_from_palette(color_index);
+    /// The backdrop and the 3 colors of the palette starting at `offset`
+    fn tile_palette(&self, offset: u8, grey_mask: u8) -> [u8; 4] {
+        let color = |i: u8| self.palette[palette_index((offset | i) as u16)] & grey_mask;
+        [self.palette[0] & grey_mask, color(1), color(2), color(3)]
+    }
+
+    /// The sprite tile at the current dot, the same one [`Self::get_sprite_pixel`]
+    /// picked when `opaque`
+    fn sprite_tile(&self, opaque: bool, grey_mask: u8) -> Option<TileRef> {
+        let dot_x = self.dot - 1;
+        if !self.mask.show_sprites() || (dot_x < 8 && !self.mask.show_sprites_left()) {
+            return None;
+        }
+        self.line_sprite_rows()
+            .filter(|((sprite, _, _), _)| {
+                let sx = sprite.x as u16;
+                dot_x >= sx && dot_x < sx + 8
+            })
+            .map(|((sprite, lo, hi), row)| {
+                let mut fine_x = (dot_x - sprite.x as u16) as u8;
+                if sprite.flip_h() {
+                    fine_x = 7 - fine_x;
+                }
+                let bit = 7 - fine_x;
+                TileRef {
+                    chr_offset: row.chr_offset,
+                    palette: self.tile_palette(0x10 | (sprite.palette() << 2), grey_mask),
+                    x: fine_x,
+                    y: row.row,
+                    color: (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1),
+                    flip_h: sprite.flip_h(),
+                    flip_v: sprite.flip_v(),
+                    behind_bg: sprite.priority(),
+                }
+            })
+            .reduce(|first, tile| {
+                if !opaque || first.color != 0 {
+                    first
+                } else {
+                    tile
+                }
+            })
     }
 
     #[inline]
@@ -648,19 +1162,55 @@ impl Ppu {
         self.v = (self.v & !0x041F) | (self.t & 0x041F);
     }
 
+    /// After the horizontal copy at dot 257, `v` holds where the next
+    /// scanline starts rendering from
+    fn capture_scanline_registers(&mut self) {
+        let mut registers = ScanlineRegisters {
+            v: self.v,
+            t: self.t,
+            x: self.x,
+            ctrl: self.ctrl,
+            mask: self.mask,
+        };
+        if self.scanline == 261 {
+            // The vertical bits only reach `v` at dots 280-304 of the pre-render line
+            if self.mask.rendering_enabled() {
+                registers.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+            }
+            self.next_first_line = registers;
+            return;
+        }
+        let line = self.scanline as usize;
+        if line == 0
+            && let Some(first) = self.scanline_registers.first_mut()
+        {
+            *first = self.next_first_line;
+        }
+        if let Some(next) = self.scanline_registers.get_mut(line + 1) {
+            *next = registers;
+        }
+    }
+
     #[inline]
     fn copy_vertical(&mut self) {
         self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
     }
 
-    pub fn read_vram(&self, addr: u16, mapper: &mut MapperEnum) -> u8 {
+    fn fetch_pattern(&self, addr: u16, mapper: &mut MapperEnum, cdl: &mut CodeDataLog) -> u8 {
+        if cdl.enabled {
+            cdl.log_chr(mapper.chr_offset(addr), ChrFlags::DRAWN);
+        }
+        self.read_vram(addr, mapper)
+    }
+
+    pub fn read_vram(&self, addr: u16, mapper: &MapperEnum) -> u8 {
         let addr = addr & 0x3FFF;
         if addr < 0x2000 {
             mapper.read_chr(addr)
         } else if addr < 0x3F00 {
             self.vram[self.mirror_vram_addr(addr, mapper.mirroring())]
         } else {
-            self.palette[(addr & 0x1F) as usize] & 0x3F
+            self.palette[palette_index(addr)] & 0x3F
         }
     }
 
@@ -672,12 +1222,19 @@ impl Ppu {
             let m = self.mirror_vram_addr(addr, mapper.mirroring());
             self.vram[m] = value;
         } else {
-            let p = (addr & 0x1F) as usize;
-            let v = value & 0x3F;
-            self.palette[p] = v;
-            if p & 0x03 == 0 {
-                self.palette[p ^ 0x10] = v;
-            }
+            self.write_palette(addr, value);
+        }
+    }
+
+    /// Only 6 bits are stored, and the backdrop entries of the sprite palettes
+    /// share their byte with the background ones. Both copies are kept in sync
+    /// so viewers can show all 32 entries
+    pub fn write_palette(&mut self, addr: u16, value: u8) {
+        let value = value & 0x3F;
+        let index = palette_index(addr);
+        self.palette[index] = value;
+        if index & 0x03 == 0 {
+            self.palette[index | 0x10] = value;
         }
     }
 
@@ -716,17 +1273,15 @@ impl Ppu {
         } else if !self.ctrl.nmi_enable() {
             self.nmi_pending = false;
         }
-
-        self.open_bus = value;
     }
 
     pub fn write_mask(&mut self, value: u8) {
         self.mask = PpuMask::from_bytes([value]);
-        self.open_bus = value;
     }
 
+    /// Only bits 5-7 are driven, the rest come from the open bus
     pub fn read_status(&mut self) -> u8 {
-        let mut result = (self.status.bytes[0] & 0xE0) | (self.open_bus & 0x1F);
+        let mut result = self.status.bytes[0] & 0xE0;
 
         if self.scanline == 241 && self.dot == 1 {
             self.suppress_vbl = true;
@@ -741,23 +1296,34 @@ impl Ppu {
         self.nmi_pending = false;
         self.w = false;
 
-        self.open_bus = result;
         result
     }
 
     pub fn write_oam_addr(&mut self, value: u8) {
         self.oam_addr = value;
-        self.open_bus = value;
     }
 
-    pub fn read_oam_data(&self) -> u8 {
-        self.oam[self.oam_addr as usize]
+    /// OAM is busy on the visible and pre-render lines while rendering
+    #[inline]
+    fn oam_busy(&self) -> bool {
+        self.mask.rendering_enabled() && (self.scanline < 240 || self.scanline == 261)
+    }
+
+    pub fn read_oam_data(&mut self) -> u8 {
+        if self.oam_busy() {
+            return self.oam_latch;
+        }
+        self.read_oam(self.oam_addr)
     }
 
     pub fn write_oam_data(&mut self, value: u8) {
-        self.oam[self.oam_addr as usize] = value;
-        self.oam_addr = self.oam_addr.wrapping_add(1);
-        self.open_bus = value;
+        if self.oam_busy() {
+            // The write is dropped and only the high 6 bits of OAMADDR move on
+            self.oam_addr = self.oam_addr.wrapping_add(4);
+        } else {
+            self.write_oam(self.oam_addr, value);
+            self.oam_addr = self.oam_addr.wrapping_add(1);
+        }
     }
 
     pub fn write_scroll(&mut self, value: u8) {
@@ -770,7 +1336,6 @@ impl Ppu {
                 (self.t & !0x73E0) | ((value as u16 & 0x07) << 12) | ((value as u16 & 0xF8) << 2);
             self.w = false;
         }
-        self.open_bus = value;
     }
 
     pub fn write_addr(&mut self, value: u8) {
@@ -782,38 +1347,59 @@ impl Ppu {
             self.v = self.t;
             self.w = false;
         }
-        self.open_bus = value;
     }
 
+    /// Palette reads skip the read buffer and only drive the low 6 bits,
+    /// the top 2 come from the open bus
     pub fn read_data(&mut self, mapper: &mut MapperEnum) -> u8 {
-        let addr = self.v;
+        let addr = self.v & 0x3FFF;
         let inc = self.vram_inc();
         self.v = self.v.wrapping_add(inc);
 
         if addr < 0x3F00 {
             let buf = self.read_buffer;
             self.read_buffer = self.read_vram(addr, mapper);
-            self.open_bus = buf;
             buf
         } else {
+            // The buffer is filled from the nametable hidden under the palette
             self.read_buffer = self.read_vram(addr & 0x2FFF, mapper);
-            let v = (self.read_vram(addr, mapper) & 0x3F) | (self.open_bus & 0xC0);
-            self.open_bus = v;
-            v
+            let grey_mask = if self.mask.greyscale() { 0x30 } else { 0x3F };
+            self.read_vram(addr, mapper) & grey_mask
+        }
+    }
+
+    /// Drives the bits of the open bus set in `mask` with `value` and lets
+    /// the rest fade out once they've gone undriven for long enough
+    pub fn set_open_bus(&mut self, mask: u8, value: u8) {
+        for bit in 0..8 {
+            let bit_mask = 1 << bit;
+            if mask & bit_mask != 0 {
+                self.open_bus = (self.open_bus & !bit_mask) | (value & bit_mask);
+                self.open_bus_refresh[bit] = self.frame;
+            } else if self.frame.saturating_sub(self.open_bus_refresh[bit]) > OPEN_BUS_DECAY_FRAMES
+            {
+                self.open_bus &= !bit_mask;
+            }
         }
     }
 
+    /// A register read that drives the bits outside `open_mask` with
+    /// `value`, returning the open bus in the others
+    pub fn apply_open_bus(&mut self, open_mask: u8, value: u8) -> u8 {
+        self.set_open_bus(!open_mask, value);
+        self.open_bus
+    }
+
     pub fn write_data(&mut self, value: u8, mapper: &mut MapperEnum) {
         let addr = self.v;
         let inc = self.vram_inc();
         self.write_vram(addr, value, mapper);
         self.v = self.v.wrapping_add(inc);
-        self.open_bus = value;
     }
 
     pub fn write_oam_dma(&mut self, data: &[u8]) {
         for (i, &byte) in data.iter().enumerate() {
-            self.oam[self.oam_addr.wrapping_add(i as u8) as usize] = byte;
+            self.write_oam(self.oam_addr.wrapping_add(i as u8), byte);
         }
     }
 
@@ -832,11 +1418,217 @@ impl Ppu {
         }
         false
     }
+}
 
-    #[inline]
-    pub fn get_color_from_palette(index: u8) -> Color32 {
-        let c = PALETTE_COLORS[(index & 0x3F) as usize];
-        let [a, r, g, b] = c.to_be_bytes();
-        Color32::from_rgba_unmultiplied(r, g, b, a)
+/// Palette RAM index of `addr`, with $3F10/$3F14/$3F18/$3F1C mirroring
+/// $3F00/$3F04/$3F08/$3F0C
+#[inline]
+fn palette_index(addr: u16) -> usize {
+    let index = (addr & 0x1F) as usize;
+    if index & 0x13 == 0x10 {
+        index & 0x0F
+    } else {
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cart::Cart;
+
+    /// Runs a whole line of sprite evaluation for the sprites on the next one
+    fn evaluate_line(ppu: &mut Ppu, scanline: u16) {
+        ppu.scanline = scanline;
+        for dot in 1..=256 {
+            ppu.dot = dot;
+            ppu.evaluate_sprites();
+        }
+    }
+
+    /// 8 sprites on line 10 and the rest of OAM hidden below the screen
+    fn full_line() -> Ppu {
+        let mut ppu = Ppu::new();
+        ppu.oam = [0xFF; 256];
+        for sprite in 0..8 {
+            ppu.oam[sprite * 4] = 10;
+        }
+        ppu
+    }
+
+    #[test]
+    fn eight_sprites_do_not_overflow() {
+        let mut ppu = full_line();
+        evaluate_line(&mut ppu, 10);
+        assert_eq!(ppu.sprite_eval.count, 8);
+        assert!(!ppu.status.sprite_overflow());
+    }
+
+    #[test]
+    fn overflow_bug_reads_the_wrong_byte_as_y() {
+        // Sprite 9 is off the line, but after missing sprite 8 its tile
+        // number is compared instead of its Y
+        let mut ppu = full_line();
+        ppu.oam[9 * 4 + 1] = 10;
+        evaluate_line(&mut ppu, 10);
+        assert!(ppu.status.sprite_overflow());
+
+        // And the other way around, sprite 9 is on the line but missed
+        let mut ppu = full_line();
+        ppu.oam[9 * 4] = 10;
+        evaluate_line(&mut ppu, 10);
+        assert!(!ppu.status.sprite_overflow());
+    }
+
+    #[test]
+    fn ninth_sprite_right_after_the_eighth_overflows() {
+        let mut ppu = full_line();
+        ppu.oam[8 * 4] = 10;
+        evaluate_line(&mut ppu, 10);
+        assert_eq!(ppu.sprite_eval.count, 8);
+        assert!(ppu.status.sprite_overflow());
+    }
+
+    #[test]
+    fn oam_data_write_while_rendering_bumps_oam_addr_by_4() {
+        let mut ppu = Ppu::new();
+        ppu.mask = PpuMask::new().with_show_bg(true);
+        ppu.scanline = 100;
+        ppu.oam_addr = 0x05;
+        ppu.write_oam_data(0xAA);
+        assert_eq!(ppu.oam_addr, 0x09);
+        assert!(ppu.oam.iter().all(|&byte| byte == 0));
+
+        ppu.oam_addr = 0xFE;
+        ppu.write_oam_data(0xAA);
+        assert_eq!(ppu.oam_addr, 0x02);
+
+        // In vblank the write lands and OAMADDR moves on by one
+        ppu.scanline = 241;
+        ppu.oam_addr = 0x05;
+        ppu.write_oam_data(0xAA);
+        assert_eq!(ppu.oam_addr, 0x06);
+        assert_eq!(ppu.oam[0x05], 0xAA);
+    }
+
+    #[test]
+    fn decayed_rows_stay_decayed() {
+        let mut ppu = Ppu::new();
+        ppu.oam = [0x42; 256];
+        ppu.set_oam_decay(true);
+        ppu.clock += OAM_DECAY_DOTS / 2;
+        ppu.oam_addr = 0x08;
+        assert_eq!(ppu.read_oam_data(), 0x42);
+
+        ppu.clock += OAM_DECAY_DOTS;
+        ppu.oam_addr = 0x03;
+        assert_eq!(ppu.read_oam_data(), 0x10);
+        assert_eq!(ppu.oam[0..8], [0x10; 8]);
+        // Row 1 was refreshed by the first read and is still within the limit
+        ppu.oam_addr = 0x08;
+        assert_eq!(ppu.read_oam_data(), 0x42);
+        assert_eq!(ppu.oam[8..16], [0x42; 8]);
+
+        // Reading it again right away doesn't bring the old bytes back
+        ppu.oam_addr = 0x04;
+        assert_eq!(ppu.read_oam_data(), 0x10);
+
+        ppu.oam_addr = 0x00;
+        ppu.write_oam_data(0x20);
+        ppu.oam_addr = 0x00;
+        assert_eq!(ppu.read_oam_data(), 0x20);
+    }
+
+    /// A bus with an NROM cart, for going through the PPU registers
+    fn bus() -> Bus {
+        let mut rom = vec![b'N', b'E', b'S', 0x1A, 2, 1];
+        rom.resize(16 + 0x8000 + 0x2000, 0);
+        let mut bus = Bus::new();
+        bus.insert_cartridge(Cart::from_bytes(rom).unwrap());
+        bus
+    }
+
+    fn set_vram_addr(bus: &mut Bus, addr: u16) {
+        bus.write_byte(0x2006, (addr >> 8) as u8);
+        bus.write_byte(0x2006, addr as u8);
+    }
+
+    #[test]
+    fn open_bus_decays_bit_by_bit() {
+        let mut ppu = Ppu::new();
+        ppu.set_open_bus(0xFF, 0xFF);
+        ppu.frame = 20;
+        ppu.set_open_bus(0x0F, 0x05);
+        assert_eq!(ppu.open_bus, 0xF5);
+
+        // Undriven bits hold their value for a while
+        ppu.frame = OPEN_BUS_DECAY_FRAMES;
+        ppu.set_open_bus(0x00, 0x00);
+        assert_eq!(ppu.open_bus, 0xF5);
+        // Then fade, each after its own last refresh
+        ppu.frame = OPEN_BUS_DECAY_FRAMES + 1;
+        ppu.set_open_bus(0x00, 0x00);
+        assert_eq!(ppu.open_bus, 0x05);
+        ppu.frame = 20 + OPEN_BUS_DECAY_FRAMES + 1;
+        assert_eq!(ppu.apply_open_bus(0xFF, 0x00), 0x00);
+    }
+
+    #[test]
+    fn palette_reads_fill_the_top_bits_from_open_bus() {
+        let mut bus = bus();
+        set_vram_addr(&mut bus, 0x3F01);
+        bus.write_byte(0x2007, 0xEA);
+
+        set_vram_addr(&mut bus, 0x3F01);
+        // Drive the bus with a write to a register that doesn't mind
+        bus.write_byte(0x2003, 0xC0);
+        assert_eq!(bus.read_byte(0x2007), 0xEA);
+        // The read drives the low bits only
+        assert_eq!(bus.ppu.open_bus, 0xEA);
+
+        // Setting the address left $01 on the bus
+        set_vram_addr(&mut bus, 0x3F01);
+        assert_eq!(bus.read_byte(0x2007), 0x2A);
+    }
+
+    #[test]
+    fn sprite_backdrops_mirror_background_ones() {
+        let mut bus = bus();
+        let read = |bus: &Bus, addr| bus.ppu.read_vram(addr, &bus.cart.as_ref().unwrap().mapper);
+        for (i, addr) in [0x3F10, 0x3F14, 0x3F18, 0x3F1C].into_iter().enumerate() {
+            set_vram_addr(&mut bus, addr);
+            bus.write_byte(0x2007, 0x30 + i as u8);
+            assert_eq!(read(&bus, addr - 0x10), 0x30 + i as u8);
+        }
+        set_vram_addr(&mut bus, 0x3F04);
+        bus.write_byte(0x2007, 0x21);
+        assert_eq!(read(&bus, 0x3F14), 0x21);
+        // Mirrored every 32 bytes up to $3FFF
+        assert_eq!(read(&bus, 0x3FF4), 0x21);
+
+        // The other sprite entries are their own
+        set_vram_addr(&mut bus, 0x3F11);
+        bus.write_byte(0x2007, 0x11);
+        assert_eq!(read(&bus, 0x3F01), 0x00);
+        assert_eq!(read(&bus, 0x3F11), 0x11);
+    }
+
+    #[test]
+    fn rendering_off_backdrop_comes_from_v() {
+        let mut ppu = Ppu::new();
+        ppu.write_palette(0x3F00, 0x0F);
+        ppu.write_palette(0x3F13, 0x16);
+        ppu.scanline = 0;
+        let pixel = |ppu: &mut Ppu, v: u16| {
+            ppu.v = v;
+            ppu.dot += 1;
+            ppu.render_pixel();
+            ppu.screen[ppu.dot as usize - 1]
+        };
+        // Outside the palette it's the usual backdrop
+        assert_eq!(pixel(&mut ppu, 0x2000), 0x0F);
+        assert_eq!(pixel(&mut ppu, 0x3F13), 0x16);
+        assert_eq!(pixel(&mut ppu, 0x3F10), 0x0F);
     }
 }
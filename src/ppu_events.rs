@@ -0,0 +1,97 @@
+/// Dots per scanline and scanlines per frame, the size of the event grid
+pub const DOTS: usize = 341;
+pub const SCANLINES: usize = 262;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PpuEventKind {
+    /// CPU write to $2000-$2007, `addr` folded down to the register
+    RegisterWrite,
+    /// CPU write to $4014
+    OamDma,
+    /// CPU write to $4020-$FFFF
+    MapperWrite,
+    Nmi,
+    Irq,
+    Sprite0Hit,
+}
+
+impl PpuEventKind {
+    pub const ALL: [PpuEventKind; 6] = [
+        PpuEventKind::RegisterWrite,
+        PpuEventKind::OamDma,
+        PpuEventKind::MapperWrite,
+        PpuEventKind::Nmi,
+        PpuEventKind::Irq,
+        PpuEventKind::Sprite0Hit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PpuEventKind::RegisterWrite => "PPU register writes",
+            PpuEventKind::OamDma => "OAM DMA",
+            PpuEventKind::MapperWrite => "Mapper writes",
+            PpuEventKind::Nmi => "NMI",
+            PpuEventKind::Irq => "IRQ",
+            PpuEventKind::Sprite0Hit => "Sprite 0 hit",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PpuEvent {
+    pub kind: PpuEventKind,
+    pub scanline: u16,
+    pub dot: u16,
+    /// Address of the instruction that was executing
+    pub pc: u16,
+    pub addr: u16,
+    pub value: u8,
+}
+
+impl PpuEvent {
+    /// Position in the frame, for ordering
+    pub fn cycle(&self) -> usize {
+        self.scanline as usize * DOTS + self.dot as usize
+    }
+}
+
+/// PPU-relevant writes and interrupts of the current and previous frame
+#[derive(Debug, Default, Clone)]
+pub struct PpuEventLog {
+    pub enabled: bool,
+    /// Instruction being executed, kept up to date by the CPU while enabled
+    pub pc: u16,
+    current: Vec<PpuEvent>,
+    previous: Vec<PpuEvent>,
+}
+
+impl PpuEventLog {
+    pub fn push(&mut self, kind: PpuEventKind, addr: u16, value: u8, scanline: u16, dot: u16) {
+        self.current.push(PpuEvent {
+            kind,
+            scanline,
+            dot,
+            pc: self.pc,
+            addr,
+            value,
+        });
+    }
+
+    pub fn end_frame(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    pub fn reset(&mut self) {
+        self.current.clear();
+        self.previous.clear();
+    }
+
+    /// One frame's worth of events ending at (`scanline`, `dot`): the current
+    /// frame so far, then what the previous frame had past that point
+    pub fn events(&self, scanline: u16, dot: u16) -> Vec<PpuEvent> {
+        let now = scanline as usize * DOTS + dot as usize;
+        let mut events = self.current.clone();
+        events.extend(self.previous.iter().filter(|e| e.cycle() >= now));
+        events
+    }
+}
@@ -0,0 +1,279 @@
+use egui::Color32;
+
+use crate::palette::Palette;
+
+pub const NAMETABLES_WIDTH: usize = 512;
+pub const NAMETABLES_HEIGHT: usize = 480;
+const TILES_X: usize = 32;
+const ATTRIBUTE_OFFSET: usize = 0x3C0;
+
+/// Colors of one 4-color palette, entry 0 being the universal background
+pub fn palette_colors(colors: &Palette, palette: &[u8; 32], index: usize) -> [Color32; 4] {
+    std::array::from_fn(|i| {
+        let entry = if i == 0 { 0 } else { index * 4 + i };
+        colors.color(palette[entry])
+    })
+}
+
+/// 2-bit color of a pattern pixel, `addr` being the tile's first byte
+pub fn pattern_pixel(chr: &[u8], addr: usize, row: usize, col: usize) -> u8 {
+    let lo = chr.get(addr + row).copied().unwrap_or(0);
+    let hi = chr.get(addr + row + 8).copied().unwrap_or(0);
+    let bit = 7 - col;
+    ((lo >> bit) & 1) | (((hi >> bit) & 1) << 1)
+}
+
+/// Draws an 8x8 tile into `out`, leaving pixels with a `None` color untouched
+pub fn draw_tile(
+    out: &mut [Color32],
+    width: usize,
+    (x, y): (usize, usize),
+    chr: &[u8],
+    addr: usize,
+    colors: [Option<Color32>; 4],
+    (flip_h, flip_v): (bool, bool),
+) {
+    for row in 0..8 {
+        for col in 0..8 {
+            let src_row = if flip_v { 7 - row } else { row };
+            let src_col = if flip_h { 7 - col } else { col };
+            let color = colors[pattern_pixel(chr, addr, src_row, src_col) as usize];
+            if let (Some(color), Some(pixel)) = (color, out.get_mut((y + row) * width + x + col)) {
+                *pixel = color;
+            }
+        }
+    }
+}
+
+pub const PATTERN_TABLE_SIZE: usize = 128;
+
+/// Renders the 16x16 tiles of the pattern table at `base` ($0000 or $1000)
+pub fn render_pattern_table(chr: &[u8], base: usize, colors: [Color32; 4]) -> Vec<Color32> {
+    let mut out = vec![Color32::BLACK; PATTERN_TABLE_SIZE * PATTERN_TABLE_SIZE];
+    for tile in 0..256 {
+        draw_tile(
+            &mut out,
+            PATTERN_TABLE_SIZE,
+            ((tile % 16) * 8, (tile / 16) * 8),
+            chr,
+            base + tile * 16,
+            colors.map(Some),
+            (false, false),
+        );
+    }
+    out
+}
+
+/// What sits under one tile of the logical nametables
+#[derive(Debug, Clone, Copy)]
+pub struct NametableTile {
+    pub nametable: usize,
+    pub x: usize,
+    pub y: usize,
+    pub tile: u8,
+    pub palette: u8,
+    /// PPU address of the tile byte
+    pub addr: u16,
+    pub attr_addr: u16,
+}
+
+/// Looks up the tile covering pixel (`px`, `py`) of the 512x480 nametable image
+pub fn nametable_tile(nametables: &[u8], px: usize, py: usize) -> Option<NametableTile> {
+    if px >= NAMETABLES_WIDTH || py >= NAMETABLES_HEIGHT {
+        return None;
+    }
+    let nametable = (py / 240) * 2 + px / 256;
+    let x = (px % 256) / 8;
+    let y = (py % 240) / 8;
+    let base = nametable * 0x400;
+    let attr_offset = ATTRIBUTE_OFFSET + (y / 4) * 8 + x / 4;
+    let attr = *nametables.get(base + attr_offset)?;
+    let shift = ((y & 2) << 1) | (x & 2);
+    Some(NametableTile {
+        nametable,
+        x,
+        y,
+        tile: *nametables.get(base + y * TILES_X + x)?,
+        palette: (attr >> shift) & 0x03,
+        addr: 0x2000 + (base + y * TILES_X + x) as u16,
+        attr_addr: 0x2000 + (base + attr_offset) as u16,
+    })
+}
+
+/// Renders the four logical nametables ($2000-$2FFF after mirroring) side by side
+pub fn render_nametables(
+    nametables: &[u8],
+    chr: &[u8],
+    palette: &[u8; 32],
+    colors: &Palette,
+    bg_pattern_table: usize,
+) -> Vec<Color32> {
+    let mut out = vec![Color32::BLACK; NAMETABLES_WIDTH * NAMETABLES_HEIGHT];
+    for py in (0..NAMETABLES_HEIGHT).step_by(8) {
+        for px in (0..NAMETABLES_WIDTH).step_by(8) {
+            let Some(tile) = nametable_tile(nametables, px, py) else {
+                continue;
+            };
+            draw_tile(
+                &mut out,
+                NAMETABLES_WIDTH,
+                (px, py),
+                chr,
+                bg_pattern_table + tile.tile as usize * 16,
+                palette_colors(colors, palette, tile.palette as usize).map(Some),
+                (false, false),
+            );
+        }
+    }
+    out
+}
+
+/// Top-left corner of the visible screen in nametable pixels, from a loopy
+/// `t`/`v` register and fine X
+pub fn scroll_origin(loopy: u16, fine_x: u8) -> (usize, usize) {
+    let coarse_x = (loopy & 0x1F) as usize;
+    let coarse_y = ((loopy >> 5) & 0x1F) as usize;
+    let nametable = ((loopy >> 10) & 0x03) as usize;
+    let fine_y = ((loopy >> 12) & 0x07) as usize;
+    (
+        (nametable & 1) * 256 + coarse_x * 8 + fine_x as usize,
+        (nametable >> 1) * 240 + coarse_y * 8 + fine_y,
+    )
+}
+
+/// Scroll a scanline was rendered with: the nametable pixel at its left edge,
+/// minus the scanline vertically so an unsplit screen reads the same on every line
+pub fn scanline_scroll(v: u16, fine_x: u8, scanline: usize) -> (usize, usize) {
+    let (x, y) = scroll_origin(v, fine_x);
+    (x, (y + NAMETABLES_HEIGHT - scanline) % NAMETABLES_HEIGHT)
+}
+
+pub const SPRITE_COUNT: usize = 64;
+/// Sprites are laid out 8 per row, each in an 8x16 cell
+pub const SPRITE_SHEET_WIDTH: usize = 8 * 8;
+pub const SPRITE_SHEET_HEIGHT: usize = 8 * 16;
+/// Hardware limit of sprites fetched per scanline
+const SPRITES_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct OamSprite {
+    /// One less than the first scanline the sprite appears on
+    pub y: u8,
+    pub tile: u8,
+    pub attributes: u8,
+    pub x: u8,
+}
+
+impl OamSprite {
+    pub fn from_oam(oam: &[u8; 256], index: usize) -> Self {
+        let entry = &oam[index * 4..index * 4 + 4];
+        Self {
+            y: entry[0],
+            tile: entry[1],
+            attributes: entry[2],
+            x: entry[3],
+        }
+    }
+
+    pub fn palette(&self) -> usize {
+        (self.attributes & 0x03) as usize + 4
+    }
+
+    pub fn behind_background(&self) -> bool {
+        self.attributes & 0x20 != 0
+    }
+
+    pub fn flip_h(&self) -> bool {
+        self.attributes & 0x40 != 0
+    }
+
+    pub fn flip_v(&self) -> bool {
+        self.attributes & 0x80 != 0
+    }
+
+    /// Pattern address of the top (or only) tile. 8x16 sprites pick their
+    /// table from bit 0 of the tile index instead of PPUCTRL.
+    pub fn pattern_addr(&self, sprite_table: usize, tall: bool) -> usize {
+        if tall {
+            (self.tile as usize & 1) * 0x1000 + (self.tile as usize & 0xFE) * 16
+        } else {
+            sprite_table + self.tile as usize * 16
+        }
+    }
+}
+
+/// Marks the sprites that lose out to 8 lower-indexed ones on at least one scanline
+pub fn sprites_over_limit(oam: &[u8; 256], tall: bool) -> [bool; SPRITE_COUNT] {
+    let height = if tall { 16 } else { 8 };
+    let mut per_line = [0usize; 240];
+    let mut dropped = [false; SPRITE_COUNT];
+    for (index, dropped) in dropped.iter_mut().enumerate() {
+        let top = OamSprite::from_oam(oam, index).y as usize + 1;
+        for line in per_line.iter_mut().skip(top).take(height) {
+            if *line == SPRITES_PER_LINE {
+                *dropped = true;
+            } else {
+                *line += 1;
+            }
+        }
+    }
+    dropped
+}
+
+/// Renders every sprite into its own 8x16 cell, transparent pixels left as `background`
+pub fn render_sprites(
+    oam: &[u8; 256],
+    chr: &[u8],
+    palette: &[u8; 32],
+    colors: &Palette,
+    sprite_table: usize,
+    tall: bool,
+    background: Color32,
+) -> Vec<Color32> {
+    let mut out = vec![background; SPRITE_SHEET_WIDTH * SPRITE_SHEET_HEIGHT];
+    for index in 0..SPRITE_COUNT {
+        let sprite = OamSprite::from_oam(oam, index);
+        let (x, y) = ((index % 8) * 8, (index / 8) * 16);
+        let mut sprite_colors = palette_colors(colors, palette, sprite.palette()).map(Some);
+        sprite_colors[0] = None;
+        let flip = (sprite.flip_h(), sprite.flip_v());
+        let addr = sprite.pattern_addr(sprite_table, tall);
+        if tall {
+            // Vertical flip also swaps the two tiles
+            let (top, bottom) = if sprite.flip_v() {
+                (addr + 16, addr)
+            } else {
+                (addr, addr + 16)
+            };
+            draw_tile(
+                &mut out,
+                SPRITE_SHEET_WIDTH,
+                (x, y),
+                chr,
+                top,
+                sprite_colors,
+                flip,
+            );
+            draw_tile(
+                &mut out,
+                SPRITE_SHEET_WIDTH,
+                (x, y + 8),
+                chr,
+                bottom,
+                sprite_colors,
+                flip,
+            );
+        } else {
+            draw_tile(
+                &mut out,
+                SPRITE_SHEET_WIDTH,
+                (x, y),
+                chr,
+                addr,
+                sprite_colors,
+                flip,
+            );
+        }
+    }
+    out
+}
@@ -0,0 +1,300 @@
+/// Where cartridge PRG RAM appears on the CPU bus
+pub const PRG_RAM_START: u16 = 0x6000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ValueSize {
+    #[default]
+    Byte,
+    /// Little endian, starting at the candidate address
+    Word,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    #[default]
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+    /// Current value minus the previous one equals the operand
+    ChangedBy,
+}
+
+impl Comparison {
+    pub const ALL: [Comparison; 5] = [
+        Comparison::Equal,
+        Comparison::NotEqual,
+        Comparison::Greater,
+        Comparison::Less,
+        Comparison::ChangedBy,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "≠",
+            Comparison::Greater => ">",
+            Comparison::Less => "<",
+            Comparison::ChangedBy => "changed by",
+        }
+    }
+}
+
+/// What the current value is compared against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Previous,
+    Value(i64),
+}
+
+impl Operand {
+    fn resolve(self, previous: i64) -> i64 {
+        match self {
+            Operand::Previous => previous,
+            Operand::Value(value) => value,
+        }
+    }
+}
+
+/// Internal RAM and PRG RAM as seen on the CPU bus
+#[derive(Clone, Copy)]
+pub struct SearchMemory<'a> {
+    pub ram: &'a [u8],
+    pub prg_ram: &'a [u8],
+}
+
+impl SearchMemory<'_> {
+    pub fn get(&self, addr: u16) -> Option<u8> {
+        if (addr as usize) < self.ram.len() {
+            Some(self.ram[addr as usize])
+        } else {
+            let offset = addr.checked_sub(PRG_RAM_START)? as usize;
+            self.prg_ram.get(offset).copied()
+        }
+    }
+
+    fn addresses(&self) -> impl Iterator<Item = u16> {
+        let ram = 0..self.ram.len() as u16;
+        let prg_ram = PRG_RAM_START..PRG_RAM_START + self.prg_ram.len() as u16;
+        ram.chain(prg_ram)
+    }
+}
+
+/// Narrows down RAM addresses by repeatedly comparing their values between
+/// snapshots, Cheat Engine style.
+#[derive(Debug, Default, Clone)]
+pub struct RamSearch {
+    pub size: ValueSize,
+    pub signed: bool,
+    candidates: Vec<u16>,
+    previous_ram: Vec<u8>,
+    previous_prg_ram: Vec<u8>,
+    /// Filters applied since the last reset
+    pub passes: usize,
+}
+
+impl RamSearch {
+    /// Makes every address a candidate again and remembers the current values
+    pub fn reset(&mut self, memory: SearchMemory) {
+        self.candidates = memory.addresses().collect();
+        self.passes = 0;
+        self.remember(memory);
+    }
+
+    fn remember(&mut self, memory: SearchMemory) {
+        self.previous_ram = memory.ram.to_vec();
+        self.previous_prg_ram = memory.prg_ram.to_vec();
+    }
+
+    fn previous_memory(&self) -> SearchMemory<'_> {
+        SearchMemory {
+            ram: &self.previous_ram,
+            prg_ram: &self.previous_prg_ram,
+        }
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    pub fn is_started(&self) -> bool {
+        !self.previous_ram.is_empty()
+    }
+
+    /// Reads the value at `addr` with the selected size and signedness
+    pub fn value(&self, memory: SearchMemory, addr: u16) -> Option<i64> {
+        let lo = memory.get(addr)?;
+        match (self.size, self.signed) {
+            (ValueSize::Byte, false) => Some(lo as i64),
+            (ValueSize::Byte, true) => Some(lo as i8 as i64),
+            (ValueSize::Word, signed) => {
+                let word = u16::from_le_bytes([lo, memory.get(addr.wrapping_add(1))?]);
+                Some(if signed {
+                    word as i16 as i64
+                } else {
+                    word as i64
+                })
+            }
+        }
+    }
+
+    pub fn previous_value(&self, addr: u16) -> Option<i64> {
+        self.value(self.previous_memory(), addr)
+    }
+
+    /// Keeps the candidates whose current value passes the comparison, then
+    /// makes the current values the baseline for the next pass.
+    pub fn filter(&mut self, memory: SearchMemory, comparison: Comparison, operand: Operand) {
+        if !self.is_started() {
+            self.reset(memory);
+        }
+        let candidates = std::mem::take(&mut self.candidates);
+        let previous = self.previous_memory();
+        let kept = candidates
+            .into_iter()
+            .filter(|&addr| {
+                let (Some(current), Some(previous)) =
+                    (self.value(memory, addr), self.value(previous, addr))
+                else {
+                    return false;
+                };
+                match (comparison, operand) {
+                    // Against the previous value, this just means "changed"
+                    (Comparison::ChangedBy, Operand::Previous) => current != previous,
+                    (Comparison::ChangedBy, Operand::Value(delta)) => current - previous == delta,
+                    (Comparison::Equal, _) => current == operand.resolve(previous),
+                    (Comparison::NotEqual, _) => current != operand.resolve(previous),
+                    (Comparison::Greater, _) => current > operand.resolve(previous),
+                    (Comparison::Less, _) => current < operand.resolve(previous),
+                }
+            })
+            .collect();
+        self.candidates = kept;
+        self.passes += 1;
+        self.remember(memory);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 8 bytes of RAM and 4 of PRG RAM
+    struct Fixture {
+        ram: [u8; 8],
+        prg_ram: [u8; 4],
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            Self {
+                ram: [0; 8],
+                prg_ram: [0; 4],
+            }
+        }
+
+        fn memory(&self) -> SearchMemory<'_> {
+            SearchMemory {
+                ram: &self.ram,
+                prg_ram: &self.prg_ram,
+            }
+        }
+    }
+
+    #[test]
+    fn memory_map() {
+        let mut fixture = Fixture::new();
+        fixture.ram[7] = 1;
+        fixture.prg_ram[0] = 2;
+        let memory = fixture.memory();
+        assert_eq!(memory.get(0x0007), Some(1));
+        assert_eq!(memory.get(0x0008), None);
+        assert_eq!(memory.get(0x6000), Some(2));
+        assert_eq!(memory.get(0x6004), None);
+
+        let mut search = RamSearch::default();
+        assert!(!search.is_started());
+        search.reset(memory);
+        assert!(search.is_started());
+        assert_eq!(search.candidates().len(), 12);
+        assert_eq!(search.candidates()[8], 0x6000);
+    }
+
+    #[test]
+    fn equal_and_not_equal() {
+        let mut fixture = Fixture::new();
+        fixture.ram[2] = 5;
+        fixture.prg_ram[1] = 5;
+        let mut search = RamSearch::default();
+        search.filter(fixture.memory(), Comparison::Equal, Operand::Value(5));
+        assert_eq!(search.candidates(), [0x0002, 0x6001]);
+        assert_eq!(search.passes, 1);
+
+        fixture.ram[2] = 6;
+        search.filter(fixture.memory(), Comparison::NotEqual, Operand::Previous);
+        assert_eq!(search.candidates(), [0x0002]);
+        assert_eq!(search.previous_value(0x0002), Some(6));
+    }
+
+    #[test]
+    fn greater_and_less_than_before() {
+        let mut fixture = Fixture::new();
+        fixture.ram = [5; 8];
+        fixture.prg_ram = [5; 4];
+        let mut search = RamSearch::default();
+        search.reset(fixture.memory());
+        fixture.ram[0] = 6;
+        fixture.ram[1] = 4;
+        search.filter(fixture.memory(), Comparison::Greater, Operand::Previous);
+        assert_eq!(search.candidates(), [0x0000]);
+
+        search.reset(fixture.memory());
+        search.filter(fixture.memory(), Comparison::Less, Operand::Value(5));
+        assert_eq!(search.candidates(), [0x0001]);
+    }
+
+    #[test]
+    fn changed_by() {
+        let mut fixture = Fixture::new();
+        fixture.ram = [10; 8];
+        let mut search = RamSearch::default();
+        search.reset(fixture.memory());
+        fixture.ram[0] = 7;
+        fixture.ram[1] = 13;
+        fixture.ram[2] = 11;
+        search.filter(fixture.memory(), Comparison::ChangedBy, Operand::Value(-3));
+        assert_eq!(search.candidates(), [0x0000]);
+
+        search.reset(fixture.memory());
+        fixture.ram[5] = 0;
+        search.filter(fixture.memory(), Comparison::ChangedBy, Operand::Previous);
+        assert_eq!(search.candidates(), [0x0005]);
+    }
+
+    #[test]
+    fn signed_and_word_values() {
+        let mut fixture = Fixture::new();
+        fixture.ram[0] = 0xFF;
+        fixture.ram[1] = 0x80;
+        let mut search = RamSearch {
+            signed: true,
+            ..Default::default()
+        };
+        assert_eq!(search.value(fixture.memory(), 0), Some(-1));
+        search.filter(fixture.memory(), Comparison::Less, Operand::Value(0));
+        assert_eq!(search.candidates(), [0x0000, 0x0001]);
+
+        search.size = ValueSize::Word;
+        assert_eq!(
+            search.value(fixture.memory(), 0),
+            Some(0x80FFu16 as i16 as i64)
+        );
+        search.signed = false;
+        assert_eq!(search.value(fixture.memory(), 0), Some(0x80FF));
+        // A word needs both its bytes, so the last byte of each area has none
+        assert_eq!(search.value(fixture.memory(), 0x0007), None);
+        search.reset(fixture.memory());
+        search.filter(fixture.memory(), Comparison::Equal, Operand::Previous);
+        assert_eq!(search.candidates().len(), 10);
+    }
+}
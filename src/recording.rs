@@ -0,0 +1,513 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::palette::Palette;
+use crate::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+
+/// NTSC frame rate as a fraction, 60.0988 Hz
+const FRAME_RATE: (u32, u32) = (39_375_000, 655_171);
+/// GIF delays are in hundredths of a second, too coarse for 60 fps, so every other frame is kept
+const GIF_FRAME_STEP: u64 = 2;
+/// The frame count isn't known until the recording stops, so the largest one
+/// PNG allows goes in the header until then
+const APNG_FRAMES_PLACEHOLDER: u32 = i32::MAX as u32;
+/// acTL comes straight after the 8 byte signature and the 25 byte IHDR chunk
+const APNG_ACTL_OFFSET: u64 = 33;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// Lossless animated PNG
+    Apng,
+    /// Animated GIF at half the frame rate
+    Gif,
+    /// Uncompressed Y4M video next to a WAV file with the audio, for offline encoding
+    Y4m,
+}
+
+impl RecordingFormat {
+    pub const ALL: [RecordingFormat; 3] = [
+        RecordingFormat::Apng,
+        RecordingFormat::Gif,
+        RecordingFormat::Y4m,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RecordingFormat::Apng => "Animated PNG",
+            RecordingFormat::Gif => "GIF",
+            RecordingFormat::Y4m => "Y4M + WAV",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            RecordingFormat::Apng => "png",
+            RecordingFormat::Gif => "gif",
+            RecordingFormat::Y4m => "y4m",
+        }
+    }
+}
+
+enum Output {
+    Apng(ApngWriter),
+    Gif(GifWriter),
+    Y4m {
+        video: BufWriter<File>,
+        audio: WavWriter,
+    },
+}
+
+/// Writes every emulated frame, and the audio for Y4M, between start and stop
+pub struct Recorder {
+    path: PathBuf,
+    palette: Palette,
+    output: Output,
+    frames: u64,
+}
+
+impl Recorder {
+    pub fn start(
+        format: RecordingFormat,
+        path: PathBuf,
+        palette: Palette,
+        sample_rate: u32,
+    ) -> Result<Self> {
+        let file = create(&path)?;
+        let output = match format {
+            RecordingFormat::Apng => Output::Apng(ApngWriter::new(file)?),
+            RecordingFormat::Gif => Output::Gif(GifWriter::new(file)?),
+            RecordingFormat::Y4m => {
+                let mut video = file;
+                writeln!(
+                    video,
+                    "YUV4MPEG2 W{FRAME_WIDTH} H{FRAME_HEIGHT} F{}:{} Ip A1:1 C444",
+                    FRAME_RATE.0, FRAME_RATE.1
+                )?;
+                let audio = WavWriter::new(create(&path.with_extension("wav"))?, sample_rate)?;
+                Output::Y4m { video, audio }
+            }
+        };
+        Ok(Self {
+            path,
+            palette,
+            output,
+            frames: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Takes one finished frame of 9-bit palette indices
+    pub fn push_frame(&mut self, screen: &[u16]) -> Result<()> {
+        let frame = self.frames;
+        self.frames += 1;
+        match &mut self.output {
+            Output::Apng(apng) => apng.write_frame(&to_rgb(screen, &self.palette))?,
+            Output::Gif(gif) => {
+                if frame.is_multiple_of(GIF_FRAME_STEP) {
+                    // Delays alternate so they add up to the real frame rate
+                    let time = |frame: u64| frame * 100 * FRAME_RATE.1 as u64 / FRAME_RATE.0 as u64;
+                    let delay = time(frame + GIF_FRAME_STEP) - time(frame);
+                    gif.write_frame(screen, &self.palette, delay as u16)?;
+                }
+            }
+            Output::Y4m { video, .. } => {
+                video.write_all(b"FRAME\n")?;
+                let yuv: Vec<[u8; 3]> = screen
+                    .iter()
+                    .map(|&pixel| {
+                        let c = self.palette.emphasized(pixel);
+                        rgb_to_yuv(c.r(), c.g(), c.b())
+                    })
+                    .collect();
+                for plane in 0..3 {
+                    let bytes: Vec<u8> = yuv.iter().map(|p| p[plane]).collect();
+                    video.write_all(&bytes)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn push_sample(&mut self, sample: f32) {
+        if let Output::Y4m { audio, .. } = &mut self.output {
+            audio.push(sample);
+        }
+    }
+
+    /// Flushes everything to disk, returning how many frames were recorded
+    pub fn finish(self) -> Result<u64> {
+        match self.output {
+            Output::Apng(apng) => apng.finish(self.frames)?,
+            Output::Gif(gif) => gif.finish()?,
+            Output::Y4m { mut video, audio } => {
+                video.flush()?;
+                audio.finish()?;
+            }
+        }
+        Ok(self.frames)
+    }
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>> {
+    let file = File::create(path).with_context(|| format!("Couldn't create {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+fn to_rgb(screen: &[u16], palette: &Palette) -> Vec<u8> {
+    screen
+        .iter()
+        .flat_map(|&pixel| {
+            let c = palette.emphasized(pixel);
+            [c.r(), c.g(), c.b()]
+        })
+        .collect()
+}
+
+/// BT.601 limited range, what encoders assume for standard definition Y4M
+fn rgb_to_yuv(r: u8, g: u8, b: u8) -> [u8; 3] {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    [y as u8, u as u8, v as u8]
+}
+
+/// Streams APNG frames, with the frame count in acTL filled in once it's known
+struct ApngWriter {
+    writer: png::Writer<BufWriter<File>>,
+    /// Second handle to the same file, for patching the header after the
+    /// encoder is done with it
+    file: File,
+}
+
+impl ApngWriter {
+    fn new(file: BufWriter<File>) -> Result<Self> {
+        let handle = file.get_ref().try_clone()?;
+        let mut encoder = png::Encoder::new(file, FRAME_WIDTH as u32, FRAME_HEIGHT as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(APNG_FRAMES_PLACEHOLDER, 0)?;
+        // Both halves of the delay fraction are 16 bits, 1000/60099 is as close as they get
+        encoder.set_frame_delay(1000, 60_099)?;
+        Ok(Self {
+            writer: encoder.write_header()?,
+            file: handle,
+        })
+    }
+
+    fn write_frame(&mut self, rgb: &[u8]) -> Result<()> {
+        self.writer.write_image_data(rgb)?;
+        Ok(())
+    }
+
+    fn finish(mut self, frames: u64) -> Result<()> {
+        self.writer.finish()?;
+        if frames == 0 {
+            bail!("No frames were recorded");
+        }
+        // num_frames then num_plays, 0 for looping forever
+        let mut data = [0; 8];
+        data[..4]
+            .copy_from_slice(&(frames.min(APNG_FRAMES_PLACEHOLDER as u64) as u32).to_be_bytes());
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(b"acTL");
+        crc.update(&data);
+        self.file.seek(SeekFrom::Start(APNG_ACTL_OFFSET + 8))?;
+        self.file.write_all(&data)?;
+        self.file.write_all(&crc.finalize().to_be_bytes())?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Streams GIF89a frames, each with its own color table of the colors it uses
+struct GifWriter {
+    file: BufWriter<File>,
+}
+
+impl GifWriter {
+    fn new(mut file: BufWriter<File>) -> Result<Self> {
+        file.write_all(b"GIF89a")?;
+        file.write_all(&(FRAME_WIDTH as u16).to_le_bytes())?;
+        file.write_all(&(FRAME_HEIGHT as u16).to_le_bytes())?;
+        // No global color table, background color 0, square pixels
+        file.write_all(&[0x00, 0x00, 0x00])?;
+        // Loop forever
+        file.write_all(&[0x21, 0xFF, 0x0B])?;
+        file.write_all(b"NETSCAPE2.0")?;
+        file.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])?;
+        Ok(Self { file })
+    }
+
+    fn write_frame(&mut self, screen: &[u16], palette: &Palette, delay: u16) -> Result<()> {
+        // A frame rarely has more than a few dozen colors, but emphasis changes
+        // mid-frame could push it past 256, the rest then use the closest one
+        let mut colors: Vec<u16> = Vec::new();
+        let mut lookup = [None::<u8>; 512];
+        let mut indices = Vec::with_capacity(screen.len());
+        for &pixel in screen {
+            let pixel = pixel & 0x1FF;
+            let index = match lookup[pixel as usize] {
+                Some(index) => index,
+                None if colors.len() < 256 => {
+                    colors.push(pixel);
+                    let index = (colors.len() - 1) as u8;
+                    lookup[pixel as usize] = Some(index);
+                    index
+                }
+                None => closest_color(&colors, pixel, palette),
+            };
+            indices.push(index);
+        }
+
+        let table_bits = (colors.len().max(2) as u32)
+            .next_power_of_two()
+            .trailing_zeros();
+        let file = &mut self.file;
+        // Graphic control extension with the delay, no transparency
+        file.write_all(&[0x21, 0xF9, 0x04, 0x00])?;
+        file.write_all(&delay.to_le_bytes())?;
+        file.write_all(&[0x00, 0x00])?;
+        // Image descriptor covering the whole screen with a local color table
+        file.write_all(&[0x2C, 0x00, 0x00, 0x00, 0x00])?;
+        file.write_all(&(FRAME_WIDTH as u16).to_le_bytes())?;
+        file.write_all(&(FRAME_HEIGHT as u16).to_le_bytes())?;
+        file.write_all(&[0x80 | (table_bits - 1) as u8])?;
+        for i in 0..1 << table_bits {
+            let c = colors
+                .get(i)
+                .map(|&pixel| palette.emphasized(pixel))
+                .unwrap_or_default();
+            file.write_all(&[c.r(), c.g(), c.b()])?;
+        }
+
+        let min_code_size = table_bits.max(2) as u8;
+        let data = weezl::encode::Encoder::new(weezl::BitOrder::Lsb, min_code_size)
+            .encode(&indices)
+            .context("Couldn't compress GIF frame")?;
+        file.write_all(&[min_code_size])?;
+        for block in data.chunks(255) {
+            file.write_all(&[block.len() as u8])?;
+            file.write_all(block)?;
+        }
+        file.write_all(&[0x00])?;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        self.file.write_all(&[0x3B])?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+fn closest_color(colors: &[u16], pixel: u16, palette: &Palette) -> u8 {
+    let target = palette.emphasized(pixel);
+    let distance = |&(_, &other): &(usize, &u16)| {
+        let c = palette.emphasized(other);
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(c.r(), target.r()) + d(c.g(), target.g()) + d(c.b(), target.b())
+    };
+    colors
+        .iter()
+        .enumerate()
+        .min_by_key(distance)
+        .map_or(0, |(i, _)| i as u8)
+}
+
+/// 16-bit mono PCM, with the chunk sizes filled in once the length is known
+struct WavWriter {
+    file: BufWriter<File>,
+    samples: u32,
+}
+
+impl WavWriter {
+    fn new(mut file: BufWriter<File>, sample_rate: u32) -> Result<Self> {
+        file.write_all(b"RIFF")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // PCM, 1 channel
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * 2).to_le_bytes())?;
+        // 2 bytes per frame, 16 bits per sample
+        file.write_all(&2u16.to_le_bytes())?;
+        file.write_all(&16u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        Ok(Self { file, samples: 0 })
+    }
+
+    fn push(&mut self, sample: f32) {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        if self.file.write_all(&value.to_le_bytes()).is_ok() {
+            self.samples += 1;
+        }
+    }
+
+    fn finish(mut self) -> Result<()> {
+        let data_size = self.samples * 2;
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(36 + data_size).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&data_size.to_le_bytes())?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Read};
+
+    use super::*;
+
+    /// A file in the temp directory, removed again when the test is done
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!("nessie-{}-{name}", std::process::id())))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// Frames that differ from each other, with emphasis on the last one
+    fn frames(count: u16) -> Vec<Vec<u16>> {
+        (0..count)
+            .map(|i| {
+                (0..FRAME_WIDTH * FRAME_HEIGHT)
+                    .map(|pixel| ((pixel as u16 + i) % 0x40) | ((i / 3) << 6))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn record(format: RecordingFormat, file: &TempFile, frames: &[Vec<u16>]) -> u64 {
+        let mut recorder =
+            Recorder::start(format, file.0.clone(), Palette::default(), 44_100).unwrap();
+        for frame in frames {
+            recorder.push_frame(frame).unwrap();
+        }
+        recorder.finish().unwrap()
+    }
+
+    #[test]
+    fn apng_round_trip() {
+        let file = TempFile::new("round_trip.png");
+        let frames = frames(4);
+        assert_eq!(record(RecordingFormat::Apng, &file, &frames), 4);
+
+        let decoder = png::Decoder::new(BufReader::new(File::open(&file.0).unwrap()));
+        let mut reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().animation_control.unwrap().num_frames, 4);
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        for frame in &frames {
+            reader.next_frame(&mut buf).unwrap();
+            assert!(buf == to_rgb(frame, &Palette::default()));
+        }
+        assert!(reader.next_frame(&mut buf).is_err());
+    }
+
+    #[test]
+    fn gif_round_trip() {
+        let file = TempFile::new("round_trip.gif");
+        let frames = frames(4);
+        assert_eq!(record(RecordingFormat::Gif, &file, &frames), 4);
+
+        let mut bytes = Vec::new();
+        File::open(&file.0)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(&bytes[..6], b"GIF89a");
+        assert_eq!(bytes[6..10], [0x00, 0x01, 0xF0, 0x00]);
+        // Past the logical screen descriptor and the looping extension
+        let mut pos = 13 + 19;
+        let mut decoded = Vec::new();
+        while bytes[pos] != 0x3B {
+            assert_eq!(bytes[pos..pos + 4], [0x21, 0xF9, 0x04, 0x00]);
+            let delay = u16::from_le_bytes([bytes[pos + 4], bytes[pos + 5]]);
+            pos += 8;
+            assert_eq!(bytes[pos], 0x2C);
+            let table_len = 2 << (bytes[pos + 9] & 0x07);
+            pos += 10;
+            let table = &bytes[pos..pos + table_len * 3];
+            pos += table_len * 3;
+            let min_code_size = bytes[pos];
+            pos += 1;
+            let mut data = Vec::new();
+            while bytes[pos] != 0 {
+                let len = bytes[pos] as usize;
+                data.extend_from_slice(&bytes[pos + 1..pos + 1 + len]);
+                pos += 1 + len;
+            }
+            pos += 1;
+            let indices = weezl::decode::Decoder::new(weezl::BitOrder::Lsb, min_code_size)
+                .decode(&data)
+                .unwrap();
+            let rgb: Vec<u8> = indices
+                .iter()
+                .flat_map(|&i| table[i as usize * 3..i as usize * 3 + 3].to_vec())
+                .collect();
+            decoded.push((delay, rgb));
+        }
+
+        // Every other frame, with delays adding up to 2 frames at 60.0988 Hz
+        assert_eq!(decoded.len(), 2);
+        for ((delay, rgb), frame) in decoded.iter().zip(frames.iter().step_by(2)) {
+            assert_eq!(*delay, 3);
+            assert!(*rgb == to_rgb(frame, &Palette::default()));
+        }
+    }
+
+    #[test]
+    fn wav_chunk_sizes() {
+        let file = TempFile::new("chunk_sizes.wav");
+        let mut wav = WavWriter::new(create(&file.0).unwrap(), 44_100).unwrap();
+        for sample in [0.0, 1.0, -1.0, 2.0, -0.5] {
+            wav.push(sample);
+        }
+        wav.finish().unwrap();
+
+        let mut bytes = Vec::new();
+        File::open(&file.0)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        let u32_at = |pos: usize| u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap());
+        assert_eq!(bytes.len(), 44 + 5 * 2);
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(u32_at(4), bytes.len() as u32 - 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(24), 44_100);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(40), 5 * 2);
+        let samples: Vec<i16> = bytes[44..]
+            .chunks(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(samples, [0, 32767, -32767, 32767, -16383]);
+    }
+
+    #[test]
+    fn bt601_limited_range() {
+        assert_eq!(rgb_to_yuv(0, 0, 0), [16, 128, 128]);
+        assert_eq!(rgb_to_yuv(255, 255, 255), [235, 128, 128]);
+        assert_eq!(rgb_to_yuv(255, 0, 0), [82, 90, 240]);
+        assert_eq!(rgb_to_yuv(0, 0, 255), [41, 240, 110]);
+    }
+}
@@ -0,0 +1,941 @@
+use egui::Color32;
+
+/// Neighbour offsets and output coordinates are worked out for the bottom-right
+/// corner of a pixel, then turned a quarter at a time to cover the other three
+#[derive(Clone, Copy)]
+struct Rotation(u8);
+
+impl Rotation {
+    const ALL: [Rotation; 4] = [Rotation(0), Rotation(1), Rotation(2), Rotation(3)];
+
+    fn apply(self, dx: isize, dy: isize) -> (isize, isize) {
+        match self.0 {
+            0 => (dx, dy),
+            1 => (-dy, dx),
+            2 => (-dx, -dy),
+            _ => (dy, -dx),
+        }
+    }
+
+    /// Index of the corner (-1 or 1 on each axis) in `[top left, top right, bottom right, bottom left]`
+    fn corner(self, dx: isize, dy: isize) -> usize {
+        match self.apply(dx, dy) {
+            (-1, -1) => 0,
+            (1, -1) => 1,
+            (1, 1) => 2,
+            _ => 3,
+        }
+    }
+
+    /// Position in a `scale` sized block of the output pixel at (`sx`, `sy`) before rotating
+    fn output(self, sx: usize, sy: usize, scale: usize) -> (usize, usize) {
+        let center = |s: usize| 2 * s as isize - (scale as isize - 1);
+        let (x, y) = self.apply(center(sx), center(sy));
+        let back = |c: isize| ((c + scale as isize - 1) / 2) as usize;
+        (back(x), back(y))
+    }
+}
+
+/// Reads pixels with coordinates clamped to the image, repeating the edges
+struct Source<'a> {
+    pixels: &'a [Color32],
+    width: usize,
+    height: usize,
+}
+
+impl Source<'_> {
+    #[inline]
+    fn get(&self, x: isize, y: isize) -> Color32 {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    /// The 3x3 neighbourhood of (`x`, `y`) turned by `rotation`, row by row
+    fn kernel(&self, x: usize, y: usize, rotation: Rotation) -> [Color32; 9] {
+        std::array::from_fn(|i| {
+            let (dx, dy) = rotation.apply(i as isize % 3 - 1, i as isize / 3 - 1);
+            self.get(x as isize + dx, y as isize + dy)
+        })
+    }
+}
+
+/// Copies every pixel into a `scale` sized block
+pub fn nearest(pixels: &[Color32], width: usize, height: usize, scale: usize) -> Vec<Color32> {
+    let src = Source {
+        pixels,
+        width,
+        height,
+    };
+    nearest_source(&src, scale)
+}
+
+fn nearest_source(src: &Source, scale: usize) -> Vec<Color32> {
+    let out_width = src.width * scale;
+    let mut out = vec![Color32::BLACK; out_width * src.height * scale];
+    for (i, row) in out.chunks_exact_mut(out_width).enumerate() {
+        let src_row = &src.pixels[(i / scale) * src.width..][..src.width];
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = src_row[x / scale];
+        }
+    }
+    out
+}
+
+/// Scale2x (AdvMAME2x): copies a neighbour into a corner where two edges meet
+pub fn scale2x(pixels: &[Color32], width: usize, height: usize) -> Vec<Color32> {
+    let src = Source {
+        pixels,
+        width,
+        height,
+    };
+    let mut out = nearest_source(&src, 2);
+    for y in 0..height {
+        for x in 0..width {
+            for rotation in Rotation::ALL {
+                let [_, b, _, d, _, f, _, h, _] = src.kernel(x, y, rotation);
+                if h == f && h != d && f != b {
+                    let (ox, oy) = rotation.output(1, 1, 2);
+                    out[(y * 2 + oy) * width * 2 + x * 2 + ox] = f;
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Scale3x (AdvMAME3x), Scale2x's rules extended to the edge pixels of a 3x3 block
+pub fn scale3x(pixels: &[Color32], width: usize, height: usize) -> Vec<Color32> {
+    let src = Source {
+        pixels,
+        width,
+        height,
+    };
+    let mut out = nearest_source(&src, 3);
+    let out_width = width * 3;
+    for y in 0..height {
+        for x in 0..width {
+            for rotation in Rotation::ALL {
+                let [_, b, c, d, e, f, _, h, i] = src.kernel(x, y, rotation);
+                let mut set = |sx: usize, sy: usize, color: Color32| {
+                    let (ox, oy) = rotation.output(sx, sy, 3);
+                    out[(y * 3 + oy) * out_width + x * 3 + ox] = color;
+                };
+                let bottom_right = h == f && d != h && b != f;
+                if bottom_right {
+                    set(2, 2, f);
+                }
+                // The right edge, shared with the top-right corner's rules
+                if (b == f && b != d && f != h && e != i) || (bottom_right && e != c) {
+                    set(2, 1, f);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// YUV difference test of hqx: luma 48, U 7, V 6 apart on a 0-255 scale, each
+/// component truncated the way hqx's conversion table holds it
+fn hq_differ(a: Color32, b: Color32) -> bool {
+    let yuv = |c: Color32| {
+        let (r, g, b) = (c.r() as f64, c.g() as f64, c.b() as f64);
+        [
+            (0.299 * r + 0.587 * g + 0.114 * b) as i32,
+            (-0.169 * r - 0.331 * g + 0.5 * b) as i32,
+            (0.5 * r - 0.419 * g - 0.081 * b) as i32,
+        ]
+    };
+    let ([ya, ua, va], [yb, ub, vb]) = (yuv(a), yuv(b));
+    (ya - yb).abs() > 48 || (ua - ub).abs() > 7 || (va - vb).abs() > 6
+}
+
+/// hqx's interpolations: a weighted average with the weights adding up to a
+/// power of two, rounded down per channel
+fn hq_mix<const N: usize>(parts: [(Color32, u32); N]) -> Color32 {
+    let total: u32 = parts.iter().map(|&(_, weight)| weight).sum();
+    let channel = |get: fn(&Color32) -> u8| {
+        let sum: u32 = parts
+            .iter()
+            .map(|&(c, weight)| get(&c) as u32 * weight)
+            .sum();
+        (sum / total) as u8
+    };
+    Color32::from_rgb(
+        channel(Color32::r),
+        channel(Color32::g),
+        channel(Color32::b),
+    )
+}
+
+/// What hqx does with the top-left corner of a pixel `e`, where `a` is the
+/// neighbour across the corner, `b` the one above and `d` the one to the left.
+/// The `...OrCorner` rules and the ones without it only differ when `b` and `d`
+/// don't look alike: the corner then takes a little of `a` or stays `e`
+#[derive(Debug, Clone, Copy)]
+enum HqRule {
+    /// Blends in `b` and `d`
+    Edges,
+    /// Blends in `a` and `b`
+    CornerTop,
+    /// Blends in `a` and `d`
+    CornerLeft,
+    /// Blends in `a` alone
+    Corner,
+    /// Blends in `b` alone
+    Top,
+    /// Blends in `d` alone
+    Left,
+    /// A shallow line along the top if `b` and `f` look alike, otherwise `Left`
+    Shallow,
+    /// A steep line down the left if `d` and `h` look alike, otherwise `Top`
+    Steep,
+    /// A line cutting across the corner when `b` and `d` look alike
+    Sharp,
+    SharpOrCorner,
+    /// A corner only slightly rounded off
+    SoftOrCorner,
+    /// The corner of a lone pixel, barely rounded
+    Round,
+    /// A line across the corner that carries on along the top
+    LongTop,
+    LongTopOrCorner,
+    /// A line across the corner that carries on down the left
+    LongLeft,
+    LongLeftOrCorner,
+}
+
+/// hqx's rule for each pattern of neighbours that look different from the
+/// pixel, with `a` in bit 0 and then `b`, `c`, `d`, `f`, `g`, `h`, `i`
+#[rustfmt::skip]
+const HQ_RULES: [HqRule; 256] = {
+    use HqRule::*;
+    [
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Left,
+    CornerTop,         Top,               SharpOrCorner,     Sharp,             CornerTop,         Top,               LongTopOrCorner,   LongTop,
+    Edges,             Edges,             CornerLeft,        Shallow,           Edges,             Edges,             CornerLeft,        Shallow,
+    CornerTop,         Top,               Sharp,             Sharp,             CornerTop,         Top,               Corner,            Sharp,
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Left,
+    CornerTop,         Top,               LongLeftOrCorner,  LongLeft,          CornerTop,         Top,               SoftOrCorner,      Round,
+    Edges,             Edges,             CornerLeft,        Shallow,           Edges,             Edges,             CornerLeft,        Shallow,
+    CornerTop,         Top,               SoftOrCorner,      Sharp,             CornerTop,         Top,               Corner,            Round,
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Left,
+    CornerTop,         Steep,             Sharp,             Sharp,             CornerTop,         Steep,             SoftOrCorner,      Sharp,
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Left,
+    CornerTop,         Top,               SoftOrCorner,      Sharp,             CornerTop,         Top,               SoftOrCorner,      Sharp,
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Left,
+    CornerTop,         Steep,             Corner,            Sharp,             CornerTop,         Steep,             Corner,            Round,
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Shallow,
+    CornerTop,         Top,               SoftOrCorner,      Sharp,             CornerTop,         Steep,             Corner,            Round,
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Left,
+    CornerTop,         Top,               SharpOrCorner,     Sharp,             CornerTop,         Top,               LongTopOrCorner,   LongTop,
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Left,
+    CornerTop,         Top,               SoftOrCorner,      Sharp,             CornerTop,         Top,               SoftOrCorner,      Sharp,
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Left,
+    CornerTop,         Top,               LongLeftOrCorner,  LongLeft,          CornerTop,         Top,               SoftOrCorner,      Round,
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Left,
+    CornerTop,         Top,               SoftOrCorner,      LongLeft,          CornerTop,         Top,               Corner,            Round,
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Left,
+    CornerTop,         Top,               SoftOrCorner,      Sharp,             CornerTop,         Top,               SoftOrCorner,      LongTop,
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Left,
+    CornerTop,         Top,               SoftOrCorner,      Sharp,             CornerTop,         Top,               Corner,            Sharp,
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Left,
+    CornerTop,         Top,               SoftOrCorner,      Sharp,             CornerTop,         Top,               Corner,            Round,
+    Edges,             Edges,             CornerLeft,        Left,              Edges,             Edges,             CornerLeft,        Left,
+    CornerTop,         Top,               Corner,            Sharp,             CornerTop,         Top,               Corner,            Round,
+    ]
+};
+
+/// An [`HqRule`] with its condition settled
+#[derive(Debug, Clone, Copy)]
+enum HqShape {
+    Edges,
+    CornerTop,
+    CornerLeft,
+    Corner,
+    Top,
+    Left,
+    Pixel,
+    ShallowLine,
+    SteepLine,
+    Sharp,
+    Soft,
+    Round,
+    LongTop,
+    LongLeft,
+}
+
+impl HqRule {
+    fn shape(self, [_, b, _, d, _, f, _, h, _]: [Color32; 9]) -> HqShape {
+        let apart = |shape: HqShape, or_corner: bool| {
+            if !hq_differ(b, d) {
+                shape
+            } else if or_corner {
+                HqShape::Corner
+            } else {
+                HqShape::Pixel
+            }
+        };
+        match self {
+            HqRule::Edges => HqShape::Edges,
+            HqRule::CornerTop => HqShape::CornerTop,
+            HqRule::CornerLeft => HqShape::CornerLeft,
+            HqRule::Corner => HqShape::Corner,
+            HqRule::Top => HqShape::Top,
+            HqRule::Left => HqShape::Left,
+            HqRule::Shallow if hq_differ(b, f) => HqShape::Left,
+            HqRule::Shallow => HqShape::ShallowLine,
+            HqRule::Steep if hq_differ(d, h) => HqShape::Top,
+            HqRule::Steep => HqShape::SteepLine,
+            HqRule::Sharp => apart(HqShape::Sharp, false),
+            HqRule::SharpOrCorner => apart(HqShape::Sharp, true),
+            HqRule::SoftOrCorner => apart(HqShape::Soft, true),
+            HqRule::Round => apart(HqShape::Round, false),
+            HqRule::LongTop => apart(HqShape::LongTop, false),
+            HqRule::LongTopOrCorner => apart(HqShape::LongTop, true),
+            HqRule::LongLeft => apart(HqShape::LongLeft, false),
+            HqRule::LongLeftOrCorner => apart(HqShape::LongLeft, true),
+        }
+    }
+}
+
+/// How much of its neighbour an edge pixel between two corners takes in hq3x,
+/// from none to mostly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum HqEdge {
+    Keep,
+    Faint,
+    Blend,
+    Line,
+}
+
+impl HqEdge {
+    fn color(self, e: Color32, n: Color32) -> Color32 {
+        match self {
+            HqEdge::Keep => e,
+            HqEdge::Faint => hq_mix([(e, 7), (n, 1)]),
+            HqEdge::Blend => hq_mix([(e, 3), (n, 1)]),
+            HqEdge::Line => hq_mix([(n, 3), (e, 1)]),
+        }
+    }
+}
+
+impl HqShape {
+    /// The top-left pixel of a 2x2 block
+    fn hq2x(self, [a, b, _, d, e, _, _, _, _]: [Color32; 9]) -> Color32 {
+        match self {
+            HqShape::Edges | HqShape::Sharp => hq_mix([(e, 2), (d, 1), (b, 1)]),
+            HqShape::CornerTop => hq_mix([(e, 2), (a, 1), (b, 1)]),
+            HqShape::CornerLeft => hq_mix([(e, 2), (a, 1), (d, 1)]),
+            HqShape::Corner => hq_mix([(e, 3), (a, 1)]),
+            HqShape::Top => hq_mix([(e, 3), (b, 1)]),
+            HqShape::Left => hq_mix([(e, 3), (d, 1)]),
+            HqShape::Pixel => e,
+            HqShape::ShallowLine => hq_mix([(e, 5), (b, 2), (d, 1)]),
+            HqShape::SteepLine => hq_mix([(e, 5), (d, 2), (b, 1)]),
+            HqShape::Soft => hq_mix([(e, 6), (d, 1), (b, 1)]),
+            HqShape::Round => hq_mix([(e, 14), (d, 1), (b, 1)]),
+            HqShape::LongTop | HqShape::LongLeft => hq_mix([(e, 2), (d, 3), (b, 3)]),
+        }
+    }
+
+    /// The top-left pixel of a 3x3 block and what it asks of the edge pixels
+    /// to its right and below
+    fn hq3x(self, [a, b, _, d, e, _, _, _, _]: [Color32; 9]) -> (Color32, HqEdge, HqEdge) {
+        let keep = HqEdge::Keep;
+        match self {
+            HqShape::Edges | HqShape::Soft | HqShape::Round => {
+                (hq_mix([(e, 2), (d, 1), (b, 1)]), keep, keep)
+            }
+            HqShape::CornerTop | HqShape::CornerLeft | HqShape::Corner => {
+                (hq_mix([(e, 3), (a, 1)]), keep, keep)
+            }
+            HqShape::Top => (hq_mix([(e, 3), (b, 1)]), keep, keep),
+            HqShape::Left => (hq_mix([(e, 3), (d, 1)]), keep, keep),
+            HqShape::Pixel => (e, keep, keep),
+            HqShape::ShallowLine => (hq_mix([(e, 2), (d, 1), (b, 1)]), HqEdge::Line, keep),
+            HqShape::SteepLine => (hq_mix([(e, 2), (d, 1), (b, 1)]), keep, HqEdge::Line),
+            HqShape::Sharp => (
+                hq_mix([(e, 2), (d, 7), (b, 7)]),
+                HqEdge::Faint,
+                HqEdge::Faint,
+            ),
+            HqShape::LongTop => (hq_mix([(d, 1), (b, 1)]), HqEdge::Line, HqEdge::Blend),
+            HqShape::LongLeft => (hq_mix([(d, 1), (b, 1)]), HqEdge::Blend, HqEdge::Line),
+        }
+    }
+
+    /// The top-left 2x2 pixels of a 4x4 block, row by row
+    fn hq4x(self, [a, b, _, d, e, _, _, _, _]: [Color32; 9]) -> [Color32; 4] {
+        let mix1 = |x, y| hq_mix([(x, 3), (y, 1)]);
+        let mix3 = |x, y| hq_mix([(x, 7), (y, 1)]);
+        let mix5 = |x, y| hq_mix([(x, 1), (y, 1)]);
+        let mix8 = |x, y| hq_mix([(x, 5), (y, 3)]);
+        match self {
+            HqShape::Edges => [
+                hq_mix([(e, 2), (d, 1), (b, 1)]),
+                hq_mix([(e, 5), (b, 2), (d, 1)]),
+                hq_mix([(e, 5), (d, 2), (b, 1)]),
+                hq_mix([(e, 6), (d, 1), (b, 1)]),
+            ],
+            HqShape::CornerTop => [
+                mix8(e, a),
+                hq_mix([(e, 5), (b, 2), (a, 1)]),
+                mix1(e, a),
+                mix3(e, a),
+            ],
+            HqShape::CornerLeft => [
+                mix8(e, a),
+                mix1(e, a),
+                hq_mix([(e, 5), (d, 2), (a, 1)]),
+                mix3(e, a),
+            ],
+            HqShape::Corner => [mix8(e, a), mix1(e, a), mix1(e, a), mix3(e, a)],
+            HqShape::Top => [mix8(e, b), mix8(e, b), mix3(e, b), mix3(e, b)],
+            HqShape::Left => [mix8(e, d), mix3(e, d), mix8(e, d), mix3(e, d)],
+            HqShape::Pixel => [e; 4],
+            HqShape::ShallowLine => [mix1(e, b), mix1(b, e), mix8(e, d), mix3(e, d)],
+            HqShape::SteepLine => [mix1(e, d), mix8(e, b), mix1(d, e), mix3(e, b)],
+            HqShape::Sharp => [mix5(b, d), mix5(b, e), mix5(d, e), e],
+            HqShape::Soft | HqShape::Round => {
+                [hq_mix([(e, 2), (d, 1), (b, 1)]), mix1(e, b), mix1(e, d), e]
+            }
+            HqShape::LongTop => [
+                mix5(b, d),
+                mix8(b, d),
+                hq_mix([(d, 2), (e, 1), (b, 1)]),
+                hq_mix([(e, 6), (d, 1), (b, 1)]),
+            ],
+            HqShape::LongLeft => [
+                mix5(d, b),
+                hq_mix([(b, 2), (e, 1), (d, 1)]),
+                mix8(d, b),
+                hq_mix([(e, 6), (d, 1), (b, 1)]),
+            ],
+        }
+    }
+}
+
+/// Bits of the neighbours that look different from the middle pixel, in the
+/// order of [`HQ_RULES`]
+fn hq_pattern(k: &[Color32; 9]) -> usize {
+    let e = k[4];
+    [0, 1, 2, 3, 5, 6, 7, 8]
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, n)| k[n] != e && hq_differ(k[n], e))
+        .fold(0, |pattern, (bit, _)| pattern | 1 << bit)
+}
+
+/// hqx (hq2x, hq3x and hq4x): every corner of a pixel follows the rule hqx's
+/// table has for which of its neighbours look different
+pub fn hqx(pixels: &[Color32], width: usize, height: usize, scale: usize) -> Vec<Color32> {
+    let src = Source {
+        pixels,
+        width,
+        height,
+    };
+    let out_width = width * scale;
+    let mut out = vec![Color32::BLACK; out_width * height * scale];
+    for y in 0..height {
+        for x in 0..width {
+            let at = |ox: usize, oy: usize| (y * scale + oy) * out_width + x * scale + ox;
+            let e = src.get(x as isize, y as isize);
+            // What the corners ask of the edge pixels of a 3x3 block
+            let mut edges = [HqEdge::Keep; 9];
+            for rotation in Rotation::ALL {
+                // Turned a half further, so the corner is at the top left like in hqx
+                let mut k = src.kernel(x, y, rotation);
+                k.reverse();
+                let shape = HQ_RULES[hq_pattern(&k)].shape(k);
+                let output =
+                    |sx: usize, sy: usize| rotation.output(scale - 1 - sx, scale - 1 - sy, scale);
+                match scale {
+                    2 => {
+                        let (ox, oy) = output(0, 0);
+                        out[at(ox, oy)] = shape.hq2x(k);
+                    }
+                    3 => {
+                        let (corner, right, below) = shape.hq3x(k);
+                        let (ox, oy) = output(0, 0);
+                        out[at(ox, oy)] = corner;
+                        for ((ox, oy), edge) in [(output(1, 0), right), (output(0, 1), below)] {
+                            edges[oy * 3 + ox] = edges[oy * 3 + ox].max(edge);
+                        }
+                    }
+                    _ => {
+                        for (i, color) in shape.hq4x(k).into_iter().enumerate() {
+                            let (ox, oy) = output(i % 2, i / 2);
+                            out[at(ox, oy)] = color;
+                        }
+                    }
+                }
+            }
+            if scale == 3 {
+                out[at(1, 1)] = e;
+                for (ox, oy) in [(1, 0), (0, 1), (2, 1), (1, 2)] {
+                    let n = src.get(x as isize + ox as isize - 1, y as isize + oy as isize - 1);
+                    let alike = if hq_differ(e, n) {
+                        HqEdge::Keep
+                    } else {
+                        HqEdge::Blend
+                    };
+                    out[at(ox, oy)] = edges[oy * 3 + ox].max(alike).color(e, n);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Blend {
+    None,
+    Normal,
+    Dominant,
+}
+
+/// xBRZ's tuning constants
+const EQUAL_COLOR_TOLERANCE: f32 = 30.0;
+const DOMINANT_DIRECTION_THRESHOLD: f32 = 3.6;
+const STEEP_DIRECTION_THRESHOLD: f32 = 2.2;
+const CENTER_DIRECTION_BIAS: f32 = 4.0;
+
+/// Perceptual distance in YCbCr (BT.2020 coefficients)
+fn xbrz_distance(a: Color32, b: Color32) -> f32 {
+    const K_B: f32 = 0.0593;
+    const K_R: f32 = 0.2627;
+    const K_G: f32 = 1.0 - K_B - K_R;
+    let r = a.r() as f32 - b.r() as f32;
+    let g = a.g() as f32 - b.g() as f32;
+    let b = a.b() as f32 - b.b() as f32;
+    let y = K_R * r + K_G * g + K_B * b;
+    let c_b = 0.5 / (1.0 - K_B) * (b - y);
+    let c_r = 0.5 / (1.0 - K_R) * (r - y);
+    (y * y + c_b * c_b + c_r * c_r).sqrt()
+}
+
+/// Output pixels xBRZ paints over in the bottom-right of a block, each as `x`,
+/// `y` and an opacity of `m / n`
+type XbrzBlend = &'static [(usize, usize, u32, u32)];
+
+/// xBRZ's blends for one scale. Steep lines are shallow ones mirrored along
+/// the diagonal
+struct XbrzScaler {
+    shallow: XbrzBlend,
+    steep_and_shallow: XbrzBlend,
+    diagonal: XbrzBlend,
+    corner: XbrzBlend,
+}
+
+const XBRZ_2X: XbrzScaler = XbrzScaler {
+    shallow: &[(0, 1, 1, 4), (1, 1, 3, 4)],
+    steep_and_shallow: &[(0, 1, 1, 4), (1, 0, 1, 4), (1, 1, 5, 6)],
+    diagonal: &[(1, 1, 1, 2)],
+    corner: &[(1, 1, 21, 100)],
+};
+
+const XBRZ_3X: XbrzScaler = XbrzScaler {
+    shallow: &[(0, 2, 1, 4), (2, 1, 1, 4), (1, 2, 3, 4), (2, 2, 1, 1)],
+    steep_and_shallow: &[
+        (0, 2, 1, 4),
+        (2, 0, 1, 4),
+        (1, 2, 3, 4),
+        (2, 1, 3, 4),
+        (2, 2, 1, 1),
+    ],
+    diagonal: &[(2, 1, 1, 8), (1, 2, 1, 8), (2, 2, 7, 8)],
+    corner: &[(2, 2, 45, 100)],
+};
+
+const XBRZ_4X: XbrzScaler = XbrzScaler {
+    shallow: &[
+        (0, 3, 1, 4),
+        (2, 2, 1, 4),
+        (1, 3, 3, 4),
+        (3, 2, 3, 4),
+        (2, 3, 1, 1),
+        (3, 3, 1, 1),
+    ],
+    steep_and_shallow: &[
+        (1, 3, 3, 4),
+        (3, 1, 3, 4),
+        (0, 3, 1, 4),
+        (3, 0, 1, 4),
+        (2, 2, 1, 3),
+        (3, 3, 1, 1),
+        (2, 3, 1, 1),
+        (3, 2, 1, 1),
+    ],
+    diagonal: &[(2, 3, 1, 2), (3, 2, 1, 2), (3, 3, 1, 1)],
+    corner: &[(3, 3, 68, 100), (2, 3, 9, 100), (3, 2, 9, 100)],
+};
+
+/// Paints `color` over a pixel's output block as `blend` says, mirrored along
+/// the diagonal if `transpose`
+#[allow(clippy::too_many_arguments)]
+fn xbrz_paint(
+    out: &mut [Color32],
+    out_width: usize,
+    (x, y): (usize, usize),
+    scale: usize,
+    rotation: Rotation,
+    blend: XbrzBlend,
+    transpose: bool,
+    color: Color32,
+) {
+    for &(bx, by, m, n) in blend {
+        let (bx, by) = if transpose { (by, bx) } else { (bx, by) };
+        let (ox, oy) = rotation.output(bx, by, scale);
+        let pixel = &mut out[(y * scale + oy) * out_width + x * scale + ox];
+        // Rounded down per channel like xBRZ's gradient
+        let channel = |front: u8, back: u8| ((front as u32 * m + back as u32 * (n - m)) / n) as u8;
+        *pixel = Color32::from_rgb(
+            channel(color.r(), pixel.r()),
+            channel(color.g(), pixel.g()),
+            channel(color.b(), pixel.b()),
+        );
+    }
+}
+
+/// xBRZ: finds the dominant gradient of every 2x2 block, then draws the edge
+/// through each corner as a shallow, steep or diagonal line with xBRZ's blends
+pub fn xbrz(pixels: &[Color32], width: usize, height: usize, scale: usize) -> Vec<Color32> {
+    let src = Source {
+        pixels,
+        width,
+        height,
+    };
+    let scaler = match scale {
+        2 => &XBRZ_2X,
+        3 => &XBRZ_3X,
+        _ => &XBRZ_4X,
+    };
+    let dist = xbrz_distance;
+    let eq = |a, b| dist(a, b) < EQUAL_COLOR_TOLERANCE;
+
+    // Blend type of each pixel's corners, top left clockwise
+    let mut blend = vec![[Blend::None; 4]; width * height];
+    for y in -1..height as isize {
+        for x in -1..width as isize {
+            // |a|b|c|d|
+            // |e|f|g|h|  f is at (x, y)
+            // |i|j|k|l|
+            // |m|n|o|p|
+            let p = |dx: isize, dy: isize| src.get(x + dx, y + dy);
+            let (b, c) = (p(0, -1), p(1, -1));
+            let (e, f, g, h) = (p(-1, 0), p(0, 0), p(1, 0), p(2, 0));
+            let (i, j, k, l) = (p(-1, 1), p(0, 1), p(1, 1), p(2, 1));
+            let (n, o) = (p(0, 2), p(1, 2));
+            if (f == g && j == k) || (f == j && g == k) {
+                continue;
+            }
+            let jg = dist(i, f)
+                + dist(f, c)
+                + dist(n, k)
+                + dist(k, h)
+                + CENTER_DIRECTION_BIAS * dist(j, g);
+            let fk = dist(e, j)
+                + dist(j, o)
+                + dist(b, g)
+                + dist(g, l)
+                + CENTER_DIRECTION_BIAS * dist(f, k);
+            let mut set = |dx: isize, dy: isize, corner: usize, strength: Blend| {
+                let (x, y) = (x + dx, y + dy);
+                if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+                    blend[y as usize * width + x as usize][corner] = strength;
+                }
+            };
+            if jg < fk {
+                let strength = if DOMINANT_DIRECTION_THRESHOLD * jg < fk {
+                    Blend::Dominant
+                } else {
+                    Blend::Normal
+                };
+                if f != g && f != j {
+                    set(0, 0, 2, strength);
+                }
+                if k != j && k != g {
+                    set(1, 1, 0, strength);
+                }
+            } else if fk < jg {
+                let strength = if DOMINANT_DIRECTION_THRESHOLD * fk < jg {
+                    Blend::Dominant
+                } else {
+                    Blend::Normal
+                };
+                if j != f && j != k {
+                    set(0, 1, 1, strength);
+                }
+                if g != f && g != k {
+                    set(1, 0, 3, strength);
+                }
+            }
+        }
+    }
+
+    let mut out = nearest_source(&src, scale);
+    for y in 0..height {
+        for x in 0..width {
+            let corners = blend[y * width + x];
+            if corners.iter().all(|&c| c == Blend::None) {
+                continue;
+            }
+            // The same order as xBRZ, as blends land on top of each other
+            for rotation in [Rotation(0), Rotation(3), Rotation(2), Rotation(1)] {
+                let corner = |dx, dy| corners[rotation.corner(dx, dy)];
+                let bottom_right = corner(1, 1);
+                if bottom_right == Blend::None {
+                    continue;
+                }
+                let [_, b, c, d, e, f, g, h, i] = src.kernel(x, y, rotation);
+                let line = bottom_right == Blend::Dominant
+                    // Another blended corner next to this one means an isolated pixel
+                    || !((corner(1, -1) != Blend::None && !eq(e, g))
+                        || (corner(-1, 1) != Blend::None && !eq(e, c))
+                        // An L shape only gets its corner rounded
+                        || (!eq(e, i) && eq(g, h) && eq(h, i) && eq(i, f) && eq(f, c)));
+                let color = if dist(e, f) <= dist(e, h) { f } else { h };
+                let (blend, transpose) = if line {
+                    let (fg, hc) = (dist(f, g), dist(h, c));
+                    let shallow = STEEP_DIRECTION_THRESHOLD * fg <= hc && e != g && d != g;
+                    let steep = STEEP_DIRECTION_THRESHOLD * hc <= fg && e != c && b != c;
+                    match (shallow, steep) {
+                        (true, true) => (scaler.steep_and_shallow, false),
+                        (true, false) => (scaler.shallow, false),
+                        (false, true) => (scaler.shallow, true),
+                        (false, false) => (scaler.diagonal, false),
+                    }
+                } else {
+                    (scaler.corner, false)
+                };
+                xbrz_paint(
+                    &mut out,
+                    width * scale,
+                    (x, y),
+                    scale,
+                    rotation,
+                    blend,
+                    transpose,
+                    color,
+                );
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: Color32 = Color32::WHITE;
+    const K: Color32 = Color32::BLACK;
+
+    /// Small image of up to 3 colors, different every time `seed` is
+    fn noise(width: usize, height: usize, seed: u32) -> Vec<Color32> {
+        let colors = [W, K, Color32::RED];
+        let mut state = seed;
+        (0..width * height)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                colors[(state >> 16) as usize % 3]
+            })
+            .collect()
+    }
+
+    /// The neighbourhood of (`x`, `y`) as named in the AdvMAME spec, A to I
+    fn neighbours(
+        pixels: &[Color32],
+        width: usize,
+        height: usize,
+        x: usize,
+        y: usize,
+    ) -> [Color32; 9] {
+        std::array::from_fn(|i| {
+            let x = (x as isize + i as isize % 3 - 1).clamp(0, width as isize - 1) as usize;
+            let y = (y as isize + i as isize / 3 - 1).clamp(0, height as isize - 1) as usize;
+            pixels[y * width + x]
+        })
+    }
+
+    /// Scale2x written out rule by rule from the AdvMAME spec
+    fn reference_scale2x(pixels: &[Color32], width: usize, height: usize) -> Vec<Color32> {
+        let mut out = vec![K; width * height * 4];
+        for y in 0..height {
+            for x in 0..width {
+                let [_, b, _, d, e, f, _, h, _] = neighbours(pixels, width, height, x, y);
+                let block = if b != h && d != f {
+                    [
+                        if d == b { d } else { e },
+                        if b == f { f } else { e },
+                        if d == h { d } else { e },
+                        if h == f { f } else { e },
+                    ]
+                } else {
+                    [e; 4]
+                };
+                for (i, color) in block.into_iter().enumerate() {
+                    out[(y * 2 + i / 2) * width * 2 + x * 2 + i % 2] = color;
+                }
+            }
+        }
+        out
+    }
+
+    /// Scale3x written out rule by rule from the AdvMAME spec
+    fn reference_scale3x(pixels: &[Color32], width: usize, height: usize) -> Vec<Color32> {
+        let mut out = vec![K; width * height * 9];
+        for y in 0..height {
+            for x in 0..width {
+                let [a, b, c, d, e, f, g, h, i] = neighbours(pixels, width, height, x, y);
+                let block = if b != h && d != f {
+                    [
+                        if d == b { d } else { e },
+                        if (d == b && e != c) || (b == f && e != a) {
+                            b
+                        } else {
+                            e
+                        },
+                        if b == f { f } else { e },
+                        if (d == b && e != g) || (d == h && e != a) {
+                            d
+                        } else {
+                            e
+                        },
+                        e,
+                        if (b == f && e != i) || (h == f && e != c) {
+                            f
+                        } else {
+                            e
+                        },
+                        if d == h { d } else { e },
+                        if (d == h && e != i) || (h == f && e != g) {
+                            h
+                        } else {
+                            e
+                        },
+                        if h == f { f } else { e },
+                    ]
+                } else {
+                    [e; 9]
+                };
+                for (n, color) in block.into_iter().enumerate() {
+                    out[(y * 3 + n / 3) * width * 3 + x * 3 + n % 3] = color;
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn scale2x_checkerboard() {
+        let out = scale2x(&[W, K, K, W], 2, 2);
+        #[rustfmt::skip]
+        let expected = [
+            W, W, K, K,
+            W, K, W, K,
+            K, W, K, W,
+            K, K, W, W,
+        ];
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn scale2x_matches_the_spec() {
+        for seed in 0..16 {
+            let pixels = noise(7, 5, seed);
+            assert_eq!(scale2x(&pixels, 7, 5), reference_scale2x(&pixels, 7, 5));
+        }
+    }
+
+    #[test]
+    fn scale3x_matches_the_spec() {
+        for seed in 0..16 {
+            let pixels = noise(7, 5, seed);
+            assert_eq!(scale3x(&pixels, 7, 5), reference_scale3x(&pixels, 7, 5));
+        }
+    }
+
+    #[test]
+    fn straight_edges_stay_sharp() {
+        #[rustfmt::skip]
+        let pixels = [
+            W, W, K, K,
+            W, W, K, K,
+            K, K, K, K,
+        ];
+        for scale in 2..=4 {
+            let nearest = nearest(&pixels, 4, 3, scale);
+            let sharp = |out: &[Color32]| (0..4 * scale).all(|x| out[x] == nearest[x]);
+            assert!(sharp(&hqx(&pixels, 4, 3, scale)));
+            assert!(sharp(&xbrz(&pixels, 4, 3, scale)));
+        }
+    }
+
+    #[test]
+    fn flat_images_are_untouched() {
+        let pixels = [Color32::RED; 12];
+        for scale in 2..=4 {
+            let expected = vec![Color32::RED; 12 * scale * scale];
+            assert_eq!(hqx(&pixels, 4, 3, scale), expected);
+            assert_eq!(xbrz(&pixels, 4, 3, scale), expected);
+        }
+    }
+
+    #[test]
+    fn diagonals_get_smoothed() {
+        #[rustfmt::skip]
+        let pixels = [
+            K, K, K, W,
+            K, K, W, W,
+            K, W, W, W,
+            W, W, W, W,
+        ];
+        for scale in 2..=4 {
+            let nearest = nearest(&pixels, 4, 4, scale);
+            assert_ne!(hqx(&pixels, 4, 4, scale), nearest);
+            assert_ne!(xbrz(&pixels, 4, 4, scale), nearest);
+        }
+    }
+
+    /// A white pixel on its own in black, and the 2x2 block it becomes at 2x
+    fn lone_pixel(scale: fn(&[Color32], usize, usize, usize) -> Vec<Color32>) -> [Color32; 4] {
+        let mut pixels = [K; 9];
+        pixels[4] = W;
+        let out = scale(&pixels, 3, 3, 2);
+        [out[14], out[15], out[20], out[21]]
+    }
+
+    #[test]
+    fn hq2x_rounds_a_lone_pixel() {
+        // All eight neighbours differ and look alike: 14 parts white to 2 black
+        assert_eq!(lone_pixel(hqx), [Color32::from_gray(223); 4]);
+    }
+
+    #[test]
+    fn xbrz_rounds_a_lone_pixel() {
+        // Every corner gets xBRZ's 21% corner blend, no lines
+        assert_eq!(lone_pixel(xbrz), [Color32::from_gray(201); 4]);
+    }
+
+    #[test]
+    fn hqx_is_mirror_symmetric() {
+        let mirror = |pixels: &[Color32], width: usize| -> Vec<Color32> {
+            pixels
+                .chunks_exact(width)
+                .flat_map(|row| row.iter().rev())
+                .copied()
+                .collect()
+        };
+        for seed in 0..16 {
+            let pixels = noise(7, 5, seed);
+            for scale in 2..=4 {
+                assert_eq!(
+                    hqx(&mirror(&pixels, 7), 7, 5, scale),
+                    mirror(&hqx(&pixels, 7, 5, scale), 7 * scale),
+                );
+            }
+        }
+    }
+}
@@ -1,9 +1,15 @@
+use std::path::PathBuf;
+
 use anyhow::Result;
 use egui::{Key, KeyboardShortcut, Modifiers};
 use indexmap::IndexMap;
 use log::error;
 use serde::{Deserialize, Serialize};
 
+use crate::ntsc::NtscSettings;
+use crate::palette::{Palette, PaletteEdit, PaletteKind};
+use crate::ppu::{FRAME_HEIGHT, FRAME_WIDTH, RenderOptions};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Action {
     // In-game
@@ -19,10 +25,16 @@ pub enum Action {
     // Application
     PauseResume,
     Step,
+    StepInstruction,
+    StepOver,
+    StepOut,
     SaveState,
     LoadState,
     #[cfg(not(target_arch = "wasm32"))]
     TakeScreenshot,
+    #[cfg(not(target_arch = "wasm32"))]
+    CopyScreenshot,
+    ToggleFullscreen,
     OpenRom,
     Quit,
 }
@@ -140,7 +152,29 @@ impl Default for Keybindings {
         );
         application.insert(
             Action::Step,
-            Keybinding::new("Step", KeyboardShortcut::new(Modifiers::NONE, Key::P)),
+            Keybinding::new("Step frame", KeyboardShortcut::new(Modifiers::NONE, Key::P)),
+        );
+        // Shortcuts are consumed in order, so Shift+F11 must come before plain F11
+        application.insert(
+            Action::StepOut,
+            Keybinding::new(
+                "Step out",
+                KeyboardShortcut::new(Modifiers::SHIFT, Key::F11),
+            ),
+        );
+        application.insert(
+            Action::StepInstruction,
+            Keybinding::new(
+                "Step instruction",
+                KeyboardShortcut::new(Modifiers::NONE, Key::F11),
+            ),
+        );
+        application.insert(
+            Action::StepOver,
+            Keybinding::new(
+                "Step over",
+                KeyboardShortcut::new(Modifiers::NONE, Key::F10),
+            ),
         );
         #[cfg(not(target_arch = "wasm32"))]
         application.insert(
@@ -150,6 +184,21 @@ impl Default for Keybindings {
                 KeyboardShortcut::new(Modifiers::NONE, Key::F12),
             ),
         );
+        #[cfg(not(target_arch = "wasm32"))]
+        application.insert(
+            Action::CopyScreenshot,
+            Keybinding::new(
+                "Copy screenshot",
+                KeyboardShortcut::new(Modifiers::SHIFT, Key::F12),
+            ),
+        );
+        application.insert(
+            Action::ToggleFullscreen,
+            Keybinding::new(
+                "Fullscreen",
+                KeyboardShortcut::new(Modifiers::ALT, Key::Enter),
+            ),
+        );
         application.insert(
             Action::OpenRom,
             Keybinding::new(
@@ -232,9 +281,280 @@ impl Keybindings {
     }
 }
 
+/// How frames are turned into the picture on screen
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VideoFilter {
+    /// Palette colors, scaled up with nearest neighbour
+    #[default]
+    Nearest,
+    /// Composite signal simulation with artifact colors and dot crawl
+    Ntsc,
+    Scale2x,
+    Scale3x,
+    Hq2x,
+    Hq3x,
+    Hq4x,
+    Xbrz2x,
+    Xbrz3x,
+    Xbrz4x,
+}
+
+impl VideoFilter {
+    pub const ALL: [VideoFilter; 10] = [
+        VideoFilter::Nearest,
+        VideoFilter::Ntsc,
+        VideoFilter::Scale2x,
+        VideoFilter::Scale3x,
+        VideoFilter::Hq2x,
+        VideoFilter::Hq3x,
+        VideoFilter::Hq4x,
+        VideoFilter::Xbrz2x,
+        VideoFilter::Xbrz3x,
+        VideoFilter::Xbrz4x,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            VideoFilter::Nearest => "Nearest neighbour",
+            VideoFilter::Ntsc => "NTSC composite",
+            VideoFilter::Scale2x => "Scale2x",
+            VideoFilter::Scale3x => "Scale3x",
+            VideoFilter::Hq2x => "HQ2x",
+            VideoFilter::Hq3x => "HQ3x",
+            VideoFilter::Hq4x => "HQ4x",
+            VideoFilter::Xbrz2x => "xBRZ 2x",
+            VideoFilter::Xbrz3x => "xBRZ 3x",
+            VideoFilter::Xbrz4x => "xBRZ 4x",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScaleMode {
+    /// As large as fits the window
+    #[default]
+    Fit,
+    /// The largest whole multiple that fits, so every pixel is the same size
+    Integer,
+}
+
+impl ScaleMode {
+    pub const ALL: [ScaleMode; 2] = [ScaleMode::Fit, ScaleMode::Integer];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScaleMode::Fit => "Fit to window",
+            ScaleMode::Integer => "Integer",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AspectRatio {
+    /// One frame pixel per square screen pixel
+    #[default]
+    Square,
+    /// The 8:7 pixels of an NTSC TV
+    Ntsc,
+    /// Whatever is left after cropping stretched to fill a 4:3 screen
+    Stretch4x3,
+}
+
+impl AspectRatio {
+    pub const ALL: [AspectRatio; 3] = [
+        AspectRatio::Square,
+        AspectRatio::Ntsc,
+        AspectRatio::Stretch4x3,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AspectRatio::Square => "Square pixels",
+            AspectRatio::Ntsc => "8:7 pixels (NTSC)",
+            AspectRatio::Stretch4x3 => "Stretch to 4:3",
+        }
+    }
+
+    /// Width an image of `width` x `height` frame pixels is shown at, in units of its height
+    pub fn display_width(self, width: usize, height: usize) -> f32 {
+        match self {
+            AspectRatio::Square => width as f32,
+            AspectRatio::Ntsc => width as f32 * 8.0 / 7.0,
+            AspectRatio::Stretch4x3 => height as f32 * 4.0 / 3.0,
+        }
+    }
+}
+
+/// Frame pixels hidden at each edge, like the bezel of a TV would
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Overscan {
+    pub top: u8,
+    pub bottom: u8,
+    pub left: u8,
+    pub right: u8,
+}
+
+impl Overscan {
+    /// Most that can be cropped from one edge
+    pub const MAX: u8 = 64;
+
+    /// The part of the frame left visible, as a min and max corner in frame pixels
+    pub fn visible(&self) -> ((usize, usize), (usize, usize)) {
+        let edge = |v: u8| v.min(Self::MAX) as usize;
+        (
+            (edge(self.left), edge(self.top)),
+            (
+                FRAME_WIDTH - edge(self.right),
+                FRAME_HEIGHT - edge(self.bottom),
+            ),
+        )
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoSettings {
+    pub filter: VideoFilter,
+    pub ntsc: NtscSettings,
+    /// How much the gaps between scanlines are darkened, 0 to 1
+    pub scanlines: f32,
+    /// Strength of the RGB stripe mask, 0 to 1
+    pub aperture_grille: f32,
+    pub scale_mode: ScaleMode,
+    pub aspect_ratio: AspectRatio,
+    pub overscan: Overscan,
+    /// Shows nothing but the screen, covering the whole monitor
+    pub fullscreen: bool,
+    /// Sprite limit and layer toggles, applied by the PPU itself
+    pub render: RenderOptions,
+    pub palette: PaletteKind,
+    /// .pal file used by `PaletteKind::Custom`
+    pub palette_file: Option<PathBuf>,
+    /// Entries changed in the palette editor, applied over the selected palette
+    pub palette_edits: Vec<PaletteEdit>,
+}
+
+impl VideoSettings {
+    /// The selected palette with the editor's changes applied
+    pub fn build_palette(&self) -> Result<Palette> {
+        let mut palette = match (self.palette, &self.palette_file) {
+            (PaletteKind::Custom, Some(path)) => Palette::from_pal(&std::fs::read(path)?)?,
+            (kind, _) => Palette::builtin(kind),
+        };
+        palette.apply_edits(&self.palette_edits);
+        Ok(palette)
+    }
+
+    pub fn palette_edit(&self, index: u8) -> Option<&PaletteEdit> {
+        self.palette_edits.iter().find(|e| e.index == index)
+    }
+
+    pub fn set_palette_edit(&mut self, index: u8, color: [u8; 3]) {
+        match self.palette_edits.iter_mut().find(|e| e.index == index) {
+            Some(edit) => edit.color = color,
+            None => self.palette_edits.push(PaletteEdit { index, color }),
+        }
+    }
+
+    pub fn reset_palette_edit(&mut self, index: u8) {
+        self.palette_edits.retain(|e| e.index != index);
+    }
+}
+
+/// What a screenshot is filled in with when its file name template is expanded
+pub struct ScreenshotInfo<'a> {
+    pub title: &'a str,
+    pub sha1: &'a str,
+    pub frame: u64,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenshotSettings {
+    /// Where screenshots are saved, the working directory when unset
+    pub directory: Option<PathBuf>,
+    /// File name without extension, with `{title}`, `{sha1}`, `{frame}` and `{timestamp}` replaced
+    pub template: String,
+    /// Saves the picture with the video filter applied instead of one pixel per NES pixel
+    pub filtered: bool,
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            template: "{title}_{timestamp}".to_string(),
+            filtered: false,
+        }
+    }
+}
+
+impl ScreenshotSettings {
+    pub const FIELDS: [&str; 4] = ["{title}", "{sha1}", "{frame}", "{timestamp}"];
+
+    pub fn file_name(&self, info: &ScreenshotInfo) -> String {
+        let name = self
+            .template
+            .replace("{title}", info.title)
+            .replace("{sha1}", info.sha1)
+            .replace("{frame}", &info.frame.to_string())
+            .replace("{timestamp}", &format_timestamp(info.timestamp));
+        let name: String = name
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c if c.is_control() => '_',
+                c => c,
+            })
+            .collect();
+        match name.trim() {
+            "" => "screenshot".to_string(),
+            name => name.to_string(),
+        }
+    }
+}
+
+/// `YYYYMMDD-HHMMSS` in UTC
+fn format_timestamp(secs: u64) -> String {
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Days to a civil date, from Howard Hinnant's `civil_from_days`
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Hardware quirks that are accurate but can get in the way
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmulationSettings {
+    /// OAM rows left alone for about 3000 CPU cycles lose their contents
+    pub oam_decay: bool,
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Settings {
     pub keybindings: Keybindings,
+    #[serde(default)]
+    pub video: VideoSettings,
+    #[serde(default)]
+    pub emulation: EmulationSettings,
+    #[serde(default)]
+    pub screenshots: ScreenshotSettings,
 }
 
 fn load_from_file() -> Result<Option<Settings>> {
@@ -291,3 +611,61 @@ impl Settings {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0), "19700101-000000");
+        assert_eq!(format_timestamp(1_234_567_890), "20090213-233130");
+        assert_eq!(format_timestamp(951_782_400), "20000229-000000");
+        assert_eq!(format_timestamp(1_709_251_199), "20240229-235959");
+        // 2100 isn't a leap year
+        assert_eq!(format_timestamp(4_107_542_400), "21000301-000000");
+    }
+
+    fn info() -> ScreenshotInfo<'static> {
+        ScreenshotInfo {
+            title: "Super Mario Bros.",
+            sha1: "EA343F4E445A9050D4B4FBAC2C77D0693B1D0922",
+            frame: 1234,
+            timestamp: 1_234_567_890,
+        }
+    }
+
+    #[test]
+    fn file_name_templates() {
+        let name = |template: &str| {
+            ScreenshotSettings {
+                template: template.to_string(),
+                ..Default::default()
+            }
+            .file_name(&info())
+        };
+        assert_eq!(
+            ScreenshotSettings::default().file_name(&info()),
+            "Super Mario Bros._20090213-233130"
+        );
+        assert_eq!(
+            name("{sha1}-{frame}"),
+            "EA343F4E445A9050D4B4FBAC2C77D0693B1D0922-1234"
+        );
+        assert_eq!(name("{title}{title}"), "Super Mario Bros.Super Mario Bros.");
+        assert_eq!(name("shot {unknown}"), "shot {unknown}");
+    }
+
+    #[test]
+    fn file_names_are_safe() {
+        let settings = ScreenshotSettings {
+            template: "{title}".to_string(),
+            ..Default::default()
+        };
+        let name = |title: &str| settings.file_name(&ScreenshotInfo { title, ..info() });
+        assert_eq!(name("a/b\\c:d*e?f\"g<h>i|j"), "a_b_c_d_e_f_g_h_i_j");
+        assert_eq!(name("tab\there"), "tab_here");
+        assert_eq!(name("  "), "screenshot");
+        assert_eq!(name(" padded "), "padded");
+    }
+}
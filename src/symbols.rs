@@ -0,0 +1,520 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use log::{error, info, warn};
+
+/// ld65 file offsets include the iNES header
+const INES_HEADER_SIZE: usize = 16;
+/// FCEUX numbers its per-bank label files in 16 KiB units
+const NL_BANK_SIZE: usize = 0x4000;
+/// Mesen numbers save and work RAM labels from $6000
+const MLB_SAVE_RAM_START: u16 = 0x6000;
+const MLB_SAVE_RAM_SIZE: usize = 0x2000;
+
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub comment: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SourceLine {
+    pub file: usize,
+    pub line: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Location {
+    /// RAM, registers and anything else not backed by PRG ROM
+    Cpu(u16),
+    Prg(usize),
+}
+
+/// Labels keyed by where they live, so code in switchable banks only
+/// resolves while its bank is mapped in.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    cpu: HashMap<u16, Symbol>,
+    prg: HashMap<usize, Symbol>,
+    names: HashMap<String, u16>,
+    lines: HashMap<usize, SourceLine>,
+    files: Vec<PathBuf>,
+}
+
+impl SymbolTable {
+    /// Loads every symbol file sitting next to the ROM that shares its name
+    pub fn discover(rom_path: &Path) -> Self {
+        let mut table = Self::default();
+        let (Some(dir), Some(file_name), Some(stem)) = (
+            rom_path.parent(),
+            rom_path.file_name().and_then(|n| n.to_str()),
+            rom_path.file_stem().and_then(|n| n.to_str()),
+        ) else {
+            return table;
+        };
+        let Ok(entries) = fs::read_dir(dir) else {
+            return table;
+        };
+
+        let nl_prefix = format!("{}.", file_name);
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| {
+                let Some(name) = p.file_name().and_then(|n| n.to_str()) else {
+                    return false;
+                };
+                name == format!("{}.dbg", stem)
+                    || name == format!("{}.mlb", stem)
+                    || (name.starts_with(&nl_prefix) && name.ends_with(".nl"))
+            })
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            match table.load(&path) {
+                Ok(()) => info!("Symbols loaded from \"{}\"", path.display()),
+                Err(e) => error!("Failed to load symbols from \"{}\": {e}", path.display()),
+            }
+        }
+        table
+    }
+
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read \"{}\"", path.display()))?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("dbg") => self.load_ca65(&text, path.parent().unwrap_or(Path::new(""))),
+            Some("mlb") => {
+                self.load_mlb(&text);
+                Ok(())
+            }
+            Some("nl") => {
+                // game.nes.ram.nl or game.nes.<bank>.nl
+                let bank = path
+                    .file_stem()
+                    .and_then(|s| Path::new(s).extension())
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("ram");
+                if bank == "ram" {
+                    self.load_nl(&text, None);
+                } else {
+                    let bank = usize::from_str_radix(bank, 16)
+                        .with_context(|| format!("Invalid bank \"{}\"", bank))?;
+                    self.load_nl(&text, Some(bank));
+                }
+                Ok(())
+            }
+            _ => bail!("Unsupported symbol file \"{}\"", path.display()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cpu.is_empty() && self.prg.is_empty()
+    }
+
+    pub fn lookup(&self, addr: u16, prg_offset: Option<usize>) -> Option<&Symbol> {
+        match prg_offset {
+            Some(offset) => self.prg.get(&offset),
+            None => self.cpu.get(&addr),
+        }
+    }
+
+    /// CPU address of a label, for address fields that accept names
+    pub fn find(&self, name: &str) -> Option<u16> {
+        self.names.get(name.trim()).copied()
+    }
+
+    pub fn source_line(&self, prg_offset: usize) -> Option<SourceLine> {
+        self.lines.get(&prg_offset).copied()
+    }
+
+    pub fn file(&self, index: usize) -> Option<&Path> {
+        self.files.get(index).map(|p| p.as_path())
+    }
+
+    fn insert(
+        &mut self,
+        location: Location,
+        addr: Option<u16>,
+        name: &str,
+        comment: Option<&str>,
+        size: usize,
+    ) {
+        if name.is_empty() {
+            return;
+        }
+        for i in 0..size.max(1) {
+            let symbol = Symbol {
+                name: if i == 0 {
+                    name.to_string()
+                } else {
+                    format!("{}+{}", name, i)
+                },
+                comment: comment
+                    .filter(|c| i == 0 && !c.is_empty())
+                    .map(|c| c.to_string()),
+            };
+            match location {
+                Location::Cpu(addr) => place(self.cpu.entry(addr.wrapping_add(i as u16)), symbol),
+                Location::Prg(offset) => place(self.prg.entry(offset + i), symbol),
+            }
+        }
+        if let Some(addr) = addr {
+            self.names.entry(name.to_string()).or_insert(addr);
+        }
+    }
+
+    /// FCEUX: `$C000#Label#Comment`, with `$0300/10#Label#` for arrays.
+    /// Lines that don't parse are skipped so one typo doesn't lose the file
+    fn load_nl(&mut self, text: &str, bank: Option<usize>) {
+        for (i, line) in text.lines().enumerate() {
+            if let Err(e) = self.load_nl_line(line, bank) {
+                warn!("Skipping line {} of .nl file: {e}", i + 1);
+            }
+        }
+    }
+
+    fn load_nl_line(&mut self, line: &str, bank: Option<usize>) -> Result<()> {
+        let mut parts = line.trim_end().splitn(3, '#');
+        let (Some(addr), Some(name)) = (parts.next(), parts.next()) else {
+            return Ok(());
+        };
+        let Some(addr) = addr.strip_prefix('$') else {
+            return Ok(());
+        };
+        let (addr, size) = match addr.split_once('/') {
+            Some((addr, size)) => (addr, usize::from_str_radix(size, 16)?),
+            None => (addr, 1),
+        };
+        let addr = u16::from_str_radix(addr, 16)?;
+        let location = match bank {
+            Some(bank) => Location::Prg(bank * NL_BANK_SIZE + (addr as usize & 0x3FFF)),
+            None => Location::Cpu(addr),
+        };
+        self.insert(location, Some(addr), name, parts.next(), size);
+        Ok(())
+    }
+
+    /// Mesen: `Type:Address[-End]:Label[:Comment]`. Lines that don't parse are
+    /// skipped so one typo doesn't lose the file
+    fn load_mlb(&mut self, text: &str) {
+        for (i, line) in text.lines().enumerate() {
+            if let Err(e) = self.load_mlb_line(line) {
+                warn!("Skipping line {} of .mlb file: {e}", i + 1);
+            }
+        }
+    }
+
+    fn load_mlb_line(&mut self, line: &str) -> Result<()> {
+        let mut parts = line.trim_end().splitn(4, ':');
+        let (Some(kind), Some(range), Some(name)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Ok(());
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (start, end),
+            None => (range, range),
+        };
+        let start = usize::from_str_radix(start, 16)?;
+        let end = usize::from_str_radix(end, 16)?;
+        let size = end.saturating_sub(start) + 1;
+        let comment = parts.next();
+        let (location, addr) = match kind {
+            "P" | "NesPrgRom" => (Location::Prg(start), None),
+            "R" | "NesInternalRam" => (Location::Cpu(start as u16), Some(start as u16)),
+            "S" | "W" | "NesSaveRam" | "NesWorkRam" => {
+                let addr = u16::try_from(start)
+                    .ok()
+                    .and_then(|start| MLB_SAVE_RAM_START.checked_add(start))
+                    .filter(|_| start.max(end) < MLB_SAVE_RAM_SIZE);
+                let Some(addr) = addr else {
+                    bail!("\"{name}\" at ${start:X} is outside of $6000-$7FFF");
+                };
+                (Location::Cpu(addr), Some(addr))
+            }
+            "G" | "NesMemory" | "Register" => (Location::Cpu(start as u16), Some(start as u16)),
+            _ => return Ok(()),
+        };
+        self.insert(location, addr, name, comment, size);
+        Ok(())
+    }
+
+    /// ld65 `--dbgfile` output
+    fn load_ca65(&mut self, text: &str, base_dir: &Path) -> Result<()> {
+        struct Segment {
+            start: usize,
+            rom_offset: Option<usize>,
+        }
+        struct Span {
+            seg: usize,
+            start: usize,
+        }
+
+        let mut segments = HashMap::new();
+        let mut spans = HashMap::new();
+        let mut files = HashMap::new();
+        let mut syms = Vec::new();
+        let mut lines = Vec::new();
+
+        for line in text.lines() {
+            let Some((kind, attrs)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let attrs = parse_dbg_attrs(attrs);
+            let get = |key: &str| attrs.get(key).map(|v| v.as_str());
+            let num = |key: &str| get(key).and_then(parse_dbg_number);
+            match kind {
+                "seg" => {
+                    let (Some(id), Some(start)) = (num("id"), num("start")) else {
+                        continue;
+                    };
+                    let rom_offset = match (get("oname"), num("ooffs")) {
+                        (Some(_), Some(ooffs)) if get("type") != Some("rw") => {
+                            ooffs.checked_sub(INES_HEADER_SIZE)
+                        }
+                        _ => None,
+                    };
+                    segments.insert(id, Segment { start, rom_offset });
+                }
+                "span" => {
+                    if let (Some(id), Some(seg), Some(start)) =
+                        (num("id"), num("seg"), num("start"))
+                    {
+                        spans.insert(id, Span { seg, start });
+                    }
+                }
+                "file" => {
+                    if let (Some(id), Some(name)) = (num("id"), get("name")) {
+                        files.insert(id, base_dir.join(name));
+                    }
+                }
+                "sym" if get("type") == Some("lab") => {
+                    if let (Some(name), Some(val), Some(seg)) =
+                        (get("name"), num("val"), num("seg"))
+                    {
+                        let size = num("size").unwrap_or(1);
+                        syms.push((name.to_string(), val, seg, size));
+                    }
+                }
+                "line" => {
+                    if let (Some(file), Some(line), Some(span)) =
+                        (num("file"), num("line"), get("span"))
+                    {
+                        let span_ids: Vec<usize> =
+                            span.split('+').filter_map(parse_dbg_number).collect();
+                        let is_macro = num("type") == Some(2);
+                        lines.push((file, line as u32, span_ids, is_macro));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (name, val, seg, size) in syms {
+            let Some(segment) = segments.get(&seg) else {
+                continue;
+            };
+            let location = match segment.rom_offset {
+                Some(offset) => Location::Prg(offset + val.saturating_sub(segment.start)),
+                None => Location::Cpu(val as u16),
+            };
+            self.insert(location, Some(val as u16), &name, None, size);
+        }
+
+        // Remap file ids into our own list so several .dbg files can coexist
+        let mut file_ids = HashMap::new();
+        for (id, path) in files {
+            file_ids.insert(id, self.files.len());
+            self.files.push(path);
+        }
+
+        for (file, line, span_ids, is_macro) in lines {
+            let Some(&file) = file_ids.get(&file) else {
+                continue;
+            };
+            for span in span_ids.iter().filter_map(|id| spans.get(id)) {
+                let Some(offset) = segments.get(&span.seg).and_then(|s| s.rom_offset) else {
+                    continue;
+                };
+                let source = SourceLine { file, line };
+                // Prefer the line that invoked a macro over the macro body
+                if is_macro {
+                    self.lines.entry(offset + span.start).or_insert(source);
+                } else {
+                    self.lines.insert(offset + span.start, source);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Cheap locals (@loop) give way to real labels at the same spot
+fn place<K>(slot: Entry<K, Symbol>, symbol: Symbol) {
+    match slot {
+        Entry::Occupied(mut e) => {
+            if e.get().name.starts_with('@') && !symbol.name.starts_with('@') {
+                e.insert(symbol);
+            }
+        }
+        Entry::Vacant(e) => {
+            e.insert(symbol);
+        }
+    }
+}
+
+fn parse_dbg_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn parse_dbg_attrs(s: &str) -> HashMap<String, String> {
+    let mut attrs = HashMap::new();
+    let mut key = String::new();
+    let mut value = String::new();
+    let mut in_value = false;
+    let mut quoted = false;
+    for c in s.trim().chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '=' if !quoted && !in_value => in_value = true,
+            ',' if !quoted => {
+                attrs.insert(std::mem::take(&mut key), std::mem::take(&mut value));
+                in_value = false;
+            }
+            _ if in_value => value.push(c),
+            _ => key.push(c),
+        }
+    }
+    if !key.is_empty() {
+        attrs.insert(key, value);
+    }
+    attrs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(table: &SymbolTable, addr: u16, prg_offset: Option<usize>) -> Option<&str> {
+        table
+            .lookup(addr, prg_offset)
+            .map(|symbol| symbol.name.as_str())
+    }
+
+    #[test]
+    fn fceux_nl() {
+        let mut table = SymbolTable::default();
+        table.load_nl(
+            "$0300/3#buffer#Input buffer\n$00FF#last#\nnot a label\n",
+            None,
+        );
+        let buffer = table.lookup(0x0300, None).unwrap();
+        assert_eq!(buffer.name, "buffer");
+        assert_eq!(buffer.comment.as_deref(), Some("Input buffer"));
+        let end = table.lookup(0x0302, None).unwrap();
+        assert_eq!(end.name, "buffer+2");
+        assert_eq!(end.comment, None);
+        assert_eq!(name(&table, 0x0303, None), None);
+        assert_eq!(table.lookup(0x00FF, None).unwrap().comment, None);
+        assert_eq!(table.find("buffer"), Some(0x0300));
+
+        // Bank files place labels by PRG offset, 16 KiB per bank
+        table.load_nl("$C005#bank_fn#\n", Some(1));
+        assert_eq!(name(&table, 0xC005, Some(0x4005)), Some("bank_fn"));
+        assert_eq!(name(&table, 0xC005, None), None);
+        assert_eq!(table.find("bank_fn"), Some(0xC005));
+
+        // A bad line doesn't stop the rest from loading
+        table.load_nl("$GG00#bad#\n$0400/ZZ#bad_size#\n$0401#after#\n", None);
+        assert_eq!(table.find("bad"), None);
+        assert_eq!(table.find("bad_size"), None);
+        assert_eq!(table.find("after"), Some(0x0401));
+    }
+
+    #[test]
+    fn mesen_mlb() {
+        let mut table = SymbolTable::default();
+        table.load_mlb(
+            "R:0010-0011:ptr:Pointer\nP:1234:handler\nS:0000:save\nG:2000:PPUCTRL\nX:0000:unknown\n",
+        );
+        assert_eq!(name(&table, 0x0010, None), Some("ptr"));
+        assert_eq!(name(&table, 0x0011, None), Some("ptr+1"));
+        assert_eq!(
+            table.lookup(0x0010, None).unwrap().comment.as_deref(),
+            Some("Pointer")
+        );
+        assert_eq!(name(&table, 0, Some(0x1234)), Some("handler"));
+        // PRG ROM labels have no fixed CPU address
+        assert_eq!(table.find("handler"), None);
+        assert_eq!(name(&table, 0x6000, None), Some("save"));
+        assert_eq!(table.find("PPUCTRL"), Some(0x2000));
+        assert_eq!(table.find("unknown"), None);
+
+        // Save RAM offsets past $7FFF used to overflow, now they're skipped
+        table.load_mlb("S:1FFF:last\nS:2000:past\nW:1FFF-2000:straddles\nW:FFFFF:far\nR:XYZ:bad\nR:0020:after\n");
+        assert_eq!(table.find("last"), Some(0x7FFF));
+        assert_eq!(table.find("past"), None);
+        assert_eq!(table.find("straddles"), None);
+        assert_eq!(table.find("far"), None);
+        assert_eq!(table.find("after"), Some(0x0020));
+    }
+
+    #[test]
+    fn real_labels_replace_cheap_locals() {
+        let mut table = SymbolTable::default();
+        table.load_mlb("P:0010:@loop\nP:0010:main\nP:0010:@other\n");
+        assert_eq!(name(&table, 0, Some(0x10)), Some("main"));
+    }
+
+    #[test]
+    fn ld65_dbg() {
+        let dbg = "\
+version\tmajor=2,minor=0
+file\tid=0,name=\"src/main.s\",size=100,mtime=0x5F000000,mod=0
+seg\tid=0,name=\"CODE\",start=0x00C000,size=0x0010,addrsize=absolute,type=ro,oname=\"game.nes\",ooffs=16
+seg\tid=1,name=\"ZEROPAGE\",start=0x000000,size=0x0002,addrsize=zeropage,type=rw
+span\tid=0,seg=0,start=0,size=3
+span\tid=1,seg=0,start=3,size=2
+line\tid=0,file=0,line=10,span=0
+line\tid=1,file=0,line=11,span=1
+line\tid=2,file=0,line=40,type=2,span=1
+sym\tid=0,name=\"reset\",addrsize=absolute,scope=0,def=0,val=0xC000,seg=0,type=lab
+sym\tid=1,name=\"@loop\",addrsize=absolute,scope=0,def=1,val=0xC003,seg=0,type=lab
+sym\tid=2,name=\"counter\",addrsize=zeropage,size=2,scope=0,def=2,val=0x10,seg=1,type=lab
+sym\tid=3,name=\"SIZE\",addrsize=zeropage,scope=0,def=3,val=0x20,type=equ
+";
+        let mut table = SymbolTable::default();
+        table.load_ca65(dbg, Path::new("project")).unwrap();
+
+        // The iNES header is taken off the segment's file offset
+        assert_eq!(name(&table, 0xC000, Some(0)), Some("reset"));
+        assert_eq!(name(&table, 0xC003, Some(3)), Some("@loop"));
+        assert_eq!(table.find("reset"), Some(0xC000));
+        assert_eq!(name(&table, 0x0011, None), Some("counter+1"));
+        assert_eq!(table.find("SIZE"), None);
+
+        assert_eq!(table.source_line(0), Some(SourceLine { file: 0, line: 10 }));
+        // The macro body's line doesn't replace the line that invoked it
+        assert_eq!(table.source_line(3), Some(SourceLine { file: 0, line: 11 }));
+        assert_eq!(table.file(0), Some(Path::new("project/src/main.s")));
+    }
+
+    #[test]
+    fn dbg_attributes() {
+        let attrs = parse_dbg_attrs("id=0,name=\"a,b=c\",size=3");
+        assert_eq!(attrs["id"], "0");
+        assert_eq!(attrs["name"], "a,b=c");
+        assert_eq!(attrs["size"], "3");
+        assert_eq!(parse_dbg_number("0x1F"), Some(0x1F));
+        assert_eq!(parse_dbg_number("31"), Some(31));
+        assert_eq!(parse_dbg_number("0xZZ"), None);
+    }
+}
@@ -8,8 +8,11 @@ use egui_extras::{Column, TableBuilder};
 #[cfg(not(target_arch = "wasm32"))]
 use egui_plot::{Line, Plot, PlotPoints};
 use indexmap::IndexMap;
+use log::error;
 #[cfg(not(target_arch = "wasm32"))]
-use log::{error, info};
+use log::info;
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::{HashMap, HashSet, VecDeque};
 #[cfg(not(target_arch = "wasm32"))]
 use std::path::Path;
 #[cfg(not(target_arch = "wasm32"))]
@@ -18,26 +21,46 @@ use std::sync::{Arc, OnceLock};
 
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 #[cfg(not(target_arch = "wasm32"))]
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 #[cfg(target_arch = "wasm32")]
 use web_time::{Duration, Instant};
 
 #[cfg(not(target_arch = "wasm32"))]
 use crate::args::get_args;
+use crate::emu::Frame;
+use crate::filters::FilterPipeline;
+use crate::hd_pack::HdPack;
+use crate::ntsc::NtscSettings;
+use crate::palette::{Palette, PaletteKind};
 use crate::platform::FileDataSource;
-use crate::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
-use crate::settings::{Action, Keybinding, Keybindings, Settings};
+use crate::ppu::{BLANK_PIXEL, FRAME_HEIGHT, FRAME_WIDTH};
+use crate::settings::{
+    Action, AspectRatio, Keybinding, Keybindings, Overscan, ScaleMode, Settings, VideoFilter,
+    VideoSettings,
+};
 #[cfg(not(target_arch = "wasm32"))]
 use crate::{
-    debug::{BYTES_PER_ROW, DebugSnapshot, ROWS_TO_SHOW},
-    emu::{Command, Event},
+    breakpoint::{AddrSpace, BreakHit, BreakKind, Breakpoint, Condition},
+    cdl::PrgFlags,
+    cheats::{self, Cheat, CheatEffect},
+    debug::{BYTES_PER_ROW, DebugPanels, DebugSnapshot, MEM_BLOCK_SIZE, MemorySpace, ROWS_TO_SHOW},
+    emu::{Command, Event, StepKind},
     mapper::MapperIcon,
     platform::PlatformRunner,
-    ppu::Ppu,
+    ppu_events::{DOTS, PpuEvent, PpuEventKind, SCANLINES},
+    ppu_view::{
+        self, NAMETABLES_HEIGHT, NAMETABLES_WIDTH, OamSprite, PATTERN_TABLE_SIZE, SPRITE_COUNT,
+        SPRITE_SHEET_HEIGHT, SPRITE_SHEET_WIDTH,
+    },
+    ram_search::{Comparison, Operand, RamSearch, ValueSize},
+    recording::RecordingFormat,
+    settings::{ScreenshotInfo, ScreenshotSettings},
+    symbols::SymbolTable,
+    watch::{WATCH_BYTES, WatchType},
 };
 #[cfg(target_arch = "wasm32")]
 use crate::{
-    emu::{Command, Event},
+    emu::{Command, Event, StepKind},
     platform::PlatformRunner,
 };
 
@@ -234,6 +257,39 @@ pub struct Screen {
     pub width: usize,
     pub height: usize,
     pub texture_handle: Option<egui::TextureHandle>,
+    /// Last frame from the PPU, as 9-bit palette indices
+    pub frame: Vec<u16>,
+    /// `frame` converted through the current palette
+    pub pixels: Vec<Color32>,
+    filters: FilterPipeline,
+    scale_mode: ScaleMode,
+    aspect_ratio: AspectRatio,
+    overscan: Overscan,
+    /// Replaces the filters while loaded, drawing from the tiles in `hd_frame`
+    hd_pack: Option<HdPack>,
+    hd_frame: Frame,
+    hd_pixels: Vec<Color32>,
+    /// The texture holds `hd_pixels` rather than the filter output
+    hd_shown: bool,
+}
+
+/// Where the frame landed on screen
+#[derive(Debug, Clone, Copy)]
+pub struct ScreenArea {
+    /// The whole frame, reaching past `visible` where overscan is cropped
+    pub frame: egui::Rect,
+    pub visible: egui::Rect,
+}
+
+impl ScreenArea {
+    /// Screen position of a point in frame pixels
+    pub fn to_screen(&self, x: f32, y: f32) -> egui::Pos2 {
+        self.frame.min + egui::vec2(x, y) * self.pixel_size()
+    }
+
+    pub fn pixel_size(&self) -> egui::Vec2 {
+        self.frame.size() / egui::vec2(FRAME_WIDTH as f32, FRAME_HEIGHT as f32)
+    }
 }
 
 impl Screen {
@@ -242,31 +298,145 @@ impl Screen {
             width: FRAME_WIDTH,
             height: FRAME_HEIGHT,
             texture_handle: None,
+            frame: vec![BLANK_PIXEL; FRAME_WIDTH * FRAME_HEIGHT],
+            pixels: vec![Color32::BLACK; FRAME_WIDTH * FRAME_HEIGHT],
+            filters: FilterPipeline::default(),
+            scale_mode: ScaleMode::default(),
+            aspect_ratio: AspectRatio::default(),
+            overscan: Overscan::default(),
+            hd_pack: None,
+            hd_frame: Frame::default(),
+            hd_pixels: Vec::new(),
+            hd_shown: false,
+        }
+    }
+
+    pub fn configure(&mut self, video: &VideoSettings) {
+        self.filters.configure(video);
+        self.scale_mode = video.scale_mode;
+        self.aspect_ratio = video.aspect_ratio;
+        self.overscan = video.overscan;
+    }
+
+    pub fn set_frame(&mut self, frame: &Frame) {
+        self.frame.clear();
+        self.frame.extend_from_slice(&frame.screen);
+        if self.hd_pack.is_some() {
+            self.hd_frame.clone_from(frame);
         }
     }
 
-    pub fn set_pixels(&mut self, ui: &egui::Ui, pixels: &[Color32]) {
-        let image = egui::ColorImage::new([self.width, self.height], pixels.to_owned());
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_hd_pack(&mut self, hd_pack: Option<HdPack>) {
+        self.hd_pack = hd_pack;
+        self.hd_frame.tiles.clear();
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn hd_pack(&self) -> Option<&HdPack> {
+        self.hd_pack.as_ref()
+    }
+
+    /// Converts the frame to RGB and uploads it, also needed after a palette change
+    pub fn update_texture(&mut self, ui: &egui::Ui, palette: &Palette) {
+        self.pixels.clear();
+        self.pixels
+            .extend(self.frame.iter().map(|&index| palette.emphasized(index)));
+        // Frames from before the PPU started recording tiles can't be drawn in HD
+        self.hd_shown = match &self.hd_pack {
+            Some(hd_pack) if self.hd_frame.tiles.len() == self.frame.len() => {
+                hd_pack.compose(&self.hd_frame, palette, &mut self.hd_pixels);
+                true
+            }
+            _ => {
+                self.filters.apply(&self.frame, &self.pixels);
+                false
+            }
+        };
+        let (pixels, width, height) = self.output();
+        let image = egui::ColorImage::new([width, height], pixels.to_vec());
+        let options = self.filters.texture_options();
         if let Some(texture) = &mut self.texture_handle {
-            texture.set(image, egui::TextureOptions::NEAREST);
+            texture.set(image, options);
         } else {
-            self.texture_handle =
-                Some(ui.load_texture("screen", image, egui::TextureOptions::NEAREST));
+            self.texture_handle = Some(ui.load_texture("screen", image, options));
         }
     }
 
-    pub fn render(&self, ui: &mut egui::Ui) {
-        if let Some(texture) = &self.texture_handle {
+    /// Draws the frame cropped and scaled to fit, returning where it ended up
+    pub fn render(&self, ui: &mut egui::Ui) -> Option<ScreenArea> {
+        self.texture_handle.as_ref().map(|texture| {
+            let ((left, top), (right, bottom)) = self.overscan.visible();
+            let (width, height) = (right - left, bottom - top);
             let available = ui.available_size();
-            let aspect_ratio = self.width as f32 / self.height as f32;
-            let fitted_size = if available.x / available.y > aspect_ratio {
-                egui::Vec2::new(available.y * aspect_ratio, available.y)
-            } else {
-                egui::Vec2::new(available.x, available.x / aspect_ratio)
+            let size = egui::vec2(
+                self.aspect_ratio.display_width(width, height),
+                height as f32,
+            );
+            let fit = (available.x / size.x).min(available.y / size.y);
+            let scale = match self.scale_mode {
+                ScaleMode::Integer if fit >= 1.0 => fit.floor(),
+                _ => fit,
             };
-            ui.image((texture.id(), fitted_size));
+            let frame_size = egui::vec2(self.width as f32, self.height as f32);
+            let uv = egui::Rect::from_min_max(
+                (egui::vec2(left as f32, top as f32) / frame_size).to_pos2(),
+                (egui::vec2(right as f32, bottom as f32) / frame_size).to_pos2(),
+            );
+            let visible = ui
+                .add(egui::Image::new((texture.id(), size * scale)).uv(uv))
+                .rect;
+            let pixel = visible.size() / egui::vec2(width as f32, height as f32);
+            ScreenArea {
+                frame: egui::Rect::from_min_size(
+                    visible.min - egui::vec2(left as f32, top as f32) * pixel,
+                    frame_size * pixel,
+                ),
+                visible,
+            }
+        })
+    }
+
+    /// The unfiltered frame as shown: cropped, and resampled to the aspect ratio
+    pub fn screenshot(&self) -> (Vec<Color32>, usize, usize) {
+        let ((left, top), (right, bottom)) = self.overscan.visible();
+        let (width, height) = (right - left, bottom - top);
+        let out_width = self.aspect_ratio.display_width(width, height).round() as usize;
+        let pixels = (0..height * out_width)
+            .map(|i| {
+                let (x, y) = (i % out_width, i / out_width);
+                self.pixels[(top + y) * self.width + left + x * width / out_width]
+            })
+            .collect();
+        (pixels, out_width, height)
+    }
+
+    /// What the texture is made of: the video filter output, or the HD pack's
+    fn output(&self) -> (&[Color32], usize, usize) {
+        if self.hd_shown {
+            let scale = self.hd_pack.as_ref().map_or(1, HdPack::scale);
+            (&self.hd_pixels, self.width * scale, self.height * scale)
+        } else {
+            let filters = &self.filters;
+            (&filters.pixels, filters.width, filters.height)
         }
     }
+
+    /// The frame as it comes out of the video filter or HD pack, cropped the same way
+    pub fn filtered_screenshot(&self) -> (Vec<Color32>, usize, usize) {
+        let ((left, top), (right, bottom)) = self.overscan.visible();
+        let (output, output_width, output_height) = self.output();
+        let scale_x = |x: usize| x * output_width / self.width;
+        let scale_y = |y: usize| y * output_height / self.height;
+        let (left, right) = (scale_x(left), scale_x(right));
+        let (top, bottom) = (scale_y(top), scale_y(bottom));
+        let width = right - left;
+        let pixels = (top..bottom)
+            .flat_map(|y| &output[y * output_width + left..y * output_width + right])
+            .copied()
+            .collect();
+        (pixels, width, bottom - top)
+    }
 }
 
 fn draw_settings_panel_content(
@@ -278,7 +448,11 @@ fn draw_settings_panel_content(
         egui::Layout::left_to_right(egui::Align::TOP).with_cross_justify(true),
         |ui| {
             ui.vertical(|ui| {
-                for (ind, label) in ["Keybindings"].iter().enumerate() {
+                #[cfg(not(target_arch = "wasm32"))]
+                let tabs = ["Keybindings", "Video", "Screenshots"].as_slice();
+                #[cfg(target_arch = "wasm32")]
+                let tabs = ["Keybindings", "Video"].as_slice();
+                for (ind, label) in tabs.iter().enumerate() {
                     if ui
                         .add_sized(
                             [100., 30.],
@@ -294,6 +468,9 @@ fn draw_settings_panel_content(
             ui.separator();
             match selected_tab.load(Ordering::Relaxed) {
                 0 => draw_settings_keybindings(ui, settings),
+                1 => draw_settings_video(ui, settings),
+                #[cfg(not(target_arch = "wasm32"))]
+                2 => draw_settings_screenshots(ui, settings),
                 _ => unreachable!(),
             };
         },
@@ -419,123 +596,825 @@ fn draw_settings_keybindings(ui: &mut egui::Ui, settings: &Arc<Mutex<Settings>>)
     });
 }
 
-pub struct Ui {
-    screen: Screen,
-    runner: PlatformRunner,
-    app_icon_texture: egui::TextureHandle,
+/// Palette picker and per-entry color editor
+fn draw_settings_video(ui: &mut egui::Ui, settings: &Arc<Mutex<Settings>>) {
+    /// The last built palette, so files are only read again when the settings change
+    struct PaletteCache {
+        video: Option<VideoSettings>,
+        palette: Palette,
+        error: Option<String>,
+        selected: u8,
+    }
+    static CACHE: OnceLock<Mutex<PaletteCache>> = OnceLock::new();
+    let mut cache = CACHE
+        .get_or_init(|| {
+            Mutex::new(PaletteCache {
+                video: None,
+                palette: Palette::default(),
+                error: None,
+                selected: 0,
+            })
+        })
+        .lock();
+    let mut settings = settings.lock();
+    let video = &mut settings.video;
 
-    #[cfg(not(target_arch = "wasm32"))]
-    snapshot: DebugSnapshot,
+    if cache.video.as_ref() != Some(video) {
+        match video.build_palette() {
+            Ok(palette) => {
+                cache.palette = palette;
+                cache.error = None;
+            }
+            Err(e) => cache.error = Some(e.to_string()),
+        }
+        cache.video = Some(video.clone());
+    }
 
-    input_manager: InputManager,
-    last_controller_input: u16,
+    ui.vertical(|ui| {
+        ui.label(egui::RichText::new("Filter").strong().size(14.0));
+        ui.add_space(4.0);
+        egui::ComboBox::from_id_salt("video_filter")
+            .selected_text(video.filter.name())
+            .show_ui(ui, |ui| {
+                for filter in VideoFilter::ALL {
+                    ui.selectable_value(&mut video.filter, filter, filter.name());
+                }
+            });
+        if video.filter == VideoFilter::Ntsc {
+            let ntsc = &mut video.ntsc;
+            egui::Grid::new("ntsc_settings").show(ui, |ui| {
+                for (label, value) in [
+                    ("Sharpness", &mut ntsc.sharpness),
+                    ("Saturation", &mut ntsc.saturation),
+                    ("Hue", &mut ntsc.hue),
+                ] {
+                    ui.label(label);
+                    ui.add(egui::Slider::new(value, -1.0..=1.0));
+                    ui.end_row();
+                }
+            });
+            if ui.button("Reset").clicked() {
+                *ntsc = NtscSettings::default();
+            }
+            ui.label("Colors are decoded from the signal, the palette below is not used");
+        }
+        ui.add_space(4.0);
+        egui::Grid::new("crt_masks").show(ui, |ui| {
+            ui.label("Scanlines");
+            ui.add(egui::Slider::new(&mut video.scanlines, 0.0..=1.0));
+            ui.end_row();
+            ui.label("Aperture grille");
+            ui.add(egui::Slider::new(&mut video.aperture_grille, 0.0..=1.0));
+            ui.end_row();
+            ui.label("Scaling");
+            egui::ComboBox::from_id_salt("scale_mode")
+                .selected_text(video.scale_mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in ScaleMode::ALL {
+                        ui.selectable_value(&mut video.scale_mode, mode, mode.name());
+                    }
+                });
+            ui.end_row();
+            ui.label("Aspect ratio");
+            egui::ComboBox::from_id_salt("aspect_ratio")
+                .selected_text(video.aspect_ratio.name())
+                .show_ui(ui, |ui| {
+                    for ratio in AspectRatio::ALL {
+                        ui.selectable_value(&mut video.aspect_ratio, ratio, ratio.name());
+                    }
+                });
+            ui.end_row();
+            ui.label("Fullscreen");
+            ui.checkbox(&mut video.fullscreen, "");
+            ui.end_row();
+        });
+        ui.add_space(8.0);
 
-    emu_error_msg: Option<String>,
-    #[cfg(not(target_arch = "wasm32"))]
-    log: bool,
+        ui.label(egui::RichText::new("Overscan").strong().size(14.0));
+        ui.add_space(4.0);
+        let overscan = &mut video.overscan;
+        egui::Grid::new("overscan").show(ui, |ui| {
+            for (label, edge) in [
+                ("Top", &mut overscan.top),
+                ("Bottom", &mut overscan.bottom),
+                ("Left", &mut overscan.left),
+                ("Right", &mut overscan.right),
+            ] {
+                ui.label(label);
+                ui.add(egui::Slider::new(edge, 0..=Overscan::MAX).suffix(" px"));
+                ui.end_row();
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("TV (8 lines top and bottom)").clicked() {
+                *overscan = Overscan {
+                    top: 8,
+                    bottom: 8,
+                    ..Default::default()
+                };
+            }
+            if ui.button("None").clicked() {
+                *overscan = Overscan::default();
+            }
+        });
+        ui.add_space(12.0);
 
-    #[cfg(not(target_arch = "wasm32"))]
-    mem_search: String,
-    #[cfg(not(target_arch = "wasm32"))]
-    prev_mem_search_addr: usize,
+        ui.label(egui::RichText::new("Palette").strong().size(14.0));
+        ui.add_space(4.0);
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("palette_kind")
+                .selected_text(video.palette.name())
+                .show_ui(ui, |ui| {
+                    for kind in PaletteKind::ALL {
+                        ui.selectable_value(&mut video.palette, kind, kind.name());
+                    }
+                });
+            #[cfg(not(target_arch = "wasm32"))]
+            if video.palette == PaletteKind::Custom && ui.button("📂 Load .pal").clicked() {
+                let dialog = rfd::FileDialog::new().add_filter("NES palette", &["pal"]);
+                if let Some(path) = dialog.pick_file() {
+                    video.palette_file = Some(path);
+                }
+            }
+        });
+        if video.palette == PaletteKind::Custom {
+            match &video.palette_file {
+                Some(path) => ui.label(path.display().to_string()),
+                None => ui.label("No palette file loaded, using 2C02"),
+            };
+        }
+        if let Some(error) = &cache.error {
+            ui.colored_label(Color32::RED, error);
+        }
+        ui.add_space(8.0);
 
-    show_about: bool,
-    show_settings: Arc<AtomicBool>,
-    #[cfg(not(target_arch = "wasm32"))]
-    show_debug_panels: bool,
+        const SWATCH: f32 = 24.0;
+        for row in 0..4 {
+            ui.horizontal(|ui| {
+                ui.spacing_mut().item_spacing.x = 2.0;
+                for col in 0..16 {
+                    let index = (row * 16 + col) as u8;
+                    let (rect, response) =
+                        ui.allocate_exact_size(egui::Vec2::splat(SWATCH), egui::Sense::click());
+                    ui.painter()
+                        .rect_filled(rect, 0.0, cache.palette.color(index));
+                    if video.palette_edit(index).is_some() {
+                        ui.painter().circle_filled(
+                            rect.right_top() + egui::vec2(-4.0, 4.0),
+                            2.5,
+                            Color32::WHITE,
+                        );
+                    }
+                    if cache.selected == index {
+                        ui.painter().rect_stroke(
+                            rect,
+                            0.0,
+                            egui::Stroke::new(2.0, Color32::WHITE),
+                            egui::StrokeKind::Inside,
+                        );
+                    }
+                    if response.on_hover_text(format!("${:02X}", index)).clicked() {
+                        cache.selected = index;
+                    }
+                }
+            });
+        }
+        ui.add_space(8.0);
+
+        let index = cache.selected;
+        ui.horizontal(|ui| {
+            ui.label(format!("Entry ${:02X}", index));
+            let color = cache.palette.color(index);
+            let mut rgb = [color.r(), color.g(), color.b()];
+            if egui::color_picker::color_edit_button_srgb(ui, &mut rgb).changed() {
+                video.set_palette_edit(index, rgb);
+            }
+            ui.monospace(format!("#{:02X}{:02X}{:02X}", rgb[0], rgb[1], rgb[2]));
+            ui.add_enabled_ui(video.palette_edit(index).is_some(), |ui| {
+                if ui.button("↻").on_hover_text("Reset entry").clicked() {
+                    video.reset_palette_edit(index);
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(!video.palette_edits.is_empty(), |ui| {
+                if ui.button("Reset all edits").clicked() {
+                    video.palette_edits.clear();
+                }
+            });
+            #[cfg(not(target_arch = "wasm32"))]
+            if ui.button("💾 Export .pal").clicked() {
+                let dialog = rfd::FileDialog::new()
+                    .add_filter("NES palette", &["pal"])
+                    .set_file_name("palette.pal");
+                if let Some(path) = dialog.save_file()
+                    && let Err(e) = std::fs::write(&path, cache.palette.to_pal())
+                {
+                    error!("Couldn't save palette: {e}");
+                }
+            }
+        });
+    });
+}
 
-    settings: Arc<Mutex<Settings>>,
-    settings_selected_tab: Arc<AtomicUsize>,
+#[cfg(not(target_arch = "wasm32"))]
+fn draw_settings_screenshots(ui: &mut egui::Ui, settings: &Arc<Mutex<Settings>>) {
+    let mut settings = settings.lock();
+    let screenshots = &mut settings.screenshots;
 
-    running: bool,
-    paused: bool,
-    frame_stats: FrameStats,
+    ui.vertical(|ui| {
+        ui.label(egui::RichText::new("Folder").strong().size(14.0));
+        ui.add_space(4.0);
+        ui.horizontal(|ui| {
+            match &screenshots.directory {
+                Some(path) => ui.label(path.display().to_string()),
+                None => ui.label("Working directory"),
+            };
+            if ui.button("📂 Choose").clicked()
+                && let Some(path) = rfd::FileDialog::new().pick_folder()
+            {
+                screenshots.directory = Some(path);
+            }
+            if screenshots.directory.is_some() && ui.button("↻").clicked() {
+                screenshots.directory = None;
+            }
+        });
+        ui.add_space(8.0);
+
+        ui.label(egui::RichText::new("File name").strong().size(14.0));
+        ui.add_space(4.0);
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut screenshots.template);
+            ui.label(".png");
+        });
+        ui.horizontal(|ui| {
+            for field in ScreenshotSettings::FIELDS {
+                if ui.small_button(field).clicked() {
+                    screenshots.template.push_str(field);
+                }
+            }
+        });
+        let example = screenshots.file_name(&ScreenshotInfo {
+            title: "Game",
+            sha1: "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+            frame: 1234,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        });
+        ui.weak(format!("e.g. {example}.png"));
+        ui.add_space(8.0);
+
+        ui.checkbox(
+            &mut screenshots.filtered,
+            "Save with the video filter applied",
+        )
+        .on_hover_text("Otherwise one pixel per NES pixel, stretched to the aspect ratio");
+    });
 }
 
-impl Ui {
-    pub fn new(ctx: &egui::Context) -> Self {
-        let app_icon = Self::app_icon();
-        let app_icon_img = ImageData::Color(Arc::new(ColorImage::from_rgba_unmultiplied(
-            [app_icon.width as usize, app_icon.height as usize],
-            &app_icon.rgba,
-        )));
-        let app_icon_texture =
-            ctx.load_texture("app_icon", app_icon_img, egui::TextureOptions::NEAREST);
+#[cfg(not(target_arch = "wasm32"))]
+struct BreakpointEditor {
+    breakpoints: Vec<Breakpoint>,
+    next_id: u32,
+    addr: String,
+    kind: BreakKind,
+    space: AddrSpace,
+    condition: String,
+    hit_target: String,
+    error: Option<String>,
+    last_hit: Option<BreakHit>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for BreakpointEditor {
+    fn default() -> Self {
         Self {
-            screen: Screen::new(),
-            runner: Default::default(),
-            app_icon_texture,
+            breakpoints: Vec::new(),
+            next_id: 0,
+            addr: String::new(),
+            kind: BreakKind::EXECUTE,
+            space: AddrSpace::Cpu,
+            condition: String::new(),
+            hit_target: String::new(),
+            error: None,
+            last_hit: None,
+        }
+    }
+}
 
-            #[cfg(not(target_arch = "wasm32"))]
-            snapshot: Default::default(),
+#[cfg(not(target_arch = "wasm32"))]
+impl BreakpointEditor {
+    fn is_execute_at(bp: &Breakpoint, addr: u16) -> bool {
+        bp.kind == BreakKind::EXECUTE
+            && bp.space == AddrSpace::Cpu
+            && bp.start == addr
+            && bp.end == addr
+    }
 
-            input_manager: Default::default(),
-            last_controller_input: 0,
+    fn execute_at(&self, addr: u16) -> Option<&Breakpoint> {
+        self.breakpoints
+            .iter()
+            .find(|bp| Self::is_execute_at(bp, addr))
+    }
 
-            emu_error_msg: None,
-            #[cfg(not(target_arch = "wasm32"))]
-            log: get_args().log,
+    fn toggle_execute(&mut self, addr: u16) {
+        if self.execute_at(addr).is_some() {
+            self.breakpoints.retain(|bp| !Self::is_execute_at(bp, addr));
+            return;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.push(Breakpoint {
+            id,
+            enabled: true,
+            kind: BreakKind::EXECUTE,
+            space: AddrSpace::Cpu,
+            start: addr,
+            end: addr,
+            condition: None,
+            hit_target: 0,
+            hits: 0,
+        });
+    }
 
-            #[cfg(not(target_arch = "wasm32"))]
-            mem_search: "".into(),
-            #[cfg(not(target_arch = "wasm32"))]
-            prev_mem_search_addr: 0,
+    fn add_write(&mut self, start: u16, end: u16) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.push(Breakpoint {
+            id,
+            enabled: true,
+            kind: BreakKind::WRITE,
+            space: AddrSpace::Cpu,
+            start,
+            end,
+            condition: None,
+            hit_target: 0,
+            hits: 0,
+        });
+    }
 
-            show_about: false,
-            show_settings: Arc::new(AtomicBool::new(false)),
-            #[cfg(all(not(target_arch = "wasm32"), debug_assertions))]
-            show_debug_panels: true,
-            #[cfg(all(not(target_arch = "wasm32"), not(debug_assertions)))]
-            show_debug_panels: false,
+    fn build(&mut self, symbols: &SymbolTable) -> anyhow::Result<Breakpoint> {
+        let (start, end) = match self.addr.split_once('-') {
+            Some((start, end)) => (parse_address(start, symbols), parse_address(end, symbols)),
+            None => (
+                parse_address(&self.addr, symbols),
+                parse_address(&self.addr, symbols),
+            ),
+        };
+        let (Some(start), Some(end)) = (start, end) else {
+            anyhow::bail!("Invalid address \"{}\"", self.addr);
+        };
+        if start > end || end > 0xFFFF {
+            anyhow::bail!("Invalid address range");
+        }
+        if self.kind.is_empty() {
+            anyhow::bail!("Select at least one of R/W/X");
+        }
+        if self.space == AddrSpace::Ppu && self.kind.contains(BreakKind::EXECUTE) {
+            anyhow::bail!("PPU breakpoints can only watch reads and writes");
+        }
+        let condition = if self.condition.trim().is_empty() {
+            None
+        } else {
+            Some(Condition::parse(&self.condition)?)
+        };
+        let hit_target = if self.hit_target.trim().is_empty() {
+            0
+        } else {
+            parse_number(&self.hit_target).ok_or_else(|| anyhow::anyhow!("Invalid hit count"))?
+                as u32
+        };
 
-            settings: Arc::new(Mutex::new(Settings::new())),
-            settings_selected_tab: Arc::new(AtomicUsize::new(0)),
+        let id = self.next_id;
+        self.next_id += 1;
+        Ok(Breakpoint {
+            id,
+            enabled: true,
+            kind: self.kind,
+            space: self.space,
+            start: start as u16,
+            end: end as u16,
+            condition,
+            hit_target,
+            hits: 0,
+        })
+    }
+}
 
-            running: false,
-            paused: false,
-            frame_stats: FrameStats::new(),
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct DisassemblyView {
+    /// Address the view is pinned to, following PC when `None`
+    addr: Option<u16>,
+    goto: String,
+    history: Vec<u16>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct MemoryEditor {
+    space: MemorySpace,
+    /// Address of the first row shown
+    start_addr: usize,
+    /// Window the emulator was last asked to publish
+    requested: Option<(MemorySpace, usize)>,
+    goto: String,
+    /// Wheel movement not yet turned into whole rows
+    scroll: f32,
+    /// Byte being edited and its pending hex text
+    selected: Option<usize>,
+    edit: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl MemoryEditor {
+    fn scroll_to(&mut self, addr: usize, size: usize) {
+        let last_start = size.div_ceil(BYTES_PER_ROW).saturating_sub(ROWS_TO_SHOW) * BYTES_PER_ROW;
+        self.start_addr = (addr - addr % BYTES_PER_ROW).min(last_start);
+    }
+
+    /// Keeps `addr` on screen, with a few rows of context above it
+    fn reveal(&mut self, addr: usize, size: usize) {
+        let visible = self.start_addr..self.start_addr + MEM_BLOCK_SIZE;
+        if !visible.contains(&addr) {
+            self.scroll_to(addr.saturating_sub(ROWS_TO_SHOW / 2 * BYTES_PER_ROW), size);
         }
     }
+}
 
-    pub fn handle_input(&mut self, ui: &egui::Ui) {
-        let keys = {
-            let settings = self.settings.lock();
-            settings.keybindings.clone()
-        };
-        let (actions, controller, dropped) = self.input_manager.update(ui, &keys);
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct RamSearchView {
+    search: RamSearch,
+    comparison: Comparison,
+    /// Compare against this number instead of the previous values
+    value: String,
+    error: Option<String>,
+}
 
-        let input_val = controller.to_u8() as u16;
+/// A debug image regenerated whenever a new snapshot arrives
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct ViewerTexture {
+    texture: Option<egui::TextureHandle>,
+    serial: u64,
+}
 
-        if input_val != self.last_controller_input {
-            self.runner
-                .send_command(Command::ControllerInputs(input_val));
-            self.last_controller_input = input_val;
+#[cfg(not(target_arch = "wasm32"))]
+impl ViewerTexture {
+    fn update(
+        &mut self,
+        ctx: &Context,
+        name: &str,
+        serial: u64,
+        size: [usize; 2],
+        render: impl FnOnce() -> Vec<Color32>,
+    ) -> &egui::TextureHandle {
+        if self.texture.is_none() || self.serial != serial {
+            let image = ColorImage::new(size, render());
+            match &mut self.texture {
+                Some(texture) => texture.set(image, egui::TextureOptions::NEAREST),
+                None => {
+                    self.texture =
+                        Some(ctx.load_texture(name, image, egui::TextureOptions::NEAREST))
+                }
+            }
+            self.serial = serial;
         }
+        self.texture.as_ref().unwrap()
+    }
+}
 
-        for action in actions {
-            self.dispatch_action(ui, action);
-        }
+#[cfg(not(target_arch = "wasm32"))]
+struct NametableViewer {
+    open: bool,
+    image: ViewerTexture,
+    show_scroll: bool,
+    show_tile_grid: bool,
+    show_attribute_grid: bool,
+}
 
-        if let Some(source) = dropped {
-            self.start(source);
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for NametableViewer {
+    fn default() -> Self {
+        Self {
+            open: false,
+            image: Default::default(),
+            show_scroll: true,
+            show_tile_grid: false,
+            show_attribute_grid: false,
         }
     }
+}
 
-    fn dispatch_action(&mut self, ctx: &Context, action: Action) {
-        match action {
-            Action::PauseResume => {
-                if self.paused {
-                    self.runner.resume();
-                    self.paused = false;
-                } else {
-                    self.runner.pause();
-                    self.paused = true;
-                }
-            }
-            Action::Step if self.paused => {
-                self.runner.step();
-            }
-            Action::LoadState => {
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct ChrViewer {
+    open: bool,
+    /// 0-3 are the background palettes, 4-7 the sprite ones
+    palette: usize,
+    images: [ViewerTexture; 2],
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct SpriteViewer {
+    /// Draw sprite bounding boxes over the game screen
+    overlay: bool,
+    image: ViewerTexture,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct ScanlineViewer {
+    open: bool,
+    /// Mark scroll splits over the game screen
+    overlay: bool,
+    changes_only: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct EventViewer {
+    open: bool,
+    hidden: HashSet<PpuEventKind>,
+    selected: Option<PpuEvent>,
+    /// Scroll the list to the selection on the next frame
+    reveal: bool,
+}
+
+/// Frames of history kept for each watch's sparkline
+#[cfg(not(target_arch = "wasm32"))]
+const WATCH_HISTORY: usize = 240;
+/// How long a watch stays highlighted after its value changes
+#[cfg(not(target_arch = "wasm32"))]
+const WATCH_HIGHLIGHT_FRAMES: u64 = 30;
+
+#[cfg(not(target_arch = "wasm32"))]
+struct Watch {
+    expr: String,
+    addr: u16,
+    kind: WatchType,
+    value: Option<[u8; WATCH_BYTES]>,
+    history: VecDeque<f64>,
+    changed_frame: Option<u64>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Watch {
+    fn new(expr: String, addr: u16, kind: WatchType) -> Self {
+        Self {
+            expr,
+            addr,
+            kind,
+            value: None,
+            history: VecDeque::with_capacity(WATCH_HISTORY),
+            changed_frame: None,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct WatchList {
+    watches: Vec<Watch>,
+    last_frame: u64,
+    expr: String,
+    kind: WatchType,
+    error: Option<String>,
+}
+
+/// Cheats of the running ROM, saved under its SHA1 whenever they change
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct CheatManager {
+    cheats: Vec<Cheat>,
+    rom_hash: Option<String>,
+    code: String,
+    description: String,
+    error: Option<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CheatManager {
+    fn save(&self) {
+        if let Some(hash) = &self.rom_hash {
+            cheats::save_for_rom(hash, &self.cheats)
+                .unwrap_or_else(|e| error!("Failed to save cheats: {e}"));
+        }
+    }
+}
+
+/// Source files referenced by ca65 debug info, read on first use
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct SourceView {
+    files: HashMap<PathBuf, Option<Vec<String>>>,
+}
+
+pub struct Ui {
+    screen: Screen,
+    /// The screen texture needs converting again, for a new frame or palette
+    screen_stale: bool,
+    runner: PlatformRunner,
+    app_icon_texture: egui::TextureHandle,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    snapshot: DebugSnapshot,
+    /// Bumped for every snapshot received, so viewers know when to redraw
+    #[cfg(not(target_arch = "wasm32"))]
+    snapshot_serial: u64,
+
+    input_manager: InputManager,
+    last_controller_input: u16,
+
+    emu_error_msg: Option<String>,
+    #[cfg(not(target_arch = "wasm32"))]
+    log: bool,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    mem_editor: MemoryEditor,
+    #[cfg(not(target_arch = "wasm32"))]
+    ram_search: RamSearchView,
+    #[cfg(not(target_arch = "wasm32"))]
+    cheat_manager: CheatManager,
+    #[cfg(not(target_arch = "wasm32"))]
+    watch_list: WatchList,
+    #[cfg(not(target_arch = "wasm32"))]
+    nametable_viewer: NametableViewer,
+    #[cfg(not(target_arch = "wasm32"))]
+    chr_viewer: ChrViewer,
+    #[cfg(not(target_arch = "wasm32"))]
+    sprite_viewer: SpriteViewer,
+    #[cfg(not(target_arch = "wasm32"))]
+    event_viewer: EventViewer,
+    #[cfg(not(target_arch = "wasm32"))]
+    scanline_viewer: ScanlineViewer,
+    #[cfg(not(target_arch = "wasm32"))]
+    breakpoint_editor: BreakpointEditor,
+    #[cfg(not(target_arch = "wasm32"))]
+    run_to_addr: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    disasm_view: DisassemblyView,
+    #[cfg(not(target_arch = "wasm32"))]
+    source_view: SourceView,
+    #[cfg(not(target_arch = "wasm32"))]
+    cdl_overlay: bool,
+    /// File the emulator is recording to
+    #[cfg(not(target_arch = "wasm32"))]
+    recording: Option<PathBuf>,
+    /// File name of the running ROM, for screenshot names
+    #[cfg(not(target_arch = "wasm32"))]
+    rom_title: Option<String>,
+
+    show_about: bool,
+    show_settings: Arc<AtomicBool>,
+    #[cfg(not(target_arch = "wasm32"))]
+    show_debug_panels: bool,
+    /// What the emulator was last told to put in its snapshots, `None` to tell it again
+    #[cfg(not(target_arch = "wasm32"))]
+    debug_panels: Option<DebugPanels>,
+
+    settings: Arc<Mutex<Settings>>,
+    settings_selected_tab: Arc<AtomicUsize>,
+    palette: Palette,
+    /// Video settings `palette` was last built from
+    video: Option<VideoSettings>,
+
+    running: bool,
+    paused: bool,
+    frame_stats: FrameStats,
+}
+
+impl Ui {
+    pub fn new(ctx: &egui::Context) -> Self {
+        let app_icon = Self::app_icon();
+        let app_icon_img = ImageData::Color(Arc::new(ColorImage::from_rgba_unmultiplied(
+            [app_icon.width as usize, app_icon.height as usize],
+            &app_icon.rgba,
+        )));
+        let app_icon_texture =
+            ctx.load_texture("app_icon", app_icon_img, egui::TextureOptions::NEAREST);
+        Self {
+            screen: Screen::new(),
+            screen_stale: false,
+            runner: Default::default(),
+            app_icon_texture,
+
+            #[cfg(not(target_arch = "wasm32"))]
+            snapshot: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            snapshot_serial: 0,
+
+            input_manager: Default::default(),
+            last_controller_input: 0,
+
+            emu_error_msg: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            log: get_args().log,
+
+            #[cfg(not(target_arch = "wasm32"))]
+            mem_editor: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            ram_search: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            cheat_manager: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            watch_list: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            nametable_viewer: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            chr_viewer: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            sprite_viewer: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            event_viewer: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            scanline_viewer: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            breakpoint_editor: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            run_to_addr: "".into(),
+            #[cfg(not(target_arch = "wasm32"))]
+            disasm_view: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            source_view: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            cdl_overlay: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            recording: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            rom_title: None,
+
+            show_about: false,
+            show_settings: Arc::new(AtomicBool::new(false)),
+            #[cfg(all(not(target_arch = "wasm32"), debug_assertions))]
+            show_debug_panels: true,
+            #[cfg(all(not(target_arch = "wasm32"), not(debug_assertions)))]
+            show_debug_panels: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            debug_panels: None,
+
+            settings: Arc::new(Mutex::new(Settings::new())),
+            settings_selected_tab: Arc::new(AtomicUsize::new(0)),
+            palette: Default::default(),
+            video: None,
+
+            running: false,
+            paused: false,
+            frame_stats: FrameStats::new(),
+        }
+    }
+
+    pub fn handle_input(&mut self, ui: &egui::Ui) {
+        let keys = {
+            let settings = self.settings.lock();
+            settings.keybindings.clone()
+        };
+        let (actions, controller, dropped) = self.input_manager.update(ui, &keys);
+
+        let input_val = controller.to_u8() as u16;
+
+        if input_val != self.last_controller_input {
+            self.runner
+                .send_command(Command::ControllerInputs(input_val));
+            self.last_controller_input = input_val;
+        }
+
+        for action in actions {
+            self.dispatch_action(ui, action);
+        }
+
+        if let Some(source) = dropped {
+            self.start(source);
+        }
+    }
+
+    fn dispatch_action(&mut self, ctx: &Context, action: Action) {
+        match action {
+            Action::PauseResume => {
+                if self.paused {
+                    self.runner.resume();
+                    self.paused = false;
+                } else {
+                    self.runner.pause();
+                    self.paused = true;
+                }
+            }
+            Action::Step if self.paused => {
+                self.runner.step(StepKind::Frame);
+            }
+            Action::StepInstruction if self.paused => {
+                self.runner.step(StepKind::Instruction);
+            }
+            Action::StepOver if self.paused => {
+                self.runner.step(StepKind::Over);
+            }
+            Action::StepOut if self.paused => {
+                self.runner.step(StepKind::Out);
+            }
+            Action::LoadState => {
                 self.runner.pick_state_file();
             }
             Action::SaveState => {
@@ -545,6 +1424,17 @@ impl Ui {
             Action::TakeScreenshot => {
                 self.take_screenshot();
             }
+            #[cfg(not(target_arch = "wasm32"))]
+            Action::CopyScreenshot => {
+                self.copy_screenshot(ctx);
+            }
+            Action::ToggleFullscreen => {
+                let mut settings = self.settings.lock();
+                settings.video.fullscreen = !settings.video.fullscreen;
+                if let Err(e) = settings.save_to_file() {
+                    error!("{}", e);
+                }
+            }
             Action::OpenRom => {
                 self.open_rom();
             }
@@ -569,6 +1459,15 @@ impl Ui {
         if self.running {
             self.stop();
         }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.rom_title = match &rom {
+                FileDataSource::Path(path) => path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned()),
+                _ => None,
+            };
+        }
         self.runner.start(rom);
         self.running = true;
         self.paused = false;
@@ -632,6 +1531,10 @@ impl Ui {
                     {
                         self.runner.pick_state_file();
                     }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if ui.button("🏷 Load symbols...").clicked() {
+                        self.runner.pick_symbol_files();
+                    }
                 });
                 #[cfg(not(target_arch = "wasm32"))]
                 {
@@ -652,14 +1555,34 @@ impl Ui {
             }
             ui.menu_button("Emulator", |ui| {
                 ui.add_enabled_ui(self.running && self.paused, |ui| {
-                    if ui
-                        .add(
-                            egui::Button::new("⤵ Step")
-                                .shortcut_text(keybindings.format_shortcut(ui, Action::Step)),
-                        )
-                        .clicked()
-                    {
-                        self.runner.step();
+                    for (label, action, kind) in [
+                        ("⤵ Step frame", Action::Step, StepKind::Frame),
+                        (
+                            "➡ Step instruction",
+                            Action::StepInstruction,
+                            StepKind::Instruction,
+                        ),
+                        ("↷ Step over", Action::StepOver, StepKind::Over),
+                        ("↑ Step out", Action::StepOut, StepKind::Out),
+                    ] {
+                        if ui
+                            .add(
+                                egui::Button::new(label)
+                                    .shortcut_text(keybindings.format_shortcut(ui, action)),
+                            )
+                            .clicked()
+                        {
+                            self.runner.step(kind);
+                        }
+                    }
+                    for (label, kind) in [
+                        ("↓ Step scanline", StepKind::Scanline),
+                        ("⏭ Run to NMI", StepKind::Nmi),
+                        ("⏭ Run to IRQ", StepKind::Irq),
+                    ] {
+                        if ui.button(label).clicked() {
+                            self.runner.step(kind);
+                        }
                     }
                 });
                 ui.add_enabled_ui(self.running && self.paused, |ui| {
@@ -693,6 +1616,20 @@ impl Ui {
                         self.stop();
                     }
                 });
+                ui.menu_button("🎞 Layers", |ui| {
+                    let mut settings = self.settings.lock();
+                    let render = &mut settings.video.render;
+                    ui.checkbox(&mut render.hide_bg, "Hide background");
+                    ui.checkbox(&mut render.hide_sprites, "Hide sprites");
+                    ui.checkbox(&mut render.hide_left_column, "Hide left 8 pixels");
+                    ui.separator();
+                    ui.checkbox(&mut render.no_sprite_limit, "Remove sprite limit");
+                });
+                let mut oam_decay = self.settings.lock().emulation.oam_decay;
+                if ui.checkbox(&mut oam_decay, "OAM decay").changed() {
+                    self.settings.lock().emulation.oam_decay = oam_decay;
+                    self.runner.send_command(Command::SetOamDecay(oam_decay));
+                }
                 #[cfg(not(target_arch = "wasm32"))]
                 {
                     ui.separator();
@@ -705,8 +1642,62 @@ impl Ui {
                         {
                             self.take_screenshot();
                         }
+                        if ui
+                            .add(egui::Button::new("📋 Copy screenshot").shortcut_text(
+                                keybindings.format_shortcut(ui, Action::CopyScreenshot),
+                            ))
+                            .clicked()
+                        {
+                            self.copy_screenshot(ui.ctx());
+                        }
+                        if self.recording.is_some() {
+                            if ui.button("⏹ Stop recording").clicked() {
+                                self.runner.send_command(Command::StopRecording);
+                            }
+                        } else {
+                            ui.menu_button("⏺ Record", |ui| {
+                                for format in RecordingFormat::ALL {
+                                    if ui.button(format.name()).clicked() {
+                                        self.start_recording(format);
+                                    }
+                                }
+                            });
+                        }
                     });
+                    let hd_pack = self
+                        .screen
+                        .hd_pack()
+                        .map(|pack| pack.path().display().to_string());
+                    match hd_pack {
+                        Some(path) => {
+                            if ui.button("🖼 Unload HD pack").on_hover_text(path).clicked() {
+                                self.set_hd_pack(None);
+                            }
+                        }
+                        None => {
+                            if ui.button("🖼 Load HD pack").clicked() {
+                                self.load_hd_pack();
+                            }
+                        }
+                    }
                     ui.checkbox(&mut self.show_debug_panels, "Show debug panels");
+                    if ui
+                        .add(egui::Button::new("⛶ Fullscreen").shortcut_text(
+                            keybindings.format_shortcut(ui, Action::ToggleFullscreen),
+                        ))
+                        .clicked()
+                    {
+                        self.dispatch_action(ui.ctx(), Action::ToggleFullscreen);
+                    }
+                    ui.menu_button("🔍 PPU viewers", |ui| {
+                        ui.checkbox(&mut self.nametable_viewer.open, "Nametables");
+                        ui.checkbox(&mut self.chr_viewer.open, "Pattern tables");
+                        ui.checkbox(&mut self.event_viewer.open, "Events");
+                        ui.checkbox(&mut self.scanline_viewer.open, "Scanline registers");
+                        ui.separator();
+                        ui.checkbox(&mut self.sprite_viewer.overlay, "Sprite boxes on screen");
+                        ui.checkbox(&mut self.scanline_viewer.overlay, "Scroll splits on screen");
+                    });
                 }
             });
             ui.menu_button("Help", |ui| {
@@ -790,127 +1781,1025 @@ impl Ui {
                         show_settings.store(false, Ordering::Relaxed);
                     }
                 });
-            },
-        );
-    }
-
-    #[cfg(target_arch = "wasm32")]
-    fn draw_settings_window(&mut self, ui: &mut egui::Ui) {
-        let show_settings = self.show_settings.clone();
-        let selected_tab = self.settings_selected_tab.clone();
-        let settings = self.settings.clone();
+            },
+        );
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn draw_settings_window(&mut self, ui: &mut egui::Ui) {
+        let show_settings = self.show_settings.clone();
+        let selected_tab = self.settings_selected_tab.clone();
+        let settings = self.settings.clone();
+
+        let modal = egui::Modal::new(egui::Id::new("settings_modal")).show(ui.ctx(), |ui| {
+            ui.set_min_width(800.0);
+            ui.set_min_height(600.0);
+            ui.label(egui::RichText::new("Settings").strong().size(18.0));
+            ui.separator();
+            draw_settings_panel_content(ui, &selected_tab, &settings);
+        });
+
+        if modal.should_close() {
+            show_settings.store(false, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_memory_viewer(&mut self, ui: &mut egui::Ui) {
+        let editor = &mut self.mem_editor;
+        // The size is only known once the emulator has published the new space
+        let size = if self.snapshot.mem_space == editor.space {
+            self.snapshot.mem_space_size
+        } else {
+            0
+        };
+
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("mem_space")
+                    .selected_text(editor.space.name())
+                    .show_ui(ui, |ui| {
+                        for space in MemorySpace::ALL {
+                            if ui
+                                .selectable_value(&mut editor.space, space, space.name())
+                                .changed()
+                            {
+                                editor.start_addr = 0;
+                                editor.selected = None;
+                            }
+                        }
+                    });
+                if ui.button("⏶").on_hover_text("Page up").clicked() {
+                    editor.scroll_to(editor.start_addr.saturating_sub(MEM_BLOCK_SIZE), size);
+                }
+                if ui.button("⏷").on_hover_text("Page down").clicked() {
+                    editor.scroll_to(editor.start_addr + MEM_BLOCK_SIZE, size);
+                }
+                ui.label(
+                    egui::RichText::new(format!("{} bytes", size))
+                        .text_style(egui::TextStyle::Monospace),
+                );
+                if ui.button("Dump").clicked() {
+                    self.runner.send_command(Command::DumpMemory);
+                }
+            });
+
+            let addr_width = if size > 0x10000 { 5 } else { 4 };
+            let mut write = None;
+            let table = ui.scope(|ui| {
+                TableBuilder::new(ui)
+                    .striped(true)
+                    .column(Column::auto())
+                    .column(Column::auto())
+                    .column(Column::auto())
+                    .column(Column::auto())
+                    .id_salt("mem_viewer")
+                    .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                    .body(|mut body| {
+                        for (i, lines) in self
+                            .snapshot
+                            .mem_chunk
+                            .chunks(BYTES_PER_ROW)
+                            .take(ROWS_TO_SHOW)
+                            .enumerate()
+                        {
+                            let row_addr = self.snapshot.mem_chunk_addr + i * BYTES_PER_ROW;
+                            if row_addr >= size {
+                                break;
+                            }
+                            let len = (size - row_addr).min(BYTES_PER_ROW);
+                            let lines = &lines[..len];
+
+                            body.row(20.0, |mut row| {
+                                row.col(|ui| {
+                                    ui.label(
+                                        egui::RichText::new(format!(
+                                            "0x{:0width$X}",
+                                            row_addr,
+                                            width = addr_width
+                                        ))
+                                        .strong()
+                                        .text_style(egui::TextStyle::Monospace),
+                                    );
+                                });
+                                row.col(|ui| {
+                                    ui.spacing_mut().item_spacing.x = 4.0;
+                                    for (j, byte) in lines.iter().enumerate() {
+                                        let addr = row_addr + j;
+                                        if editor.selected == Some(addr) {
+                                            let response = ui.add(
+                                                egui::TextEdit::singleline(&mut editor.edit)
+                                                    .hint_text(format!("{:02X}", byte))
+                                                    .font(egui::TextStyle::Monospace)
+                                                    .char_limit(2)
+                                                    .desired_width(16.0),
+                                            );
+                                            if response.lost_focus() {
+                                                // Enter commits, Escape or clicking away cancels
+                                                if ui.input(|i| i.key_pressed(egui::Key::Enter))
+                                                    && let Ok(value) =
+                                                        u8::from_str_radix(editor.edit.trim(), 16)
+                                                {
+                                                    write = Some((addr, value));
+                                                }
+                                                editor.selected = None;
+                                            } else {
+                                                response.request_focus();
+                                            }
+                                            continue;
+                                        }
+
+                                        let flags = self.snapshot.cdl_chunk[i * BYTES_PER_ROW + j];
+                                        let mut text = egui::RichText::new(format!("{:02X}", byte))
+                                            .text_style(egui::TextStyle::Monospace);
+                                        if self.cdl_overlay
+                                            && let Some(color) = cdl_color(flags)
+                                        {
+                                            text = text.color(color);
+                                        }
+                                        let writable = editor.space.writable(addr);
+                                        if !writable {
+                                            text = text.weak();
+                                        }
+                                        let response = ui
+                                            .add(egui::Label::new(text).sense(egui::Sense::click()))
+                                            .on_hover_text(format!(
+                                                "0x{:0width$X}{}",
+                                                addr,
+                                                if writable { "" } else { " (read only)" },
+                                                width = addr_width
+                                            ));
+                                        if response.clicked() && writable {
+                                            editor.selected = Some(addr);
+                                            editor.edit.clear();
+                                        }
+                                    }
+                                });
+                                row.col(|ui| {
+                                    let bytes_ascii: String = lines
+                                        .iter()
+                                        .map(|b| {
+                                            if !b.is_ascii_graphic() {
+                                                '.'
+                                            } else {
+                                                *b as char
+                                            }
+                                        })
+                                        .collect();
+                                    ui.label(
+                                        egui::RichText::new(bytes_ascii)
+                                            .text_style(egui::TextStyle::Monospace),
+                                    );
+                                });
+                                row.col(|ui| {
+                                    let labels: Vec<String> = (row_addr..row_addr + len)
+                                        .filter_map(|addr| {
+                                            let symbol = self.snapshot.mem_symbol(addr)?;
+                                            Some(format!(
+                                                "{:02X}:{}",
+                                                addr % BYTES_PER_ROW,
+                                                symbol.name
+                                            ))
+                                        })
+                                        .collect();
+                                    ui.label(
+                                        egui::RichText::new(labels.join(" "))
+                                            .text_style(egui::TextStyle::Monospace)
+                                            .color(Color32::LIGHT_BLUE),
+                                    );
+                                });
+                            });
+                        }
+                    });
+            });
+
+            if table.response.contains_pointer() {
+                const ROW_HEIGHT: f32 = 20.0;
+                editor.scroll += ui.input(|i| i.smooth_scroll_delta.y);
+                let rows = (editor.scroll / ROW_HEIGHT) as isize;
+                if rows != 0 {
+                    editor.scroll -= rows as f32 * ROW_HEIGHT;
+                    let addr = editor
+                        .start_addr
+                        .saturating_add_signed(-rows * BYTES_PER_ROW as isize);
+                    editor.scroll_to(addr, size);
+                }
+            }
+
+            if let Some((addr, value)) = write {
+                self.runner
+                    .send_command(Command::WriteMemory(editor.space, addr, value));
+                // Carry on typing into the next byte
+                if addr + 1 < size && editor.space.writable(addr + 1) {
+                    editor.selected = Some(addr + 1);
+                    editor.edit.clear();
+                    editor.reveal(addr + 1, size);
+                }
+            }
+
+            ui.shrink_width_to_current();
+            let goto = egui::TextEdit::singleline(&mut editor.goto)
+                .hint_text("27, 0xD0D0, label...")
+                .char_limit(32)
+                .desired_width(f32::INFINITY)
+                .show(ui);
+            if goto.response.changed()
+                && let Some(addr) = parse_address(&editor.goto, &self.snapshot.symbols)
+            {
+                editor.reveal(addr.min(size.saturating_sub(1)), size);
+            }
+        });
+
+        let window = (editor.space, editor.start_addr);
+        if editor.requested != Some(window) {
+            self.runner
+                .send_command(Command::MemoryAddress(window.0, window.1));
+            editor.requested = Some(window);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn add_watch(&mut self, watch: Watch) {
+        self.watch_list.watches.push(watch);
+        self.send_watches();
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn send_watches(&mut self) {
+        let addrs = self.watch_list.watches.iter().map(|w| w.addr).collect();
+        self.runner.send_command(Command::SetWatches(addrs));
+    }
+
+    /// Records one history sample per emulated frame
+    #[cfg(not(target_arch = "wasm32"))]
+    fn update_watches(&mut self) {
+        let frame = self.snapshot.ppu.frame;
+        let list = &mut self.watch_list;
+        if frame == list.last_frame {
+            return;
+        }
+        list.last_frame = frame;
+        for watch in &mut list.watches {
+            let Some(bytes) = self.snapshot.watch_value(watch.addr) else {
+                continue;
+            };
+            if watch.value.is_some_and(|v| v != bytes) {
+                watch.changed_frame = Some(frame);
+            }
+            watch.value = Some(bytes);
+            if watch.history.len() == WATCH_HISTORY {
+                watch.history.pop_front();
+            }
+            watch.history.push_back(watch.kind.value(bytes));
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_watches(&mut self, ui: &mut egui::Ui) {
+        let list = &mut self.watch_list;
+        let frame = self.snapshot.ppu.frame;
+        let mut changed = false;
+
+        ui.vertical(|ui| {
+            ui.label(egui::RichText::new("Watch").strong());
+
+            let mut remove = None;
+            TableBuilder::new(ui)
+                .striped(true)
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .id_salt("watch_list")
+                .max_scroll_height(150.0)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .body(|mut body| {
+                    for (i, watch) in list.watches.iter_mut().enumerate() {
+                        body.row(26.0, |mut row| {
+                            row.col(|ui| {
+                                ui.monospace(&watch.expr)
+                                    .on_hover_text(format!("${:04X}", watch.addr));
+                            });
+                            row.col(|ui| {
+                                egui::ComboBox::from_id_salt(("watch_type", i))
+                                    .selected_text(watch.kind.name())
+                                    .width(50.0)
+                                    .show_ui(ui, |ui| {
+                                        for kind in WatchType::ALL {
+                                            if ui
+                                                .selectable_value(
+                                                    &mut watch.kind,
+                                                    kind,
+                                                    kind.name(),
+                                                )
+                                                .changed()
+                                            {
+                                                // Old samples are in the previous type's scale
+                                                watch.history.clear();
+                                            }
+                                        }
+                                    });
+                            });
+                            row.col(|ui| {
+                                let Some(bytes) = watch.value else {
+                                    return;
+                                };
+                                let text =
+                                    egui::RichText::new(watch.kind.format(bytes)).monospace();
+                                let recent = watch.changed_frame.is_some_and(|f| {
+                                    frame.saturating_sub(f) < WATCH_HIGHLIGHT_FRAMES
+                                });
+                                ui.label(if recent {
+                                    text.color(Color32::YELLOW)
+                                } else {
+                                    text
+                                });
+                            });
+                            row.col(|ui| {
+                                let points: PlotPoints = watch
+                                    .history
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(x, y)| [x as f64, *y])
+                                    .collect();
+                                Plot::new(("watch_plot", i))
+                                    .width(120.0)
+                                    .height(24.0)
+                                    .show_axes(false)
+                                    .show_grid(false)
+                                    .show_x(false)
+                                    .show_y(false)
+                                    .allow_zoom(false)
+                                    .allow_drag(false)
+                                    .allow_scroll(false)
+                                    .allow_axis_zoom_drag(false)
+                                    .show(ui, |plot_ui| {
+                                        plot_ui.line(Line::new("history", points).width(1.0));
+                                    });
+                            });
+                            row.col(|ui| {
+                                if ui.small_button("🗑").clicked() {
+                                    remove = Some(i);
+                                }
+                            });
+                        });
+                    }
+                });
+            if let Some(i) = remove {
+                list.watches.remove(i);
+                changed = true;
+            }
+
+            ui.horizontal(|ui| {
+                egui::TextEdit::singleline(&mut list.expr)
+                    .hint_text("$0300, label+2")
+                    .desired_width(110.0)
+                    .show(ui);
+                egui::ComboBox::from_id_salt("watch_new_type")
+                    .selected_text(list.kind.name())
+                    .width(50.0)
+                    .show_ui(ui, |ui| {
+                        for kind in WatchType::ALL {
+                            ui.selectable_value(&mut list.kind, kind, kind.name());
+                        }
+                    });
+                if ui.button("Add").clicked() {
+                    match parse_watch_address(&list.expr, &self.snapshot.symbols) {
+                        Some(addr) => {
+                            let expr = std::mem::take(&mut list.expr).trim().to_string();
+                            list.watches.push(Watch::new(expr, addr, list.kind));
+                            list.error = None;
+                            changed = true;
+                        }
+                        None => list.error = Some(format!("Invalid address \"{}\"", list.expr)),
+                    }
+                }
+            });
+            if let Some(error) = &list.error {
+                ui.colored_label(Color32::RED, error);
+            }
+        });
+
+        if changed {
+            self.send_watches();
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_cdl(&mut self, ui: &mut egui::Ui) {
+        let stats = self.snapshot.cdl_stats;
+        let percent = |n: usize, total: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                n as f32 * 100.0 / total as f32
+            }
+        };
+
+        ui.vertical(|ui| {
+            ui.label(egui::RichText::new("Code/Data Logger").strong());
+            ui.horizontal(|ui| {
+                let mut enabled = self.snapshot.cdl_enabled;
+                if ui.checkbox(&mut enabled, "Record").changed() {
+                    self.runner.send_command(Command::SetCdlLogging(enabled));
+                }
+                if ui.button("Reset").clicked() {
+                    self.runner.send_command(Command::ResetCdl);
+                }
+                if ui.button("Save...").clicked() {
+                    self.runner.pick_cdl_file(true);
+                }
+                if ui.button("Load...").clicked() {
+                    self.runner.pick_cdl_file(false);
+                }
+            });
+            ui.checkbox(&mut self.cdl_overlay, "Memory viewer overlay");
+
+            TableBuilder::new(ui)
+                .id_salt("cdl_stats")
+                .striped(true)
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .body(|mut body| {
+                    make_rows!(body,
+                        "Code" =>
+                            format!("{}", stats.code),
+                            format!("{:.1}%", percent(stats.code, stats.prg_size)),
+                        "Data" =>
+                            format!("{}", stats.data),
+                            format!("{:.1}%", percent(stats.data, stats.prg_size)),
+                        "PCM" =>
+                            format!("{}", stats.pcm),
+                            format!("{:.1}%", percent(stats.pcm, stats.prg_size)),
+                        "Unused PRG" =>
+                            format!("{}", stats.prg_size - stats.prg_logged),
+                            format!("{:.1}%", percent(stats.prg_size - stats.prg_logged, stats.prg_size)),
+                        "CHR drawn" =>
+                            format!("{}", stats.chr_drawn),
+                            format!("{:.1}%", percent(stats.chr_drawn, stats.chr_size)),
+                        "CHR read" =>
+                            format!("{}", stats.chr_read),
+                            format!("{:.1}%", percent(stats.chr_read, stats.chr_size)),
+                    );
+                });
+
+            if self.cdl_overlay {
+                ui.horizontal(|ui| {
+                    for (label, flags) in [
+                        ("Code", PrgFlags::CODE),
+                        ("Data", PrgFlags::DATA),
+                        ("Both", PrgFlags::CODE | PrgFlags::DATA),
+                        ("PCM", PrgFlags::PCM),
+                    ] {
+                        ui.colored_label(cdl_color(flags).unwrap_or_default(), label);
+                    }
+                });
+            }
+        });
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_ram_search(&mut self, ui: &mut egui::Ui) {
+        let view = &mut self.ram_search;
+        let memory = self.snapshot.search_memory();
+        let mut goto = None;
+        let mut watch_write = None;
+        let mut freeze = None;
+        let mut watch = None;
+
+        ui.vertical(|ui| {
+            ui.label(egui::RichText::new("RAM Search").strong());
+            ui.horizontal(|ui| {
+                let search = &mut view.search;
+                let mut changed = false;
+                changed |= ui
+                    .selectable_value(&mut search.size, ValueSize::Byte, "8-bit")
+                    .changed();
+                changed |= ui
+                    .selectable_value(&mut search.size, ValueSize::Word, "16-bit")
+                    .changed();
+                changed |= ui.checkbox(&mut search.signed, "Signed").changed();
+                // Candidates only make sense for the view they were found with
+                if changed || ui.button("Reset").clicked() {
+                    search.reset(memory);
+                }
+            });
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("ram_search_cmp")
+                    .selected_text(view.comparison.name())
+                    .width(80.0)
+                    .show_ui(ui, |ui| {
+                        for comparison in Comparison::ALL {
+                            ui.selectable_value(
+                                &mut view.comparison,
+                                comparison,
+                                comparison.name(),
+                            );
+                        }
+                    });
+                egui::TextEdit::singleline(&mut view.value)
+                    .hint_text("previous")
+                    .desired_width(70.0)
+                    .show(ui);
+                if ui.button("Filter").clicked() {
+                    let value = view.value.trim();
+                    let operand = if value.is_empty() {
+                        Some(Operand::Previous)
+                    } else if let Some(n) = value.strip_prefix('-') {
+                        parse_number(n).map(|n| Operand::Value(-(n as i64)))
+                    } else {
+                        parse_number(value).map(|n| Operand::Value(n as i64))
+                    };
+                    match operand {
+                        Some(operand) => {
+                            view.search.filter(memory, view.comparison, operand);
+                            view.error = None;
+                        }
+                        None => view.error = Some(format!("Invalid value \"{}\"", value)),
+                    }
+                }
+            });
+            if let Some(error) = &view.error {
+                ui.colored_label(Color32::RED, error);
+            }
+
+            let search = &view.search;
+            if !search.is_started() {
+                ui.label("Reset or filter to snapshot RAM");
+                return;
+            }
+            ui.label(format!(
+                "{} candidates after {} passes",
+                search.candidates().len(),
+                search.passes
+            ));
+
+            TableBuilder::new(ui)
+                .striped(true)
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .id_salt("ram_search_results")
+                .max_scroll_height(120.0)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .header(16.0, |mut header| {
+                    for title in ["Address", "Value", "Previous", "Label", ""] {
+                        header.col(|ui| {
+                            ui.strong(title);
+                        });
+                    }
+                })
+                .body(|body| {
+                    let candidates = search.candidates();
+                    body.rows(18.0, candidates.len(), |mut row| {
+                        let addr = candidates[row.index()];
+                        let current = search.value(memory, addr);
+                        let previous = search.previous_value(addr);
+                        let text =
+                            |value: Option<i64>| value.map(|v| v.to_string()).unwrap_or_default();
+                        row.col(|ui| {
+                            ui.monospace(format!("${:04X}", addr));
+                        });
+                        row.col(|ui| {
+                            let label = egui::RichText::new(text(current)).monospace();
+                            if current != previous {
+                                ui.label(label.color(Color32::YELLOW));
+                            } else {
+                                ui.label(label);
+                            }
+                        });
+                        row.col(|ui| {
+                            ui.monospace(text(previous));
+                        });
+                        row.col(|ui| {
+                            if let Some(symbol) = self.snapshot.symbol(addr) {
+                                ui.label(
+                                    egui::RichText::new(&symbol.name)
+                                        .monospace()
+                                        .color(Color32::LIGHT_BLUE),
+                                );
+                            }
+                        });
+                        row.col(|ui| {
+                            if ui
+                                .small_button("🔍")
+                                .on_hover_text("Show in memory editor")
+                                .clicked()
+                            {
+                                goto = Some(addr);
+                            }
+                            if ui
+                                .small_button("W")
+                                .on_hover_text("Break on write")
+                                .clicked()
+                            {
+                                watch_write = Some(addr);
+                            }
+                            if ui.small_button("👁").on_hover_text("Watch").clicked() {
+                                watch = Some(addr);
+                            }
+                            if ui
+                                .small_button("❄")
+                                .on_hover_text("Freeze at the current value")
+                                .clicked()
+                            {
+                                freeze = Some(addr);
+                            }
+                        });
+                    });
+                });
+        });
+
+        if let Some(addr) = goto {
+            let editor = &mut self.mem_editor;
+            editor.space = MemorySpace::CpuBus;
+            editor.selected = None;
+            editor.goto = format!("${:04X}", addr);
+            editor.reveal(addr as usize, 0x10000);
+        }
+        if let Some(addr) = watch_write {
+            let end = match self.ram_search.search.size {
+                ValueSize::Byte => addr,
+                ValueSize::Word => addr + 1,
+            };
+            self.breakpoint_editor.add_write(addr, end);
+            self.runner.send_command(Command::SetBreakpoints(
+                self.breakpoint_editor.breakpoints.clone(),
+            ));
+        }
+        if let Some(addr) = watch {
+            let search = &self.ram_search.search;
+            let kind = match (search.size, search.signed) {
+                (ValueSize::Byte, false) => WatchType::U8,
+                (ValueSize::Byte, true) => WatchType::I8,
+                (ValueSize::Word, false) => WatchType::U16,
+                (ValueSize::Word, true) => WatchType::I16,
+            };
+            let expr = match self.snapshot.symbol(addr) {
+                Some(symbol) => symbol.name.clone(),
+                None => format!("${:04X}", addr),
+            };
+            self.add_watch(Watch::new(expr, addr, kind));
+        }
+        if let Some(addr) = freeze {
+            let len = match self.ram_search.search.size {
+                ValueSize::Byte => 1,
+                ValueSize::Word => 2,
+            };
+            let memory = self.snapshot.search_memory();
+            let description = self
+                .snapshot
+                .symbol(addr)
+                .map(|s| s.name.clone())
+                .unwrap_or_default();
+            for i in 0..len {
+                let addr = addr.wrapping_add(i);
+                if let Some(value) = memory.get(addr) {
+                    self.cheat_manager
+                        .cheats
+                        .push(Cheat::freeze(addr, value, &description));
+                }
+            }
+            self.cheats_changed();
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_rom_cheats(&mut self) {
+        let hash = self.snapshot.cart.as_ref().map(|c| c.hash.clone());
+        if hash == self.cheat_manager.rom_hash {
+            return;
+        }
+        self.cheat_manager.cheats = match &hash {
+            Some(hash) => cheats::load_for_rom(hash).unwrap_or_else(|e| {
+                error!("Failed to load cheats: {e}");
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        self.cheat_manager.rom_hash = hash;
+        self.runner
+            .send_command(Command::SetCheats(self.cheat_manager.cheats.clone()));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn cheats_changed(&mut self) {
+        self.cheat_manager.save();
+        self.runner
+            .send_command(Command::SetCheats(self.cheat_manager.cheats.clone()));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_cheats(&mut self, ui: &mut egui::Ui) {
+        let manager = &mut self.cheat_manager;
+        let mut changed = false;
+
+        ui.vertical(|ui| {
+            ui.label(egui::RichText::new("Cheats").strong());
+
+            let mut remove = None;
+            TableBuilder::new(ui)
+                .striped(true)
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .id_salt("cheat_list")
+                .max_scroll_height(120.0)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .body(|mut body| {
+                    for (i, cheat) in manager.cheats.iter_mut().enumerate() {
+                        body.row(18.0, |mut row| {
+                            row.col(|ui| {
+                                changed |= ui.checkbox(&mut cheat.enabled, "").changed();
+                            });
+                            row.col(|ui| {
+                                ui.monospace(&cheat.code);
+                            });
+                            row.col(|ui| {
+                                let effect = match cheat.effect() {
+                                    Ok(CheatEffect::Patch {
+                                        addr,
+                                        value,
+                                        compare: Some(compare),
+                                    }) => {
+                                        format!("${:04X}: {:02X} → {:02X}", addr, compare, value)
+                                    }
+                                    Ok(CheatEffect::Patch { addr, value, .. }) => {
+                                        format!("${:04X} → {:02X}", addr, value)
+                                    }
+                                    Ok(CheatEffect::Freeze { addr, value }) => {
+                                        format!("${:04X} = {:02X} ❄", addr, value)
+                                    }
+                                    Err(_) => "invalid".to_string(),
+                                };
+                                ui.monospace(effect);
+                            });
+                            row.col(|ui| {
+                                ui.label(&cheat.description);
+                            });
+                            row.col(|ui| {
+                                if ui.small_button("🗑").clicked() {
+                                    remove = Some(i);
+                                }
+                            });
+                        });
+                    }
+                });
+            if let Some(i) = remove {
+                manager.cheats.remove(i);
+                changed = true;
+            }
 
-        let modal = egui::Modal::new(egui::Id::new("settings_modal")).show(ui.ctx(), |ui| {
-            ui.set_min_width(800.0);
-            ui.set_min_height(600.0);
-            ui.label(egui::RichText::new("Settings").strong().size(18.0));
-            ui.separator();
-            draw_settings_panel_content(ui, &selected_tab, &settings);
+            ui.horizontal(|ui| {
+                egui::TextEdit::singleline(&mut manager.code)
+                    .hint_text("SXIOPO or 0075:09")
+                    .desired_width(100.0)
+                    .show(ui);
+                egui::TextEdit::singleline(&mut manager.description)
+                    .hint_text("Description")
+                    .desired_width(120.0)
+                    .show(ui);
+                if ui.button("Add").clicked() {
+                    match Cheat::new(&manager.code, &manager.description) {
+                        Ok(cheat) => {
+                            manager.cheats.push(cheat);
+                            manager.code.clear();
+                            manager.description.clear();
+                            manager.error = None;
+                            changed = true;
+                        }
+                        Err(e) => manager.error = Some(e.to_string()),
+                    }
+                }
+            });
+            if let Some(error) = &manager.error {
+                ui.colored_label(Color32::RED, error);
+            }
         });
 
-        if modal.should_close() {
-            show_settings.store(false, Ordering::Relaxed);
+        if changed {
+            self.cheats_changed();
         }
     }
 
     #[cfg(not(target_arch = "wasm32"))]
-    fn draw_memory_viewer(&mut self, ui: &mut egui::Ui) {
-        ui.vertical(|ui| {
-            let n = (match self.mem_search.starts_with("0x") {
-                true => usize::from_str_radix(&self.mem_search[2..], 16).unwrap_or(0),
-                false => self.mem_search.parse::<usize>().unwrap_or(0),
-            })
-            .min(0xffff);
-
-            const ROWS_ABOVE_CURRENT: usize = ROWS_TO_SHOW / 2;
-            const MAX_ROW: usize = 0xFFFF / BYTES_PER_ROW;
+    fn draw_breakpoints(&mut self, ui: &mut egui::Ui) {
+        let editor = &mut self.breakpoint_editor;
+        let mut changed = false;
 
-            let current_row = (n & 0xFFF0) / BYTES_PER_ROW;
-            let mut start_row = current_row.saturating_sub(ROWS_ABOVE_CURRENT);
-            if start_row + ROWS_TO_SHOW - 1 > MAX_ROW {
-                start_row = (MAX_ROW + 1).saturating_sub(ROWS_TO_SHOW);
-            }
-            let start_addr = start_row * BYTES_PER_ROW;
-            if start_addr != self.prev_mem_search_addr {
-                self.runner.send_command(Command::MemoryAddress(start_addr));
-                self.prev_mem_search_addr = start_addr;
-            }
-
-            ui.horizontal(|ui| {
-                ui.label(
-                    egui::RichText::new(format!("0x{:04X} {}", n, n))
-                        .strong()
-                        .text_style(egui::TextStyle::Monospace),
+        ui.vertical(|ui| {
+            ui.label(egui::RichText::new("Breakpoints").strong());
+            if let Some(hit) = &editor.last_hit {
+                let access = if hit.kind.contains(BreakKind::EXECUTE) {
+                    "exec".to_string()
+                } else if hit.kind.contains(BreakKind::WRITE) {
+                    format!("write 0x{:02X}", hit.value)
+                } else {
+                    format!("read 0x{:02X}", hit.value)
+                };
+                ui.colored_label(
+                    Color32::YELLOW,
+                    format!(
+                        "#{} hit at 0x{:04X}: {} {} 0x{:04X}",
+                        hit.id,
+                        hit.pc,
+                        access,
+                        hit.space.name(),
+                        hit.addr
+                    ),
                 );
-                if ui.button("Dump").clicked() {
-                    self.runner.send_command(Command::DumpMemory);
-                }
-            });
+            }
 
+            let mut remove = None;
             TableBuilder::new(ui)
+                .id_salt("breakpoints")
                 .striped(true)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
                 .column(Column::auto())
                 .column(Column::auto())
                 .column(Column::auto())
-                .id_salt("mem_viewer")
-                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Column::auto())
+                .column(Column::auto().at_least(80.0))
+                .column(Column::auto())
+                .column(Column::auto())
+                .header(20.0, |mut header| {
+                    for label in ["", "Type", "Space", "Address", "Condition", "Hits", ""] {
+                        header.col(|ui| {
+                            ui.label(egui::RichText::new(label).strong());
+                        });
+                    }
+                })
                 .body(|mut body| {
-                    for (i, lines) in self
-                        .snapshot
-                        .mem_chunk
-                        .chunks(BYTES_PER_ROW)
-                        .take(ROWS_TO_SHOW)
-                        .enumerate()
-                    {
-                        let bytes_str: Vec<String> =
-                            lines.iter().map(|b| format!("{:02X}", b)).collect();
-                        let bytes_ascii: Vec<char> = lines
+                    for bp in editor.breakpoints.iter_mut() {
+                        let hits = self
+                            .snapshot
+                            .breakpoint_hits
                             .iter()
-                            .map(|b| {
-                                if !b.is_ascii_graphic() {
-                                    '.'
+                            .find(|(id, _)| *id == bp.id)
+                            .map(|(_, hits)| *hits)
+                            .unwrap_or(0);
+                        body.row(20.0, |mut row| {
+                            row.col(|ui| {
+                                changed |= ui.checkbox(&mut bp.enabled, "").changed();
+                            });
+                            row.col(|ui| {
+                                let kind: String = [
+                                    (BreakKind::READ, 'R'),
+                                    (BreakKind::WRITE, 'W'),
+                                    (BreakKind::EXECUTE, 'X'),
+                                ]
+                                .iter()
+                                .map(|(k, c)| if bp.kind.contains(*k) { *c } else { '-' })
+                                .collect();
+                                ui.monospace(kind);
+                            });
+                            row.col(|ui| {
+                                ui.label(bp.space.name());
+                            });
+                            row.col(|ui| {
+                                if bp.start == bp.end {
+                                    ui.monospace(format!("0x{:04X}", bp.start));
                                 } else {
-                                    *b as char
+                                    ui.monospace(format!("0x{:04X}-0x{:04X}", bp.start, bp.end));
                                 }
-                            })
-                            .collect();
-
-                        let row_addr = start_addr + (i * BYTES_PER_ROW);
-
-                        body.row(20.0, |mut row| {
+                                let symbol = match bp.space {
+                                    AddrSpace::Cpu => self.snapshot.symbol(bp.start),
+                                    AddrSpace::Ppu => None,
+                                };
+                                if let Some(symbol) = symbol {
+                                    ui.label(
+                                        egui::RichText::new(&symbol.name)
+                                            .monospace()
+                                            .color(Color32::LIGHT_BLUE),
+                                    );
+                                }
+                            });
                             row.col(|ui| {
-                                ui.label(
-                                    egui::RichText::new(format!("0x{:04X}", row_addr))
-                                        .strong()
-                                        .text_style(egui::TextStyle::Monospace),
+                                ui.monospace(
+                                    bp.condition.as_ref().map(|c| c.source()).unwrap_or(""),
                                 );
                             });
                             row.col(|ui| {
-                                ui.label(
-                                    egui::RichText::new(bytes_str.join(" "))
-                                        .text_style(egui::TextStyle::Monospace),
-                                );
+                                if bp.hit_target > 0 {
+                                    ui.label(format!("{}/{}", hits, bp.hit_target));
+                                } else {
+                                    ui.label(format!("{}", hits));
+                                }
                             });
                             row.col(|ui| {
-                                ui.label(
-                                    egui::RichText::new(String::from_iter(bytes_ascii).to_string())
-                                        .text_style(egui::TextStyle::Monospace),
-                                );
+                                if ui.small_button("🗑").clicked() {
+                                    remove = Some(bp.id);
+                                }
                             });
                         });
                     }
                 });
-            ui.shrink_width_to_current();
-            egui::TextEdit::singleline(&mut self.mem_search)
-                .hint_text("27, 0xD0D0, ...")
-                .char_limit(8)
-                .desired_width(f32::INFINITY)
-                .show(ui);
+            if let Some(id) = remove {
+                editor.breakpoints.retain(|bp| bp.id != id);
+                changed = true;
+            }
+
+            ui.horizontal(|ui| {
+                egui::TextEdit::singleline(&mut editor.addr)
+                    .hint_text("0xC000 or 0x0300-0x03FF")
+                    .desired_width(150.0)
+                    .show(ui);
+                for (flag, label) in [
+                    (BreakKind::READ, "R"),
+                    (BreakKind::WRITE, "W"),
+                    (BreakKind::EXECUTE, "X"),
+                ] {
+                    let mut set = editor.kind.contains(flag);
+                    if ui.checkbox(&mut set, label).changed() {
+                        editor.kind.set(flag, set);
+                    }
+                }
+                egui::ComboBox::from_id_salt("bp_space")
+                    .selected_text(editor.space.name())
+                    .width(50.0)
+                    .show_ui(ui, |ui| {
+                        for space in [AddrSpace::Cpu, AddrSpace::Ppu] {
+                            ui.selectable_value(&mut editor.space, space, space.name());
+                        }
+                    });
+            });
+            ui.horizontal(|ui| {
+                egui::TextEdit::singleline(&mut editor.condition)
+                    .hint_text("A == 0xFF && X > 3")
+                    .desired_width(150.0)
+                    .show(ui);
+                egui::TextEdit::singleline(&mut editor.hit_target)
+                    .hint_text("Hits")
+                    .desired_width(40.0)
+                    .show(ui);
+                if ui.button("Add").clicked() {
+                    match editor.build(&self.snapshot.symbols) {
+                        Ok(bp) => {
+                            editor.breakpoints.push(bp);
+                            editor.error = None;
+                            changed = true;
+                        }
+                        Err(e) => editor.error = Some(e.to_string()),
+                    }
+                }
+            });
+            if let Some(error) = &editor.error {
+                ui.colored_label(Color32::RED, error);
+            }
+        });
+
+        if changed {
+            self.runner
+                .send_command(Command::SetBreakpoints(editor.breakpoints.clone()));
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_step_controls(&mut self, ui: &mut egui::Ui) {
+        ui.add_enabled_ui(self.running && self.paused, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for (label, hover, kind) in [
+                    ("➡", "Step instruction", StepKind::Instruction),
+                    ("↷", "Step over", StepKind::Over),
+                    ("↑", "Step out", StepKind::Out),
+                    ("↓", "Step scanline", StepKind::Scanline),
+                    ("⤵", "Step frame", StepKind::Frame),
+                    ("NMI", "Run to next NMI", StepKind::Nmi),
+                    ("IRQ", "Run to next IRQ", StepKind::Irq),
+                ] {
+                    if ui.small_button(label).on_hover_text(hover).clicked() {
+                        self.runner.step(kind);
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                egui::TextEdit::singleline(&mut self.run_to_addr)
+                    .hint_text("0xC000")
+                    .char_limit(32)
+                    .desired_width(60.0)
+                    .show(ui);
+                let addr = parse_address(&self.run_to_addr, &self.snapshot.symbols)
+                    .filter(|a| *a <= 0xFFFF);
+                if ui
+                    .add_enabled(addr.is_some(), egui::Button::new("Run to"))
+                    .clicked()
+                    && let Some(addr) = addr
+                {
+                    self.runner.step(StepKind::RunTo(addr as u16));
+                }
+            });
         });
     }
 
     #[cfg(not(target_arch = "wasm32"))]
     fn draw_cpu_inspector(&mut self, ui: &mut egui::Ui) {
+        self.draw_step_controls(ui);
         let cpu = &self.snapshot.cpu;
         ui.label(egui::RichText::new("CPU").strong());
         TableBuilder::new(ui)
@@ -1168,37 +3057,13 @@ impl Ui {
                         });
                 });
                 egui::CollapsingHeader::new("OAM Data ($2004)").show(ui, |ui| {
-                    egui::ScrollArea::vertical()
-                        .max_height(200.0)
-                        .show(ui, |ui| {
-                            TableBuilder::new(ui)
-                                .id_salt("oamdata")
-                                .striped(true)
-                                .column(Column::auto())
-                                .column(Column::remainder())
-                                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
-                                .body(|mut body| {
-                                    for (i, chunk) in ppu.oam.chunks(4).enumerate() {
-                                        if i >= 64 {
-                                            break;
-                                        }
-                                        body.row(16.0, |mut row| {
-                                            row.col(|ui| {
-                                                ui.label(
-                                                    egui::RichText::new(format!("Sprite {}", i))
-                                                        .strong(),
-                                                );
-                                            });
-                                            row.col(|ui| {
-                                                ui.label(format!(
-                                                    "Y:{} Tile:{:02X} Attr:{:02X} X:{}",
-                                                    chunk[0], chunk[1], chunk[2], chunk[3]
-                                                ));
-                                            });
-                                        });
-                                    }
-                                });
-                        });
+                    Self::draw_oam_table(
+                        ui,
+                        &self.snapshot,
+                        self.snapshot_serial,
+                        &self.palette,
+                        &mut self.sprite_viewer,
+                    );
                 });
                 egui::CollapsingHeader::new("PPU Registers ($2005-$2007)").show(ui, |ui| {
                     TableBuilder::new(ui)
@@ -1260,9 +3125,8 @@ impl Ui {
                                                     _ => color_idx,
                                                 };
 
-                                                let color = Ppu::get_color_from_palette(
-                                                    displayed_idx & 0x3F,
-                                                );
+                                                let color =
+                                                    self.palette.color(displayed_idx & 0x3F);
 
                                                 let mut text = egui::RichText::new(format!(
                                                     "{:02X}",
@@ -1299,28 +3163,642 @@ impl Ui {
                                                     }
                                                     _ => color_idx,
                                                 };
-                                                let color = Ppu::get_color_from_palette(
-                                                    displayed_idx & 0x3F,
-                                                );
+                                                let color =
+                                                    self.palette.color(displayed_idx & 0x3F);
+
+                                                let mut text = egui::RichText::new(format!(
+                                                    "{:02X}",
+                                                    color_idx
+                                                ))
+                                                .text_style(egui::TextStyle::Monospace);
+                                                if [0x10, 0x14, 0x18, 0x1C].contains(&palette_addr)
+                                                {
+                                                    text = text.strikethrough();
+                                                }
+
+                                                row.col(|ui| {
+                                                    ui.colored_label(color, text);
+                                                });
+                                            }
+                                        });
+                                    }
+                                });
+                        });
+                });
+            });
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_nametable_viewer(&mut self, ctx: &Context) {
+        let viewer = &mut self.nametable_viewer;
+        let snapshot = &self.snapshot;
+        let mut open = viewer.open;
+        egui::Window::new("Nametables")
+            .open(&mut open)
+            .resizable(true)
+            .default_size([NAMETABLES_WIDTH as f32, NAMETABLES_HEIGHT as f32 + 40.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut viewer.show_scroll, "Scroll window");
+                    ui.checkbox(&mut viewer.show_tile_grid, "Tile grid");
+                    ui.checkbox(&mut viewer.show_attribute_grid, "Attribute grid");
+                });
+
+                let bg_pattern_table = snapshot.ppu.ctrl.bg_pattern_table() as usize * 0x1000;
+                let texture = viewer.image.update(
+                    ctx,
+                    "nametables",
+                    self.snapshot_serial,
+                    [NAMETABLES_WIDTH, NAMETABLES_HEIGHT],
+                    || {
+                        ppu_view::render_nametables(
+                            &snapshot.nametables,
+                            &snapshot.chr,
+                            &snapshot.ppu.palette,
+                            &self.palette,
+                            bg_pattern_table,
+                        )
+                    },
+                );
+
+                let available = ui.available_size();
+                let scale = (available.x / NAMETABLES_WIDTH as f32)
+                    .min(available.y / NAMETABLES_HEIGHT as f32)
+                    .max(0.5);
+                let size = egui::vec2(
+                    NAMETABLES_WIDTH as f32 * scale,
+                    NAMETABLES_HEIGHT as f32 * scale,
+                );
+                let response =
+                    ui.add(egui::Image::new((texture.id(), size)).sense(egui::Sense::hover()));
+                let rect = response.rect;
+                let painter = ui.painter_at(rect);
+                let to_screen = |x: f32, y: f32| rect.min + egui::vec2(x, y) * scale;
+
+                for (show, step, color) in [
+                    (viewer.show_tile_grid, 8, Color32::from_white_alpha(40)),
+                    (
+                        viewer.show_attribute_grid,
+                        16,
+                        Color32::from_rgba_unmultiplied(0, 160, 255, 120),
+                    ),
+                ] {
+                    if !show {
+                        continue;
+                    }
+                    let stroke = egui::Stroke::new(1.0, color);
+                    for x in (0..NAMETABLES_WIDTH).step_by(step) {
+                        painter.vline(to_screen(x as f32, 0.0).x, rect.y_range(), stroke);
+                    }
+                    // Rows restart at each nametable, which is 240 lines tall
+                    for y in (0..NAMETABLES_HEIGHT).filter(|y| (y % 240) % step == 0) {
+                        painter.hline(rect.x_range(), to_screen(0.0, y as f32).y, stroke);
+                    }
+                }
+
+                if viewer.show_scroll {
+                    let (sx, sy) = ppu_view::scroll_origin(snapshot.ppu.t, snapshot.ppu.x);
+                    let stroke = egui::Stroke::new(2.0, Color32::from_rgb(255, 64, 64));
+                    // The window wraps around the edges of the four nametables
+                    for dx in [0.0, -(NAMETABLES_WIDTH as f32)] {
+                        for dy in [0.0, -(NAMETABLES_HEIGHT as f32)] {
+                            let min = to_screen(sx as f32 + dx, sy as f32 + dy);
+                            let window =
+                                egui::Rect::from_min_size(min, egui::vec2(256.0, 240.0) * scale);
+                            painter.rect_stroke(window, 0.0, stroke, egui::StrokeKind::Inside);
+                        }
+                    }
+                }
+
+                if let Some(pos) = response.hover_pos() {
+                    let pixel = (pos - rect.min) / scale;
+                    if let Some(tile) = ppu_view::nametable_tile(
+                        &snapshot.nametables,
+                        pixel.x as usize,
+                        pixel.y as usize,
+                    ) {
+                        response.on_hover_ui_at_pointer(|ui| {
+                            egui::Grid::new("nametable_tile").show(ui, |ui| {
+                                ui.label("Nametable");
+                                ui.monospace(format!(
+                                    "{} (${:04X})",
+                                    tile.nametable,
+                                    0x2000 + tile.nametable * 0x400
+                                ));
+                                ui.end_row();
+                                ui.label("Tile");
+                                ui.monospace(format!("{}, {}", tile.x, tile.y));
+                                ui.end_row();
+                                ui.label("PPU address");
+                                ui.monospace(format!("${:04X}", tile.addr));
+                                ui.end_row();
+                                ui.label("Tile index");
+                                ui.monospace(format!("${:02X}", tile.tile));
+                                ui.end_row();
+                                ui.label("Attribute");
+                                ui.monospace(format!("${:04X}", tile.attr_addr));
+                                ui.end_row();
+                                ui.label("Palette");
+                                ui.monospace(format!(
+                                    "{} (${:04X})",
+                                    tile.palette,
+                                    0x3F00 + tile.palette as usize * 4
+                                ));
+                                ui.end_row();
+                            });
+                        });
+                    }
+                }
+            });
+        viewer.open = open;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_chr_viewer(&mut self, ctx: &Context) {
+        const SCALE: f32 = 2.0;
+
+        let viewer = &mut self.chr_viewer;
+        let snapshot = &self.snapshot;
+        let mut open = viewer.open;
+        let mut export = false;
+        egui::Window::new("Pattern tables")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Palette");
+                    for palette in 0..8 {
+                        let colors =
+                            ppu_view::palette_colors(&self.palette, &snapshot.ppu.palette, palette);
+                        let (rect, response) =
+                            ui.allocate_exact_size(egui::vec2(40.0, 14.0), egui::Sense::click());
+                        for (i, color) in colors.iter().enumerate() {
+                            let swatch = egui::Rect::from_min_size(
+                                rect.min + egui::vec2(i as f32 * 10.0, 0.0),
+                                egui::vec2(10.0, 14.0),
+                            );
+                            ui.painter().rect_filled(swatch, 0.0, *color);
+                        }
+                        if viewer.palette == palette {
+                            ui.painter().rect_stroke(
+                                rect,
+                                0.0,
+                                egui::Stroke::new(2.0, Color32::WHITE),
+                                egui::StrokeKind::Outside,
+                            );
+                        }
+                        let kind = if palette < 4 { "Background" } else { "Sprite" };
+                        if response
+                            .on_hover_text(format!("{} palette {}", kind, palette % 4))
+                            .clicked()
+                        {
+                            viewer.palette = palette;
+                        }
+                    }
+                    export = ui.button("💾 Export PNG").clicked();
+                });
+
+                let colors =
+                    ppu_view::palette_colors(&self.palette, &snapshot.ppu.palette, viewer.palette);
+                ui.horizontal(|ui| {
+                    for (table, image) in viewer.images.iter_mut().enumerate() {
+                        let base = table * 0x1000;
+                        let texture = image.update(
+                            ctx,
+                            &format!("pattern_table_{}", table),
+                            self.snapshot_serial,
+                            [PATTERN_TABLE_SIZE, PATTERN_TABLE_SIZE],
+                            || ppu_view::render_pattern_table(&snapshot.chr, base, colors),
+                        );
+                        ui.vertical(|ui| {
+                            ui.label(format!("${:04X}", base));
+                            let size = egui::Vec2::splat(PATTERN_TABLE_SIZE as f32 * SCALE);
+                            let response = ui.add(
+                                egui::Image::new((texture.id(), size)).sense(egui::Sense::hover()),
+                            );
+                            if let Some(pos) = response.hover_pos() {
+                                let pixel = (pos - response.rect.min) / SCALE;
+                                let tile = (pixel.y as usize / 8).min(15) * 16
+                                    + (pixel.x as usize / 8).min(15);
+                                let tile_rect = egui::Rect::from_min_size(
+                                    response.rect.min
+                                        + egui::vec2((tile % 16) as f32, (tile / 16) as f32)
+                                            * 8.0
+                                            * SCALE,
+                                    egui::Vec2::splat(8.0 * SCALE),
+                                );
+                                ui.painter().rect_stroke(
+                                    tile_rect,
+                                    0.0,
+                                    egui::Stroke::new(1.0, Color32::WHITE),
+                                    egui::StrokeKind::Outside,
+                                );
+                                response.on_hover_ui_at_pointer(|ui| {
+                                    ui.monospace(format!(
+                                        "Tile ${:02X}\nPPU address ${:04X}",
+                                        tile,
+                                        base + tile * 16
+                                    ));
+                                });
+                            }
+                        });
+                    }
+                });
+            });
+        viewer.open = open;
+
+        if export && let Some(path) = self.runner.pick_png_file("pattern_tables.png") {
+            let colors =
+                ppu_view::palette_colors(&self.palette, &snapshot.ppu.palette, viewer.palette);
+            let tables =
+                [0, 0x1000].map(|base| ppu_view::render_pattern_table(&snapshot.chr, base, colors));
+            // Both tables side by side
+            let pixels: Vec<Color32> = (0..PATTERN_TABLE_SIZE)
+                .flat_map(|y| {
+                    tables.iter().flat_map(move |table| {
+                        table[y * PATTERN_TABLE_SIZE..(y + 1) * PATTERN_TABLE_SIZE]
+                            .iter()
+                            .copied()
+                    })
+                })
+                .collect();
+            save_png(&path, &pixels, PATTERN_TABLE_SIZE * 2, PATTERN_TABLE_SIZE);
+        }
+    }
+
+    /// Register writes and interrupts plotted by the scanline and dot they happened on
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_event_viewer(&mut self, ctx: &Context) {
+        const SCALE: f32 = 2.0;
+
+        let viewer = &mut self.event_viewer;
+        let snapshot = &self.snapshot;
+        let mut open = viewer.open;
+        let mut recording = snapshot.ppu_events_enabled;
+        egui::Window::new("Events")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    if ui.checkbox(&mut recording, "⏺ Record").changed() {
+                        self.runner
+                            .send_command(Command::SetPpuEventLogging(recording));
+                    }
+                    ui.separator();
+                    for kind in PpuEventKind::ALL {
+                        let mut shown = !viewer.hidden.contains(&kind);
+                        let (rect, _) =
+                            ui.allocate_exact_size(egui::Vec2::splat(8.0), egui::Sense::hover());
+                        ui.painter().rect_filled(rect, 0.0, event_kind_color(kind));
+                        if ui.checkbox(&mut shown, kind.name()).changed() {
+                            if shown {
+                                viewer.hidden.remove(&kind);
+                            } else {
+                                viewer.hidden.insert(kind);
+                            }
+                        }
+                    }
+                });
+
+                let events: Vec<&PpuEvent> = snapshot
+                    .ppu_events
+                    .iter()
+                    .filter(|e| !viewer.hidden.contains(&e.kind))
+                    .collect();
+                let now = snapshot.ppu.scanline as usize * DOTS + snapshot.ppu.dot as usize;
+
+                let size = egui::vec2(DOTS as f32, SCANLINES as f32) * SCALE;
+                let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+                let painter = ui.painter_at(rect);
+                let to_screen =
+                    |dot: f32, scanline: f32| rect.min + egui::vec2(dot, scanline) * SCALE;
+                painter.rect_filled(rect, 0.0, Color32::from_gray(24));
+                // Blanking: hblank from dot 257, vblank from scanline 241
+                painter.rect_filled(
+                    egui::Rect::from_min_max(to_screen(257.0, 0.0), rect.max),
+                    0.0,
+                    Color32::from_gray(40),
+                );
+                painter.rect_filled(
+                    egui::Rect::from_min_max(to_screen(0.0, 241.0), to_screen(DOTS as f32, 261.0)),
+                    0.0,
+                    Color32::from_rgb(40, 32, 56),
+                );
+                if let Some(texture) = &self.screen.texture_handle {
+                    let visible = egui::Rect::from_min_max(
+                        to_screen(1.0, 0.0),
+                        to_screen(257.0, FRAME_HEIGHT as f32),
+                    );
+                    let uv = egui::Rect::from_min_max(egui::Pos2::ZERO, egui::pos2(1.0, 1.0));
+                    painter.image(texture.id(), visible, uv, Color32::from_gray(96));
+                }
+
+                for event in &events {
+                    let color = event_color(event);
+                    // The previous frame's events after the current dot are dimmed
+                    let color = if event.cycle() >= now {
+                        color.gamma_multiply(0.4)
+                    } else {
+                        color
+                    };
+                    let center = to_screen(event.dot as f32 + 0.5, event.scanline as f32 + 0.5);
+                    painter.rect_filled(
+                        egui::Rect::from_center_size(center, egui::Vec2::splat(3.0)),
+                        0.0,
+                        color,
+                    );
+                }
+                if let Some(selected) = &viewer.selected {
+                    let center =
+                        to_screen(selected.dot as f32 + 0.5, selected.scanline as f32 + 0.5);
+                    painter.circle_stroke(center, 5.0, egui::Stroke::new(1.5, Color32::WHITE));
+                }
+                let current = to_screen(snapshot.ppu.dot as f32, snapshot.ppu.scanline as f32);
+                let stroke = egui::Stroke::new(1.0, Color32::WHITE.gamma_multiply(0.5));
+                painter.hline(rect.x_range(), current.y, stroke);
+                painter.vline(current.x, rect.y_range(), stroke);
+
+                if let Some(pos) = response.hover_pos() {
+                    let cell = (pos - rect.min) / SCALE;
+                    let nearest = events
+                        .iter()
+                        .map(|e| {
+                            let offset =
+                                egui::vec2(e.dot as f32 + 0.5, e.scanline as f32 + 0.5) - cell;
+                            (e, offset.length())
+                        })
+                        .filter(|(_, distance)| *distance <= 3.0)
+                        .min_by(|a, b| a.1.total_cmp(&b.1))
+                        .map(|(e, _)| **e);
+                    if response.clicked() {
+                        viewer.selected = nearest;
+                        viewer.reveal = true;
+                    }
+                    response.on_hover_ui_at_pointer(|ui| {
+                        ui.monospace(format!(
+                            "Scanline {}, dot {}",
+                            cell.y.clamp(0.0, SCANLINES as f32 - 1.0) as usize,
+                            cell.x.clamp(0.0, DOTS as f32 - 1.0) as usize,
+                        ));
+                        if let Some(event) = nearest {
+                            ui.separator();
+                            ui.monospace(describe_event(&event));
+                            ui.monospace(format!("PC ${:04X}", event.pc));
+                        }
+                    });
+                }
+
+                ui.separator();
+                let selected_row = viewer.selected.and_then(|selected| {
+                    events.iter().position(|e| {
+                        e.cycle() == selected.cycle()
+                            && e.kind == selected.kind
+                            && e.addr == selected.addr
+                    })
+                });
+                let mut table = TableBuilder::new(ui)
+                    .id_salt("ppu_events")
+                    .striped(true)
+                    .sense(egui::Sense::click())
+                    .max_scroll_height(200.0)
+                    .columns(Column::auto(), 3)
+                    .column(Column::remainder())
+                    .cell_layout(egui::Layout::left_to_right(egui::Align::Center));
+                if viewer.reveal
+                    && let Some(row) = selected_row
+                {
+                    table = table.scroll_to_row(row, Some(egui::Align::Center));
+                }
+                viewer.reveal = false;
+                table
+                    .header(16.0, |mut header| {
+                        for title in ["Scanline", "Dot", "PC", "Event"] {
+                            header.col(|ui| {
+                                ui.strong(title);
+                            });
+                        }
+                    })
+                    .body(|body| {
+                        body.rows(16.0, events.len(), |mut row| {
+                            let event = events[row.index()];
+                            row.set_selected(selected_row == Some(row.index()));
+                            row.col(|ui| {
+                                ui.monospace(format!("{}", event.scanline));
+                            });
+                            row.col(|ui| {
+                                ui.monospace(format!("{}", event.dot));
+                            });
+                            row.col(|ui| {
+                                let pc = egui::RichText::new(format!("${:04X}", event.pc));
+                                match snapshot.symbol(event.pc) {
+                                    Some(symbol) => ui.monospace(pc).on_hover_text(&symbol.name),
+                                    None => ui.monospace(pc),
+                                };
+                            });
+                            row.col(|ui| {
+                                let (rect, _) = ui.allocate_exact_size(
+                                    egui::Vec2::splat(8.0),
+                                    egui::Sense::hover(),
+                                );
+                                ui.painter().rect_filled(rect, 0.0, event_color(event));
+                                ui.monospace(describe_event(event));
+                            });
+                            if row.response().clicked() {
+                                viewer.selected = Some(*event);
+                            }
+                        });
+                    });
+            });
+        viewer.open = open;
+    }
 
-                                                let mut text = egui::RichText::new(format!(
-                                                    "{:02X}",
-                                                    color_idx
-                                                ))
-                                                .text_style(egui::TextStyle::Monospace);
-                                                if [0x10, 0x14, 0x18, 0x1C].contains(&palette_addr)
-                                                {
-                                                    text = text.strikethrough();
-                                                }
+    /// Scroll and rendering registers of every visible scanline
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_scanline_viewer(&mut self, ctx: &Context) {
+        let viewer = &mut self.scanline_viewer;
+        let registers = &self.snapshot.ppu.scanline_registers;
+        let mut open = viewer.open;
+        egui::Window::new("Scanline registers")
+            .open(&mut open)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut viewer.changes_only, "Only lines that change");
+                    ui.checkbox(&mut viewer.overlay, "Show splits on screen");
+                });
 
-                                                row.col(|ui| {
-                                                    ui.colored_label(color, text);
-                                                });
-                                            }
-                                        });
-                                    }
+                let changed = |line: usize| {
+                    let Some(previous) = line.checked_sub(1) else {
+                        return true;
+                    };
+                    let (a, b) = (&registers[previous], &registers[line]);
+                    ppu_view::scanline_scroll(a.v, a.x, previous)
+                        != ppu_view::scanline_scroll(b.v, b.x, line)
+                        || a.t != b.t
+                        || a.ctrl.into_bytes() != b.ctrl.into_bytes()
+                        || a.mask.into_bytes() != b.mask.into_bytes()
+                };
+                let lines: Vec<usize> = (0..registers.len())
+                    .filter(|&line| !viewer.changes_only || changed(line))
+                    .collect();
+
+                TableBuilder::new(ui)
+                    .id_salt("scanline_registers")
+                    .striped(true)
+                    .columns(Column::auto(), 8)
+                    .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                    .header(16.0, |mut header| {
+                        for title in [
+                            "Line", "Scroll X", "Scroll Y", "v", "t", "x", "PPUCTRL", "PPUMASK",
+                        ] {
+                            header.col(|ui| {
+                                ui.strong(title);
+                            });
+                        }
+                    })
+                    .body(|body| {
+                        body.rows(16.0, lines.len(), |mut row| {
+                            let line = lines[row.index()];
+                            let registers = &registers[line];
+                            let (scroll_x, scroll_y) =
+                                ppu_view::scanline_scroll(registers.v, registers.x, line);
+                            let split = line > 0 && changed(line);
+                            let cells = [
+                                format!("{}", line),
+                                format!("{}", scroll_x),
+                                format!("{}", scroll_y),
+                                format!("${:04X}", registers.v),
+                                format!("${:04X}", registers.t),
+                                format!("{}", registers.x),
+                                format!("${:02X}", registers.ctrl.into_bytes()[0]),
+                                format!("${:02X}", registers.mask.into_bytes()[0]),
+                            ];
+                            for text in cells {
+                                row.col(|ui| {
+                                    let text = egui::RichText::new(text).monospace();
+                                    ui.label(if split {
+                                        text.color(Color32::YELLOW)
+                                    } else {
+                                        text
+                                    });
                                 });
+                            }
+                        });
+                    });
+            });
+        viewer.open = open;
+    }
+
+    /// Decoded OAM, one row per sprite with its graphics
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_oam_table(
+        ui: &mut egui::Ui,
+        snapshot: &DebugSnapshot,
+        serial: u64,
+        colors: &Palette,
+        viewer: &mut SpriteViewer,
+    ) {
+        const SCALE: f32 = 2.0;
+
+        let ppu = &snapshot.ppu;
+        let tall = ppu.ctrl.sprite_size() != 0;
+        let height = if tall { 16.0 } else { 8.0 };
+        let sprite_table = ppu.ctrl.sprite_pattern_table() as usize * 0x1000;
+        let over_limit = ppu_view::sprites_over_limit(&ppu.oam, tall);
+        let texture = viewer.image.update(
+            ui.ctx(),
+            "sprites",
+            serial,
+            [SPRITE_SHEET_WIDTH, SPRITE_SHEET_HEIGHT],
+            || {
+                ppu_view::render_sprites(
+                    &ppu.oam,
+                    &snapshot.chr,
+                    &ppu.palette,
+                    colors,
+                    sprite_table,
+                    tall,
+                    Color32::from_gray(40),
+                )
+            },
+        );
+
+        ui.horizontal(|ui| {
+            ui.label(if tall { "8x16 sprites" } else { "8x8 sprites" });
+            ui.checkbox(&mut viewer.overlay, "Show boxes on screen");
+        });
+        TableBuilder::new(ui)
+            .id_salt("oamdata")
+            .striped(true)
+            .max_scroll_height(300.0)
+            .columns(Column::auto(), 8)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .header(16.0, |mut header| {
+                for title in ["#", "", "X", "Y", "Tile", "Palette", "Priority", "Flip"] {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(height * SCALE + 2.0, SPRITE_COUNT, |mut row| {
+                    let index = row.index();
+                    let sprite = OamSprite::from_oam(&ppu.oam, index);
+                    row.col(|ui| {
+                        let label = egui::RichText::new(format!("{}", index)).monospace();
+                        if over_limit[index] {
+                            ui.label(label.color(Color32::RED)).on_hover_text(
+                                "Dropped by the 8 sprites per scanline limit on some lines",
+                            );
+                        } else {
+                            ui.label(label);
+                        }
+                    });
+                    row.col(|ui| {
+                        let sheet =
+                            egui::vec2(SPRITE_SHEET_WIDTH as f32, SPRITE_SHEET_HEIGHT as f32);
+                        let min = egui::vec2((index % 8) as f32 * 8.0, (index / 8) as f32 * 16.0);
+                        let uv = egui::Rect::from_min_max(
+                            (min / sheet).to_pos2(),
+                            ((min + egui::vec2(8.0, height)) / sheet).to_pos2(),
+                        );
+                        ui.add(
+                            egui::Image::new((texture.id(), egui::vec2(8.0, height) * SCALE))
+                                .uv(uv),
+                        );
+                    });
+                    row.col(|ui| {
+                        ui.monospace(format!("{}", sprite.x));
+                    });
+                    row.col(|ui| {
+                        ui.monospace(format!("{}", sprite.y));
+                    });
+                    row.col(|ui| {
+                        ui.monospace(format!("${:02X}", sprite.tile))
+                            .on_hover_text(format!(
+                                "Pattern at ${:04X}",
+                                sprite.pattern_addr(sprite_table, tall)
+                            ));
+                    });
+                    row.col(|ui| {
+                        ui.monospace(format!("{}", sprite.palette() - 4));
+                    });
+                    row.col(|ui| {
+                        ui.label(if sprite.behind_background() {
+                            "Behind"
+                        } else {
+                            "Front"
                         });
+                    });
+                    row.col(|ui| {
+                        let flip = match (sprite.flip_h(), sprite.flip_v()) {
+                            (false, false) => "",
+                            (true, false) => "H",
+                            (false, true) => "V",
+                            (true, true) => "HV",
+                        };
+                        ui.monospace(flip);
+                    });
                 });
             });
     }
@@ -1437,7 +3915,7 @@ impl Ui {
                         for color_idx in 0..4 {
                             let addr = palette_idx * 4 + color_idx;
                             let color_byte = ppu.palette[addr];
-                            let color = Ppu::get_color_from_palette(color_byte & 0x3F);
+                            let color = self.palette.color(color_byte & 0x3F);
                             let (rect, _response) = ui.allocate_exact_size(
                                 egui::vec2(square_size, square_size),
                                 egui::Sense::hover(),
@@ -1460,7 +3938,7 @@ impl Ui {
                                 0x10 | 0x14 | 0x18 | 0x1C => ppu.palette[addr - 0x10],
                                 _ => ppu.palette[addr],
                             };
-                            let color = Ppu::get_color_from_palette(color_byte & 0x3F);
+                            let color = self.palette.color(color_byte & 0x3F);
 
                             let (rect, _response) = ui.allocate_exact_size(
                                 egui::vec2(square_size, square_size),
@@ -1515,6 +3993,225 @@ impl Ui {
         }
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_disassembler(&mut self, ui: &mut egui::Ui) {
+        let view = &mut self.disasm_view;
+        let editor = &self.breakpoint_editor;
+        let pc = self.snapshot.cpu.pc;
+        let mut goto = None;
+        let mut toggle = None;
+        let mut run_to = None;
+
+        ui.label(egui::RichText::new("Disassembly").strong());
+        ui.horizontal(|ui| {
+            let input = egui::TextEdit::singleline(&mut view.goto)
+                .hint_text("0xC000")
+                .char_limit(32)
+                .desired_width(60.0)
+                .show(ui)
+                .response;
+            let addr = parse_address(&view.goto, &self.snapshot.symbols).filter(|a| *a <= 0xFFFF);
+            let entered = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if (ui
+                .add_enabled(addr.is_some(), egui::Button::new("Go"))
+                .clicked()
+                || entered)
+                && let Some(addr) = addr
+            {
+                goto = Some(Some(addr as u16));
+            }
+            if ui
+                .add_enabled(!view.history.is_empty(), egui::Button::new("⬅"))
+                .on_hover_text("Back")
+                .clicked()
+            {
+                let prev = view.history.pop();
+                view.addr = prev;
+                self.runner.send_command(Command::DisassemblyAddress(prev));
+            }
+            if ui
+                .selectable_label(view.addr.is_none(), "Follow PC")
+                .clicked()
+            {
+                goto = Some(None);
+            }
+        });
+
+        for line in &self.snapshot.disasm {
+            if let Some(label) = &line.label {
+                ui.label(
+                    egui::RichText::new(format!("{}:", label))
+                        .monospace()
+                        .color(Color32::LIGHT_BLUE),
+                );
+            }
+            ui.horizontal(|ui| {
+                ui.spacing_mut().item_spacing.x = 6.0;
+                let gutter = match editor.execute_at(line.addr) {
+                    Some(bp) if bp.enabled => egui::RichText::new("●").color(Color32::RED),
+                    Some(_) => egui::RichText::new("○").color(Color32::GRAY),
+                    None => egui::RichText::new("·").color(Color32::DARK_GRAY),
+                };
+                if ui
+                    .add(egui::Label::new(gutter.monospace()).sense(egui::Sense::click()))
+                    .on_hover_text("Toggle breakpoint")
+                    .clicked()
+                {
+                    toggle = Some(line.addr);
+                }
+
+                let current = line.addr == pc;
+                ui.monospace(if current { "▶" } else { " " });
+
+                let bank = line
+                    .prg_offset
+                    .map(|offset| format!("{:02X}", offset / 0x4000))
+                    .unwrap_or_else(|| "--".to_string());
+                ui.add(
+                    egui::Label::new(
+                        egui::RichText::new(format!("{}:{:04X}", bank, line.addr)).monospace(),
+                    )
+                    .sense(egui::Sense::click()),
+                )
+                .on_hover_text(
+                    line.prg_offset
+                        .map(|offset| format!("PRG ROM offset 0x{:05X}", offset))
+                        .unwrap_or_else(|| "Not in PRG ROM".to_string()),
+                )
+                .context_menu(|ui| {
+                    if ui.button("Toggle breakpoint").clicked() {
+                        toggle = Some(line.addr);
+                    }
+                    if ui
+                        .add_enabled(self.paused, egui::Button::new("Run to here"))
+                        .clicked()
+                    {
+                        run_to = Some(line.addr);
+                    }
+                });
+
+                let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                ui.monospace(format!("{:<8}", bytes.join(" ")));
+
+                let mut text = egui::RichText::new(format!(
+                    "{}{}",
+                    if line.illegal { "*" } else { " " },
+                    line.text
+                ))
+                .monospace();
+                if current {
+                    text = text.color(Color32::YELLOW);
+                }
+                match line.target {
+                    Some(target) => {
+                        if ui
+                            .add(egui::Label::new(text.underline()).sense(egui::Sense::click()))
+                            .on_hover_text(format!("Follow to 0x{:04X}", target))
+                            .clicked()
+                        {
+                            goto = Some(Some(target));
+                        }
+                    }
+                    None => {
+                        ui.label(text);
+                    }
+                }
+
+                if let Some((addr, value)) = line.effective {
+                    ui.label(
+                        egui::RichText::new(format!("[${:04X}] = ${:02X}", addr, value))
+                            .monospace()
+                            .color(Color32::GRAY),
+                    );
+                }
+                if let Some(comment) = &line.comment {
+                    ui.label(
+                        egui::RichText::new(format!("; {}", comment))
+                            .monospace()
+                            .color(Color32::DARK_GREEN),
+                    );
+                }
+            });
+        }
+
+        if let Some(addr) = goto {
+            view.history.push(view.addr.unwrap_or(pc));
+            view.addr = addr;
+            self.runner.send_command(Command::DisassemblyAddress(addr));
+        }
+        if let Some(addr) = toggle {
+            self.breakpoint_editor.toggle_execute(addr);
+            self.runner.send_command(Command::SetBreakpoints(
+                self.breakpoint_editor.breakpoints.clone(),
+            ));
+        }
+        if let Some(addr) = run_to {
+            self.runner.step(StepKind::RunTo(addr));
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_source_view(&mut self, ui: &mut egui::Ui) {
+        const CONTEXT_LINES: usize = 8;
+
+        let center = self.disasm_view.addr.unwrap_or(self.snapshot.cpu.pc);
+        let Some(source) = self
+            .snapshot
+            .disasm
+            .iter()
+            .find(|line| line.addr == center)
+            .and_then(|line| line.source)
+        else {
+            return;
+        };
+        let Some(path) = self.snapshot.symbols.file(source.file) else {
+            return;
+        };
+
+        ui.separator();
+        ui.label(
+            egui::RichText::new(format!(
+                "{}:{}",
+                path.file_name().unwrap_or_default().to_string_lossy(),
+                source.line
+            ))
+            .strong(),
+        )
+        .on_hover_text(path.display().to_string());
+
+        let lines = self
+            .source_view
+            .files
+            .entry(path.to_path_buf())
+            .or_insert_with(|| match std::fs::read_to_string(path) {
+                Ok(text) => Some(text.lines().map(|l| l.to_string()).collect()),
+                Err(e) => {
+                    error!("Failed to read source \"{}\": {e}", path.display());
+                    None
+                }
+            });
+        let Some(lines) = lines else {
+            ui.colored_label(Color32::RED, "Source file not found");
+            return;
+        };
+
+        let current = (source.line as usize).saturating_sub(1);
+        let first = current.saturating_sub(CONTEXT_LINES);
+        for (i, line) in lines
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(CONTEXT_LINES * 2 + 1)
+        {
+            let text = egui::RichText::new(format!("{:5} {}", i + 1, line)).monospace();
+            ui.label(if i == current {
+                text.color(Color32::YELLOW)
+            } else {
+                text
+            });
+        }
+    }
+
     #[cfg(not(target_arch = "wasm32"))]
     fn draw_fps(&self, ui: &mut egui::Ui) {
         if self.paused {
@@ -1540,11 +4237,98 @@ impl Ui {
     }
 
     fn draw_screen(&mut self, ui: &mut egui::Ui) {
-        if let Some(pixels) = self.runner.get_frame_data() {
-            self.screen.set_pixels(ui, pixels);
+        if let Some(frame) = self.runner.get_frame_data() {
+            self.screen.set_frame(frame);
+            self.screen_stale = true;
             self.frame_stats.tick();
         }
-        self.screen.render(ui);
+        if self.screen_stale {
+            self.screen.update_texture(ui, &self.palette);
+            self.screen_stale = false;
+        }
+        let _screen_area = self.screen.render(ui);
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(area) = _screen_area {
+            if self.sprite_viewer.overlay {
+                self.draw_sprite_overlay(ui, area);
+            }
+            if self.scanline_viewer.overlay {
+                self.draw_scroll_split_overlay(ui, area);
+            }
+        }
+    }
+
+    /// Draws a line where a scanline renders with a different scroll or
+    /// PPUCTRL than the one above it, labelled with the new scroll
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_scroll_split_overlay(&self, ui: &egui::Ui, area: ScreenArea) {
+        // Keeps labels readable through effects that change every line
+        const MIN_LABEL_GAP: usize = 10;
+
+        let rect = area.visible;
+        let painter = ui.painter_at(rect);
+        let color = Color32::from_rgb(255, 80, 220);
+        let mut last_label = None;
+        for (line, registers) in self.snapshot.ppu.scanline_registers.iter().enumerate() {
+            let scroll = ppu_view::scanline_scroll(registers.v, registers.x, line);
+            let split = match line.checked_sub(1) {
+                Some(previous) => {
+                    let above = &self.snapshot.ppu.scanline_registers[previous];
+                    ppu_view::scanline_scroll(above.v, above.x, previous) != scroll
+                        || above.ctrl.into_bytes() != registers.ctrl.into_bytes()
+                }
+                None => true,
+            };
+            if !split {
+                continue;
+            }
+            let y = area.to_screen(0.0, line as f32).y;
+            if line > 0 {
+                painter.hline(rect.x_range(), y, egui::Stroke::new(1.0, color));
+            }
+            if last_label.is_none_or(|last| line - last >= MIN_LABEL_GAP) {
+                painter.text(
+                    egui::pos2(rect.min.x + 4.0, y + 2.0),
+                    egui::Align2::LEFT_TOP,
+                    format!("{}: X {} Y {}", line, scroll.0, scroll.1),
+                    egui::FontId::monospace(11.0),
+                    color,
+                );
+                last_label = Some(line);
+            }
+        }
+    }
+
+    /// Outlines every on-screen sprite, red when it is dropped by the
+    /// 8-per-scanline limit somewhere and yellow for sprite 0
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_sprite_overlay(&self, ui: &egui::Ui, area: ScreenArea) {
+        let ppu = &self.snapshot.ppu;
+        let tall = ppu.ctrl.sprite_size() != 0;
+        let height = if tall { 16.0 } else { 8.0 };
+        let over_limit = ppu_view::sprites_over_limit(&ppu.oam, tall);
+        let painter = ui.painter_at(area.visible);
+        for index in (0..SPRITE_COUNT).rev() {
+            let sprite = OamSprite::from_oam(&ppu.oam, index);
+            // Y values past the bottom of the screen are how games hide sprites
+            if sprite.y as usize >= FRAME_HEIGHT - 1 {
+                continue;
+            }
+            let color = if over_limit[index] {
+                Color32::RED
+            } else if index == 0 {
+                Color32::YELLOW
+            } else {
+                Color32::GREEN
+            };
+            let min = area.to_screen(sprite.x as f32, sprite.y as f32 + 1.0);
+            painter.rect_stroke(
+                egui::Rect::from_min_size(min, egui::vec2(8.0, height) * area.pixel_size()),
+                0.0,
+                egui::Stroke::new(1.0, color),
+                egui::StrokeKind::Inside,
+            );
+        }
     }
 
     fn draw_start_screen(&self, ui: &mut egui::Ui) {
@@ -1554,20 +4338,77 @@ impl Ui {
         };
     }
 
+    /// Tells the emulator which of the costlier snapshot parts the open panels need
+    #[cfg(not(target_arch = "wasm32"))]
+    fn send_debug_panels(&mut self, fullscreen: bool) {
+        let shown = self.show_debug_panels && !fullscreen;
+        let panels = DebugPanels {
+            disassembly: shown,
+            cdl_stats: shown,
+            ram: shown,
+            // The PPU inspector's sprite table draws from CHR too
+            chr: shown || self.chr_viewer.open || self.nametable_viewer.open,
+            nametables: self.nametable_viewer.open,
+        };
+        if self.debug_panels != Some(panels) {
+            self.debug_panels = Some(panels);
+            self.runner.send_command(Command::SetDebugPanels(panels));
+        }
+    }
+
+    /// Rebuilds the palette and picks up the filter and window mode when the
+    /// video settings change
+    fn sync_video_settings(&mut self, ctx: &Context) {
+        let video = self.settings.lock().video.clone();
+        if self.video.as_ref() == Some(&video) {
+            return;
+        }
+        if self
+            .video
+            .as_ref()
+            .map_or(video.fullscreen, |old| old.fullscreen != video.fullscreen)
+        {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(video.fullscreen));
+        }
+        if self.video.as_ref().map(|old| old.render) != Some(video.render) {
+            self.runner
+                .send_command(Command::SetRenderOptions(video.render));
+        }
+        self.palette = video.build_palette().unwrap_or_else(|e| {
+            error!("Couldn't load palette: {e}");
+            Palette::default()
+        });
+        self.screen.configure(&video);
+        self.video = Some(video);
+        self.screen_stale = true;
+        // Debug viewers only redraw for a new snapshot
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.snapshot_serial += 1;
+        }
+    }
+
     pub fn draw(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        self.sync_video_settings(ui.ctx());
+        let fullscreen = self.video.as_ref().is_some_and(|v| v.fullscreen);
         #[cfg(not(target_arch = "wasm32"))]
         if let Some(snapshot) = self.runner.get_debug_snapshot() {
             self.snapshot = snapshot;
+            self.snapshot_serial += 1;
+            self.load_rom_cheats();
+            self.update_watches();
         }
 
-        egui::Panel::top("menubar")
-            .resizable(false)
-            .show_inside(ui, |ui| {
-                self.draw_menubar(ui);
-            });
+        if !fullscreen {
+            egui::Panel::top("menubar")
+                .resizable(false)
+                .show_inside(ui, |ui| {
+                    self.draw_menubar(ui);
+                });
+        }
         if self.running {
             #[cfg(not(target_arch = "wasm32"))]
-            if self.show_debug_panels {
+            if self.show_debug_panels && !fullscreen {
                 egui::Panel::left("left_panel")
                     .resizable(true)
                     .default_size(420.0)
@@ -1600,8 +4441,8 @@ impl Ui {
                     });
                 egui::Panel::right("right_panel")
                     .resizable(true)
-                    .default_size(220.0)
-                    .size_range(200.0..=400.0)
+                    .default_size(320.0)
+                    .size_range(200.0..=500.0)
                     .show_inside(ui, |ui| {
                         egui::ScrollArea::vertical()
                             .auto_shrink([false, false])
@@ -1610,6 +4451,9 @@ impl Ui {
                                     self.draw_rom_details(ui);
                                     ui.separator();
                                     self.draw_fps(ui);
+                                    ui.separator();
+                                    self.draw_disassembler(ui);
+                                    self.draw_source_view(ui);
                                 });
                             });
                     });
@@ -1623,6 +4467,16 @@ impl Ui {
                             .show(ui, |ui| {
                                 ui.horizontal_top(|ui| {
                                     self.draw_memory_viewer(ui);
+                                    ui.separator();
+                                    self.draw_watches(ui);
+                                    ui.separator();
+                                    self.draw_cdl(ui);
+                                    ui.separator();
+                                    self.draw_ram_search(ui);
+                                    ui.separator();
+                                    self.draw_cheats(ui);
+                                    ui.separator();
+                                    self.draw_breakpoints(ui);
                                     if self.log {
                                         ui.separator();
                                         self.draw_log_reader(ui);
@@ -1636,6 +4490,14 @@ impl Ui {
                     self.draw_screen(ui);
                 });
             });
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                self.draw_nametable_viewer(ui.ctx());
+                self.draw_chr_viewer(ui.ctx());
+                self.draw_event_viewer(ui.ctx());
+                self.draw_scanline_viewer(ui.ctx());
+                self.send_debug_panels(fullscreen);
+            }
         } else {
             egui::CentralPanel::default().show_inside(ui, |ui| {
                 ui.centered_and_justified(|ui| {
@@ -1654,6 +4516,22 @@ impl Ui {
                     self.running = true;
                     self.paused = false;
                     self.emu_error_msg = None;
+                    // A fresh emulator has no cheats, watches or PPU options, so send them again
+                    let (render, emulation) = {
+                        let settings = self.settings.lock();
+                        (settings.video.render, settings.emulation)
+                    };
+                    self.runner.send_command(Command::SetRenderOptions(render));
+                    self.runner
+                        .send_command(Command::SetOamDecay(emulation.oam_decay));
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        self.cheat_manager.rom_hash = None;
+                        self.debug_panels = None;
+                        self.send_watches();
+                        self.runner
+                            .send_command(Command::SetRecordTiles(self.screen.hd_pack().is_some()));
+                    }
                     #[cfg(target_arch = "wasm32")]
                     showController();
                 }
@@ -1667,40 +4545,108 @@ impl Ui {
                     self.running = false;
                     self.paused = false;
                 }
+                #[cfg(not(target_arch = "wasm32"))]
+                Event::RecordingStarted(path) => {
+                    self.recording = Some(path);
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                Event::RecordingStopped => {
+                    self.recording = None;
+                }
+                #[cfg(target_arch = "wasm32")]
+                Event::RecordingStarted(_) | Event::RecordingStopped => {}
                 Event::Crashed(e) => {
                     self.emu_error_msg = Some(e);
                     self.running = false;
                     self.paused = false;
                 }
+                #[cfg(not(target_arch = "wasm32"))]
+                Event::BreakpointHit(hit) => {
+                    self.paused = true;
+                    self.show_debug_panels = true;
+                    self.breakpoint_editor.last_hit = Some(hit);
+                    if self.disasm_view.addr.take().is_some() {
+                        self.runner.send_command(Command::DisassemblyAddress(None));
+                    }
+                }
+                #[cfg(target_arch = "wasm32")]
+                Event::BreakpointHit(_) => {
+                    self.paused = true;
+                }
             }
         }
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    fn screenshot_pixels(&self) -> (Vec<Color32>, usize, usize) {
+        if self.settings.lock().screenshots.filtered {
+            self.screen.filtered_screenshot()
+        } else {
+            self.screen.screenshot()
+        }
+    }
+
     #[cfg(not(target_arch = "wasm32"))]
     fn take_screenshot(&mut self) {
-        let frame_data: Vec<u8> = self
-            .runner
-            .frame_rx
-            .as_mut()
-            .unwrap()
-            .read()
-            .iter()
-            .flat_map(|c| {
-                let [r, g, b, _a] = c.to_array();
-                vec![r, g, b]
-            })
-            .collect();
-        let path = get_unique_path();
-        match image::save_buffer_with_format(
-            path.clone(),
-            &frame_data,
-            self.screen.width as u32,
-            self.screen.height as u32,
-            image::ColorType::Rgb8,
-            image::ImageFormat::Png,
-        ) {
-            Ok(()) => info!("Image saved to {}", path.display()),
-            Err(e) => error!("Couldn't save image: {e}"),
+        let (pixels, width, height) = self.screenshot_pixels();
+        let screenshots = self.settings.lock().screenshots.clone();
+        let sha1 = self
+            .snapshot
+            .cart
+            .as_ref()
+            .map_or_else(String::new, |cart| cart.hash.clone());
+        let name = screenshots.file_name(&ScreenshotInfo {
+            title: self.rom_title.as_deref().unwrap_or("screenshot"),
+            sha1: &sha1,
+            frame: self.snapshot.ppu.frame,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        });
+        let directory = screenshots.directory.unwrap_or_else(|| PathBuf::from("."));
+        if let Err(e) = std::fs::create_dir_all(&directory) {
+            error!("Couldn't create {}: {e}", directory.display());
+            return;
+        }
+        save_png(&get_unique_path(&directory, &name), &pixels, width, height);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn copy_screenshot(&self, ctx: &Context) {
+        let (pixels, width, height) = self.screenshot_pixels();
+        ctx.copy_image(egui::ColorImage::new([width, height], pixels));
+        info!("Screenshot copied to the clipboard");
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_hd_pack(&mut self) {
+        let Some(path) = self.runner.pick_hd_pack_folder() else {
+            return;
+        };
+        match HdPack::load(&path) {
+            Ok(hd_pack) => self.set_hd_pack(Some(hd_pack)),
+            Err(e) => error!("Couldn't load HD pack: {e:#}"),
+        }
+    }
+
+    /// The PPU only records what each pixel was drawn from while a pack needs it
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set_hd_pack(&mut self, hd_pack: Option<HdPack>) {
+        self.runner
+            .send_command(Command::SetRecordTiles(hd_pack.is_some()));
+        self.screen.set_hd_pack(hd_pack);
+        self.screen_stale = true;
+    }
+
+    /// Recordings use the palette colors at native resolution, without the video filter
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_recording(&mut self, format: RecordingFormat) {
+        if let Some(path) = self.runner.pick_recording_file(format) {
+            self.runner.send_command(Command::StartRecording(
+                format,
+                path,
+                Box::new(self.palette.clone()),
+            ));
         }
     }
 }
@@ -1712,17 +4658,130 @@ impl Drop for Ui {
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn get_unique_path() -> PathBuf {
-    let directory = ".";
-    let filename = "screenshot";
+fn parse_number(s: &str) -> Option<usize> {
+    let s = s.trim();
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+        usize::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse::<usize>().ok()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn cdl_color(flags: PrgFlags) -> Option<Color32> {
+    if flags.contains(PrgFlags::CODE | PrgFlags::DATA) {
+        Some(Color32::from_rgb(0xC0, 0x80, 0xFF))
+    } else if flags.contains(PrgFlags::CODE) {
+        Some(Color32::from_rgb(0x60, 0xD0, 0x60))
+    } else if flags.contains(PrgFlags::PCM) {
+        Some(Color32::from_rgb(0xFF, 0xA0, 0x40))
+    } else if flags.contains(PrgFlags::DATA) {
+        Some(Color32::from_rgb(0x60, 0xA0, 0xFF))
+    } else {
+        None
+    }
+}
+
+/// Like [`parse_number`], but also accepts a label name
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_address(s: &str, symbols: &SymbolTable) -> Option<usize> {
+    parse_number(s).or_else(|| symbols.find(s).map(usize::from))
+}
+
+/// An address or label, optionally followed by `+offset`
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_watch_address(s: &str, symbols: &SymbolTable) -> Option<u16> {
+    let addr = match s.split_once('+') {
+        Some((base, offset)) => parse_address(base, symbols)? + parse_number(offset)?,
+        None => parse_address(s, symbols)?,
+    };
+    u16::try_from(addr).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+const PPU_REGISTER_NAMES: [&str; 8] = [
+    "PPUCTRL",
+    "PPUMASK",
+    "PPUSTATUS",
+    "OAMADDR",
+    "OAMDATA",
+    "PPUSCROLL",
+    "PPUADDR",
+    "PPUDATA",
+];
+
+#[cfg(not(target_arch = "wasm32"))]
+fn event_kind_color(kind: PpuEventKind) -> Color32 {
+    match kind {
+        PpuEventKind::RegisterWrite => Color32::from_rgb(80, 200, 120),
+        PpuEventKind::OamDma => Color32::from_rgb(255, 140, 60),
+        PpuEventKind::MapperWrite => Color32::from_rgb(220, 90, 220),
+        PpuEventKind::Nmi => Color32::from_rgb(255, 220, 60),
+        PpuEventKind::Irq => Color32::from_rgb(255, 70, 70),
+        PpuEventKind::Sprite0Hit => Color32::from_rgb(90, 200, 255),
+    }
+}
+
+/// Register writes get one color per register so raster splits stand out
+#[cfg(not(target_arch = "wasm32"))]
+fn event_color(event: &PpuEvent) -> Color32 {
+    match (event.kind, event.addr & 0x07) {
+        (PpuEventKind::RegisterWrite, 0) => Color32::from_rgb(80, 200, 120),
+        (PpuEventKind::RegisterWrite, 1) => Color32::from_rgb(180, 230, 80),
+        (PpuEventKind::RegisterWrite, 5) => Color32::from_rgb(60, 140, 255),
+        (PpuEventKind::RegisterWrite, 6) => Color32::from_rgb(150, 110, 255),
+        (PpuEventKind::RegisterWrite, 7) => Color32::from_rgb(200, 200, 200),
+        (kind, _) => event_kind_color(kind),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn describe_event(event: &PpuEvent) -> String {
+    match event.kind {
+        PpuEventKind::RegisterWrite => format!(
+            "${:04X} {} <- ${:02X}",
+            event.addr,
+            PPU_REGISTER_NAMES[(event.addr & 0x07) as usize],
+            event.value
+        ),
+        PpuEventKind::OamDma => format!("$4014 OAMDMA <- ${:02X}", event.value),
+        PpuEventKind::MapperWrite => format!("${:04X} mapper <- ${:02X}", event.addr, event.value),
+        kind => kind.name().to_string(),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_png(path: &Path, pixels: &[Color32], width: usize, height: usize) {
+    let data: Vec<u8> = pixels
+        .iter()
+        .flat_map(|c| {
+            let [r, g, b, _a] = c.to_array();
+            [r, g, b]
+        })
+        .collect();
+    match image::save_buffer_with_format(
+        path,
+        &data,
+        width as u32,
+        height as u32,
+        image::ColorType::Rgb8,
+        image::ImageFormat::Png,
+    ) {
+        Ok(()) => info!("Image saved to {}", path.display()),
+        Err(e) => error!("Couldn't save image: {e}"),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn get_unique_path(directory: &Path, filename: &str) -> PathBuf {
     let extension = "png";
     let mut count = 0;
-    let mut path = Path::new(directory).join(format!("{}.{}", filename, extension));
+    let mut path = directory.join(format!("{}.{}", filename, extension));
 
     while path.exists() {
         count += 1;
         let new_filename = format!("{}_{}.{}", filename, count, extension);
-        path = Path::new(directory).join(new_filename);
+        path = directory.join(new_filename);
     }
 
     path
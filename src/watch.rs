@@ -0,0 +1,62 @@
+/// Bytes read for every watch, enough for the widest type
+pub const WATCH_BYTES: usize = 2;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum WatchType {
+    #[default]
+    U8,
+    I8,
+    /// Little endian
+    U16,
+    I16,
+    /// Two decimal digits per byte, as used by most score counters
+    Bcd,
+    Bitfield,
+}
+
+impl WatchType {
+    pub const ALL: [WatchType; 6] = [
+        WatchType::U8,
+        WatchType::I8,
+        WatchType::U16,
+        WatchType::I16,
+        WatchType::Bcd,
+        WatchType::Bitfield,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WatchType::U8 => "u8",
+            WatchType::I8 => "i8",
+            WatchType::U16 => "u16",
+            WatchType::I16 => "i16",
+            WatchType::Bcd => "BCD",
+            WatchType::Bitfield => "bits",
+        }
+    }
+
+    /// Numeric value for plotting
+    pub fn value(self, bytes: [u8; WATCH_BYTES]) -> f64 {
+        let word = u16::from_le_bytes(bytes);
+        match self {
+            WatchType::U8 | WatchType::Bitfield => bytes[0] as f64,
+            WatchType::I8 => bytes[0] as i8 as f64,
+            WatchType::U16 => word as f64,
+            WatchType::I16 => word as i16 as f64,
+            WatchType::Bcd => ((bytes[0] >> 4) * 10 + (bytes[0] & 0x0F)) as f64,
+        }
+    }
+
+    pub fn format(self, bytes: [u8; WATCH_BYTES]) -> String {
+        match self {
+            WatchType::U8 => format!("{} (${:02X})", bytes[0], bytes[0]),
+            WatchType::U16 => {
+                let word = u16::from_le_bytes(bytes);
+                format!("{} (${:04X})", word, word)
+            }
+            WatchType::I8 | WatchType::I16 => format!("{}", self.value(bytes)),
+            WatchType::Bcd => format!("{:02X}", bytes[0]),
+            WatchType::Bitfield => format!("{:04b}_{:04b}", bytes[0] >> 4, bytes[0] & 0x0F),
+        }
+    }
+}